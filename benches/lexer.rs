@@ -0,0 +1,75 @@
+// 字句解析にかかる時間が入力の大きさに比例することを確かめるベンチマーク
+// cargo bench --bench lexer で実行する
+use std::{hint::black_box, time::Instant};
+
+use dcc::lexer::TokenList;
+
+// 1つの関数の定義。識別子、キーワード、整数定数、文字列リテラル、コメントを一通り含む
+fn function(idx: usize) -> String {
+    format!(
+        "/* function {idx} */\n\
+         int func{idx}(int a, int b) {{\n\
+         \tint sum{idx}; // running total\n\
+         \tsum{idx} = 0;\n\
+         \tfor (a = 0; a < 0x{idx:x}; a = a + 1) {{\n\
+         \t\tif (a % 3 == 0 && b != 'x') sum{idx} += a << 2;\n\
+         \t\telse sum{idx} -= b;\n\
+         \t}}\n\
+         \tprintf(\"%d\\n\", sum{idx});\n\
+         \treturn sum{idx} * sizeof(int);\n\
+         }}\n"
+    )
+}
+
+// 大きさがおよそbytesバイトの入力を作る
+fn generate(bytes: usize) -> String {
+    let mut text = String::new();
+    let mut idx = 0;
+    while text.len() < bytes {
+        text += &function(idx);
+        idx += 1;
+    }
+    text
+}
+
+fn main() {
+    const MB: usize = 1 << 20;
+    const RUNS: usize = 5;
+    let mut base: Option<f64> = None;
+    println!(
+        "{:>8} {:>10} {:>10} {:>10} {:>8}",
+        "size", "tokens", "time", "MB/s", "ns/byte"
+    );
+    for size in [MB, 2 * MB, 4 * MB, 8 * MB, 16 * MB] {
+        let text = generate(size);
+        // 最も速かった回の時間を使う
+        let mut best = f64::MAX;
+        let mut tokens = 0;
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            let token_list = TokenList::tokenize(0, black_box(&text)).unwrap();
+            best = best.min(start.elapsed().as_secs_f64());
+            tokens = token_list.tokens.len();
+        }
+        let ns_per_byte = best * 1e9 / text.len() as f64;
+        println!(
+            "{:>6}MB {:>10} {:>8.1}ms {:>10.1} {:>8.2}",
+            size / MB,
+            tokens,
+            best * 1e3,
+            text.len() as f64 / MB as f64 / best,
+            ns_per_byte
+        );
+        // 線形時間であれば、1バイトあたりの時間は入力の大きさによらずほぼ一定になる
+        match base {
+            None => base = Some(ns_per_byte),
+            Some(base) if ns_per_byte > base * 2.0 => {
+                eprintln!(
+                    "warning: lexing is not linear ({:.2} ns/byte vs {:.2} ns/byte)",
+                    ns_per_byte, base
+                )
+            }
+            Some(_) => {}
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{common::Span, parser::Type};
+
+// 二項演算子
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum BinOp {
+    Add, // +
+    Sub, // -
+    Mul, // *
+    Div, // /
+    Eq,  // ==
+    Ne,  // !=
+    Lt,  // <。a > bはb < aとして表す
+    Le,  // <=。a >= bはb <= aとして表す
+}
+
+// 左辺値の種類
+#[derive(Debug)]
+pub enum LValueKind {
+    Var(usize),       // ローカル変数。Func::lvar_listでのindex
+    Global(usize),    // 大域変数。Program::globalsでのindex
+    Deref(Box<Expr>), // 単項*
+    // 構造体のメンバ。baseの構造体の先頭からoffsetバイト目にある
+    Member { base: Box<LValue>, offset: usize },
+}
+// 左辺値。アドレスを持ち、代入の左辺や単項&の対象にできる式
+#[derive(Debug)]
+pub struct LValue {
+    pub kind: LValueKind,
+    pub span: Span,
+    pub typ: Type,
+}
+
+// 式の種類
+#[derive(Debug)]
+pub enum ExprKind {
+    Num(isize),
+    Str(usize),   // 文字列リテラル。Func::stringsでのindex
+    Load(LValue), // 左辺値の値を読み出す
+    Binary {
+        op: BinOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Assign {
+        lhs: LValue,
+        rhs: Box<Expr>,
+    },
+    Addr(LValue),    // 単項&
+    Cast(Box<Expr>), // 式の値をExpr::typの型に変換する
+    Call {
+        callee: String,
+        args: Vec<Expr>,
+    },
+}
+// 式。型は構文解析のときに決める
+#[derive(Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+    pub typ: Type,
+}
+impl Expr {
+    // 自分自身と、その中にある全ての式をfに渡す
+    pub fn walk<'a>(&'a self, f: &mut dyn FnMut(&'a Expr)) {
+        f(self);
+        match &self.kind {
+            ExprKind::Num(_) | ExprKind::Str(_) => {}
+            ExprKind::Load(lvalue) | ExprKind::Addr(lvalue) => lvalue.walk(f),
+            ExprKind::Cast(operand) => operand.walk(f),
+            ExprKind::Binary { lhs, rhs, .. } => {
+                lhs.walk(f);
+                rhs.walk(f);
+            }
+            ExprKind::Assign { lhs, rhs } => {
+                lhs.walk(f);
+                rhs.walk(f);
+            }
+            ExprKind::Call { args, .. } => {
+                for arg in args.iter() {
+                    arg.walk(f);
+                }
+            }
+        }
+    }
+
+    // この式が値を読み書きするか、アドレスを取るローカル変数のindexを返す
+    // 構造体のメンバを使った場合は、その構造体の変数を使ったものとする
+    pub fn local_var(&self) -> Option<usize> {
+        let mut lvalue = match &self.kind {
+            ExprKind::Load(lvalue) | ExprKind::Addr(lvalue) => lvalue,
+            ExprKind::Assign { lhs, .. } => lhs,
+            _ => return None,
+        };
+        while let LValueKind::Member { base, .. } = &lvalue.kind {
+            lvalue = base;
+        }
+        match lvalue.kind {
+            LValueKind::Var(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+impl LValue {
+    // 左辺値の中にある全ての式をfに渡す
+    pub fn walk<'a>(&'a self, f: &mut dyn FnMut(&'a Expr)) {
+        match &self.kind {
+            LValueKind::Var(_) | LValueKind::Global(_) => {}
+            LValueKind::Deref(expr) => expr.walk(f),
+            LValueKind::Member { base, .. } => base.walk(f),
+        }
+    }
+}
+
+// 文の種類
+#[derive(Debug)]
+pub enum StmtKind {
+    Expr(Expr),
+    Return(Expr),
+    Decl(usize), // ローカル変数の宣言。Func::lvar_listでのindex
+    If {
+        cond: Expr,
+        then: Box<Stmt>,
+        els: Option<Box<Stmt>>,
+    },
+    While {
+        cond: Expr,
+        body: Box<Stmt>,
+    },
+    For {
+        init: Option<Box<Expr>>,
+        cond: Option<Box<Expr>>,
+        inc: Option<Box<Expr>>,
+        body: Box<Stmt>,
+    },
+    Block(Vec<Stmt>),
+}
+// 文
+#[derive(Debug)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+impl Stmt {
+    // 文の中にある全ての式をfに渡す
+    pub fn walk_exprs<'a>(&'a self, f: &mut dyn FnMut(&'a Expr)) {
+        match &self.kind {
+            StmtKind::Expr(expr) | StmtKind::Return(expr) => expr.walk(f),
+            StmtKind::Decl(_) => {}
+            StmtKind::If { cond, then, els } => {
+                cond.walk(f);
+                then.walk_exprs(f);
+                if let Some(els) = els {
+                    els.walk_exprs(f);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                cond.walk(f);
+                body.walk_exprs(f);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                inc,
+                body,
+            } => {
+                for expr in [init, cond, inc].into_iter().flatten() {
+                    expr.walk(f);
+                }
+                body.walk_exprs(f);
+            }
+            StmtKind::Block(stmts) => {
+                for stmt in stmts.iter() {
+                    stmt.walk_exprs(f);
+                }
+            }
+        }
+    }
+}
@@ -1,92 +1,211 @@
+use std::io::Write;
+
 use crate::{
-    error,
-    parser::{Func, Node, NodeKind, NodeList, Type},
+    ast::{BinOp, Expr, ExprKind, LValue, LValueKind, Stmt, StmtKind},
+    error::{Code, Diagnostic},
+    message::Message,
+    parser::{Func, GVar, NameKind, Program, StructList, Type},
+    typ::{align_to, get_align, get_size, pointee},
 };
 
 // x86-64に従った関数呼び出しの引数レジスタ
 const ARGS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+// 引数レジスタの下位32bit
+const ARGS32: [&str; 6] = ["edi", "esi", "edx", "ecx", "r8d", "r9d"];
+// 引数レジスタの下位8bit
+const ARGS8: [&str; 6] = ["dil", "sil", "dl", "cl", "r8b", "r9b"];
 
 // ユニークな数を出力するためのカウンター
-pub struct Counter {
+pub struct Counter<'a> {
     cnt: usize,
     func_name: String,
+    string_labels: Vec<usize>, // 関数内の文字列リテラルが置かれている.rodataのラベルの番号
+    program: &'a Program,      // 大域変数や構造体の定義を引くためのプログラム全体
 }
-impl Counter {
-    pub fn new(func_name: &String) -> Self {
+impl<'a> Counter<'a> {
+    pub fn new(func_name: &str, string_labels: Vec<usize>, program: &'a Program) -> Self {
         Counter {
             cnt: 0,
-            func_name: func_name.clone(),
+            func_name: func_name.to_string(),
+            string_labels,
+            program,
         }
     }
     fn new_label(&mut self) -> String {
-        let ret = format!("{}{}", self.func_name, self.cnt.to_string());
+        let ret = format!("{}{}", self.func_name, self.cnt);
         self.cnt += 1;
         ret
     }
 }
 
-// Funcからアセンブリを出力する
-pub fn gen(func: &Func, input: &Vec<char>) {
-    println!("");
-    println!("{}:", func.name);
+// プログラム全体のアセンブリを出力する
+pub fn gen_program(program: &Program, out: &mut dyn Write) -> Result<(), Diagnostic> {
+    // アセンブリのhead部分を出力
+    writeln!(out, ".intel_syntax noprefix")?;
+
+    // 文字列リテラルを.rodataに置く。同じ中身の文字列リテラルは1つのラベルを共有する
+    let mut strings: Vec<&[u8]> = vec![];
+    let mut string_labels = vec![];
+    for func in program.funcs.iter() {
+        let labels = func
+            .strings
+            .iter()
+            .map(|s| match strings.iter().position(|t| t == s) {
+                Some(label) => label,
+                None => {
+                    strings.push(s);
+                    strings.len() - 1
+                }
+            })
+            .collect::<Vec<usize>>();
+        string_labels.push(labels);
+    }
+    if !strings.is_empty() {
+        writeln!(out)?;
+        writeln!(out, ".section .rodata")?;
+        for (label, s) in strings.iter().enumerate() {
+            writeln!(out, ".LC{}:", label)?;
+            writeln!(out, "  .string \"{}\"", escape_string(s))?;
+        }
+        writeln!(out, ".text")?;
+    }
+
+    // 初期値のある大域変数を.dataに、ない大域変数を.bssに置く
+    gen_globals(program, out)?;
+
+    // アセンブリ本体を出力
+    for (func, labels) in program.funcs.iter().zip(string_labels) {
+        gen(func, labels, program, out)?;
+    }
+
+    // スタックを実行不可能にするためのセクション
+    writeln!(out)?;
+    writeln!(out, ".section .note.GNU-stack,\"\",@progbits")?;
+    Ok(())
+}
+
+// 大域変数の領域を確保するアセンブリを出力する
+fn gen_globals(program: &Program, out: &mut dyn Write) -> Result<(), Diagnostic> {
+    let structs = &program.structs;
+    // 列挙定数は値に置き換えられているので、領域を確保しない
+    let (data, bss): (Vec<&GVar>, Vec<&GVar>) = program
+        .globals
+        .iter()
+        .filter(|gvar| gvar.kind == NameKind::Var)
+        .partition(|gvar| gvar.init.is_some());
+    if !data.is_empty() {
+        writeln!(out)?;
+        writeln!(out, ".data")?;
+        for gvar in data.iter() {
+            let directive = match get_size(gvar.typ, structs) {
+                1 => ".byte",
+                2 => ".short",
+                4 => ".long",
+                _ => ".quad",
+            };
+            writeln!(out, ".global {}", gvar.name)?;
+            writeln!(out, ".align {}", get_align(gvar.typ, structs))?;
+            writeln!(out, "{}:", gvar.name)?;
+            writeln!(out, "  {} {}", directive, gvar.init.unwrap())?;
+        }
+    }
+    if !bss.is_empty() {
+        writeln!(out)?;
+        writeln!(out, ".bss")?;
+        for gvar in bss.iter() {
+            writeln!(out, ".global {}", gvar.name)?;
+            writeln!(out, ".align {}", get_align(gvar.typ, structs))?;
+            writeln!(out, "{}:", gvar.name)?;
+            writeln!(out, "  .zero {}", get_size(gvar.typ, structs))?;
+        }
+    }
+    if !data.is_empty() || !bss.is_empty() {
+        writeln!(out, ".text")?;
+    }
+    Ok(())
+}
+
+// アセンブラの文字列として書けるように、バイト列をエスケープする
+fn escape_string(s: &[u8]) -> String {
+    s.iter()
+        .map(|&c| match c {
+            b'"' => "\\\"".to_string(),
+            b'\\' => "\\\\".to_string(),
+            0x20..=0x7e => (c as char).to_string(),
+            _ => format!("\\{:03o}", c),
+        })
+        .collect()
+}
+
+// Funcからアセンブリを出力する。string_labelsは関数内の文字列リテラルのラベルの番号
+pub fn gen(
+    func: &Func,
+    string_labels: Vec<usize>,
+    program: &Program,
+    out: &mut dyn Write,
+) -> Result<(), Diagnostic> {
+    // 他の入力ファイルから呼び出せるように、全ての関数を外部に公開する
+    writeln!(out)?;
+    writeln!(out, ".global {}", func.name)?;
+    writeln!(out, "{}:", func.name)?;
 
     // 必要になるスタック領域をメモリ上に確保
-    println!("  push rbp");
-    println!("  mov rbp, rsp");
-    println!("  sub rsp, {}", func.program.lvar_list.offset() + 8); // TODO: なぜか1つ分余計にとらないと動かない...
+    writeln!(out, "  push rbp")?;
+    writeln!(out, "  mov rbp, rsp")?;
+    // pushは8バイト単位なので、関数呼び出しの前にrspを16の倍数に揃えられるよう8の倍数にしておく
+    let offset = align_to(func.lvar_list.stack_size(), 8);
+    writeln!(out, "  sub rsp, {}", offset + 8)?; // TODO: なぜか1つ分余計にとらないと動かない...
 
     // 引数の値を、引数レジスタから取り出して書き込む
     if func.args.len() > ARGS.len() {
-        error::error();
+        return Err(Diagnostic::error(
+            Code::TooManyArgs,
+            func.span,
+            Message::TooManyParams(ARGS.len()),
+        ));
     }
-    for (i, (arg_name, _)) in func.args.iter().enumerate() {
-        println!("  mov rax, rbp");
-        let lvar = func.program.lvar_list.find_lvar(arg_name).0.unwrap();
-        println!("  sub rax, {}", lvar.offset);
-        println!("  mov [rax], {}", ARGS[i]);
+    for (i, (_, arg_type, _)) in func.args.iter().enumerate() {
+        // 引数は最初に登録されているので、i番目の引数はi番目の変数
+        writeln!(out, "  mov rax, rbp")?;
+        writeln!(out, "  sub rax, {}", func.lvar_list.get(i).offset)?;
+        let reg = match arg_type {
+            Type::Char(0) => ARGS8[i],
+            Type::Int(0) => ARGS32[i],
+            _ => ARGS[i],
+        };
+        writeln!(out, "  mov [rax], {}", reg)?;
     }
 
     // ASTをトップダウンに降りコード出力
-    let mut counter = Counter::new(&func.name);
-    let node_list = &func.program;
-    for root in node_list.roots.iter() {
-        gen_from_node_list(*root, node_list, input, &mut counter);
-        println!("  pop rax"); // スタックがいっぱいにならないように毎回raxにpopする
+    let mut counter = Counter::new(&func.name, string_labels, program);
+    for stmt in func.body.iter() {
+        gen_stmt(stmt, func, out, &mut counter)?;
     }
 
-    // スタックトップに残っている式の最終的な値をraxにロードして終了
-    println!("  mov rsp, rbp");
-    println!("  pop rbp");
-    println!("  ret");
+    // returnせずに関数の終わりに到達したときは0を返す
+    writeln!(out, "  mov rax, 0")?;
+    writeln!(out, "  mov rsp, rbp")?;
+    writeln!(out, "  pop rbp")?;
+    writeln!(out, "  ret")?;
+    Ok(())
 }
 
-// 与えられたノードが変数を指しているときに、その変数のアドレスを計算して、その結果をスタックにpushする
-fn gen_lval(node: &Node, node_list: &NodeList, input: &Vec<char>, counter: &mut Counter) {
-    if node.kind == NodeKind::Lvar {
-        println!("  mov rax, rbp");
-        println!("  sub rax, {}", node.offset.unwrap());
-        println!("  push rax");
-    } else if node.kind == NodeKind::Deref {
-        gen_from_node_list(node.lhs.unwrap(), node_list, input, counter);
-    }
-}
-
-// ASTからスタックマシンをemulateする形でnode_listが表現するアセンブリを出力する
-pub fn gen_from_node_list(
-    now: usize,
-    node_list: &NodeList,
-    input: &Vec<char>,
+// 文のアセンブリを出力する。文の実行の前後でスタックの深さは変わらない
+fn gen_stmt(
+    stmt: &Stmt,
+    func: &Func,
+    out: &mut dyn Write,
     counter: &mut Counter,
-) {
-    let now_node = &node_list.nodes[now];
-
-    match now_node.kind {
-        NodeKind::Int => {
-            // ただの変数宣言なので、なにもせずに0をpushしておく
-            println!("  push 0");
-            return;
+) -> Result<(), Diagnostic> {
+    match &stmt.kind {
+        StmtKind::Expr(expr) => {
+            gen_expr(expr, func, out, counter)?;
+            writeln!(out, "  pop rax")?; // 式の値は使わないので捨てる
+        }
+        StmtKind::Decl(_) => {
+            // ただの変数宣言なので、なにもしない
         }
-        NodeKind::If => {
+        StmtKind::If { cond, then, els } => {
             /*
             if (A) B else C
 
@@ -108,42 +227,31 @@ pub fn gen_from_node_list(
             end:
             */
             let label_name = counter.new_label();
-            let lhs = &node_list.nodes[now_node.lhs.unwrap()];
-            let rhs = &node_list.nodes[now_node.rhs.unwrap()];
-            let else_exist = if let Some(_) = rhs.rhs { true } else { false };
-
-            // Aのコード出力
-            if lhs.kind != NodeKind::IfFlag {
-                error::error();
-            }
-            gen_from_node_list(lhs.lhs.unwrap(), node_list, input, counter);
 
             // Aの結果をpopして分岐
-            println!("  pop rax");
-            println!("  cmp rax, 0");
-            println!(
+            gen_expr(cond, func, out, counter)?;
+            writeln!(out, "  pop rax")?;
+            writeln!(out, "  cmp rax, 0")?;
+            writeln!(
+                out,
                 "  je .L{}{}",
-                if else_exist { "else" } else { "end" },
+                if els.is_some() { "else" } else { "end" },
                 label_name
-            );
+            )?;
 
             // Bのコード出力
-            if rhs.kind != NodeKind::IfStmt {
-                error::error();
-            }
-            gen_from_node_list(rhs.lhs.unwrap(), node_list, input, counter);
+            gen_stmt(then, func, out, counter)?;
 
             // Cのコード出力
-            if else_exist {
-                println!("  jmp .Lend{}", label_name);
-                println!(".Lelse{}:", label_name);
-                gen_from_node_list(rhs.rhs.unwrap(), node_list, input, counter);
+            if let Some(els) = els {
+                writeln!(out, "  jmp .Lend{}", label_name)?;
+                writeln!(out, ".Lelse{}:", label_name)?;
+                gen_stmt(els, func, out, counter)?;
             }
 
-            println!(".Lend{}:", label_name);
-            return;
+            writeln!(out, ".Lend{}:", label_name)?;
         }
-        NodeKind::While => {
+        StmtKind::While { cond, body } => {
             /*
             while (A) B
 
@@ -156,17 +264,21 @@ pub fn gen_from_node_list(
             */
             let label_name = counter.new_label();
 
-            println!(".Lbegin{}:", label_name);
-            gen_from_node_list(now_node.lhs.unwrap(), node_list, input, counter); // Aのコード
-            println!("  pop rax");
-            println!("  cmp rax, 0");
-            println!("  je .Lend{}", label_name);
-            gen_from_node_list(now_node.rhs.unwrap(), node_list, input, counter); // Bのコード
-            println!("  jmp .Lbegin{}", label_name);
-            println!(".Lend{}:", label_name);
-            return;
+            writeln!(out, ".Lbegin{}:", label_name)?;
+            gen_expr(cond, func, out, counter)?; // Aのコード
+            writeln!(out, "  pop rax")?;
+            writeln!(out, "  cmp rax, 0")?;
+            writeln!(out, "  je .Lend{}", label_name)?;
+            gen_stmt(body, func, out, counter)?; // Bのコード
+            writeln!(out, "  jmp .Lbegin{}", label_name)?;
+            writeln!(out, ".Lend{}:", label_name)?;
         }
-        NodeKind::For => {
+        StmtKind::For {
+            init,
+            cond,
+            inc,
+            body,
+        } => {
             /*
             for (A; B; C) D
 
@@ -180,225 +292,287 @@ pub fn gen_from_node_list(
             end:
             */
             let label_name = counter.new_label();
-            let lhs = &node_list.nodes[now_node.lhs.unwrap()];
-            let rhs = &node_list.nodes[now_node.rhs.unwrap()];
 
             // Aのコード出力
-            if let Some(a) = lhs.lhs {
-                gen_from_node_list(a, node_list, input, counter);
+            if let Some(init) = init {
+                gen_expr(init, func, out, counter)?;
+                writeln!(out, "  pop rax")?;
             }
 
-            println!(".Lbegin{}:", label_name);
+            writeln!(out, ".Lbegin{}:", label_name)?;
 
-            // Bのコード出力
-            if let Some(b) = lhs.rhs {
-                gen_from_node_list(b, node_list, input, counter);
+            // Bのコード出力。省略された場合は常に真とする
+            if let Some(cond) = cond {
+                gen_expr(cond, func, out, counter)?;
+                writeln!(out, "  pop rax")?;
+                writeln!(out, "  cmp rax, 0")?;
+                writeln!(out, "  je .Lend{}", label_name)?;
             }
 
-            println!("  pop rax");
-            println!("  cmp rax, 0");
-            println!("  je .Lend{}", label_name);
-
             // Dのコード出力
-            gen_from_node_list(rhs.rhs.unwrap(), node_list, input, counter);
+            gen_stmt(body, func, out, counter)?;
 
             // Cのコード出力
-            if let Some(c) = rhs.lhs {
-                gen_from_node_list(c, node_list, input, counter);
+            if let Some(inc) = inc {
+                gen_expr(inc, func, out, counter)?;
+                writeln!(out, "  pop rax")?;
             }
 
-            println!("  jmp .Lbegin{}", label_name);
-            println!(".Lend{}:", label_name);
-            return;
+            writeln!(out, "  jmp .Lbegin{}", label_name)?;
+            writeln!(out, ".Lend{}:", label_name)?;
+        }
+        StmtKind::Return(expr) => {
+            gen_expr(expr, func, out, counter)?;
+            writeln!(out, "  pop rax")?;
+            writeln!(out, "  mov rsp, rbp")?;
+            writeln!(out, "  pop rbp")?;
+            writeln!(out, "  ret")?;
         }
-        NodeKind::Return => {
-            gen_from_node_list(now_node.lhs.unwrap(), node_list, input, counter);
-            println!("  pop rax");
-            println!("  mov rsp, rbp");
-            println!("  pop rbp");
-            println!("  ret");
-            return;
+        StmtKind::Block(stmts) => {
+            for stmt in stmts.iter() {
+                gen_stmt(stmt, func, out, counter)?;
+            }
         }
-        NodeKind::Num => {
-            println!("  push {}", now_node.val.unwrap());
-            return;
+    }
+    Ok(())
+}
+
+// 左辺値のアドレスを計算して、その結果をスタックにpushする
+fn gen_lval(
+    lvalue: &LValue,
+    func: &Func,
+    out: &mut dyn Write,
+    counter: &mut Counter,
+) -> Result<(), Diagnostic> {
+    match &lvalue.kind {
+        LValueKind::Var(id) => {
+            writeln!(out, "  mov rax, rbp")?;
+            writeln!(out, "  sub rax, {}", func.lvar_list.get(*id).offset)?;
+            writeln!(out, "  push rax")?;
         }
-        NodeKind::Lvar => {
-            gen_lval(now_node, node_list, input, counter);
-            println!("  pop rax"); // 左辺値のアドレスを取り出す
-            println!("  mov rax, [rax]"); // 左辺値を取り出す
-            println!("  push rax");
-            return;
+        LValueKind::Global(id) => {
+            // 大域変数はRIP相対で参照する
+            writeln!(out, "  lea rax, {}[rip]", counter.program.globals[*id].name)?;
+            writeln!(out, "  push rax")?;
         }
-        NodeKind::Assign => {
-            gen_lval(
-                &node_list.nodes[now_node.lhs.unwrap()],
-                node_list,
-                input,
-                counter,
-            );
-            gen_from_node_list(now_node.rhs.unwrap(), node_list, input, counter);
-            println!("  pop rdi"); // 右辺値を取り出す
-            println!("  pop rax"); // 左辺値のアドレスを取り出す
-            println!("  mov [rax], rdi");
-            println!("  push rdi"); // 代入した値をpushしておく
-            return;
+        LValueKind::Member { base, offset } => {
+            // 構造体の先頭のアドレスにメンバのオフセットを足す
+            gen_lval(base, func, out, counter)?;
+            writeln!(out, "  pop rax")?;
+            writeln!(out, "  add rax, {}", offset)?;
+            writeln!(out, "  push rax")?;
         }
-        NodeKind::Block => {
-            let mut node = now_node; // 一番最初のblockノード
-
-            // blockノードのlhsがNoneになるまでループ
-            while let Some(stmt) = node.lhs {
-                println!("  pop rax"); // 各1つのstmtは1つの値をスタックに残したままにしているので、stmtが続くときは前の値を取り出しておく
-                gen_from_node_list(stmt, node_list, input, counter);
-                node = &node_list.nodes[node.rhs.unwrap()]; // 次のblockノードをセット
-            }
+        LValueKind::Deref(expr) => gen_expr(expr, func, out, counter)?,
+    }
+    Ok(())
+}
 
-            return;
+// スタックマシンをemulateする形で、式の値を計算してスタックにpushするアセンブリを出力する
+fn gen_expr(
+    expr: &Expr,
+    func: &Func,
+    out: &mut dyn Write,
+    counter: &mut Counter,
+) -> Result<(), Diagnostic> {
+    match &expr.kind {
+        ExprKind::Num(val) => {
+            writeln!(out, "  push {}", val)?;
         }
-        NodeKind::Addr => {
-            let lhs = &node_list.nodes[now_node.lhs.unwrap()];
-            gen_lval(lhs, node_list, input, counter);
-            return;
+        ExprKind::Str(idx) => {
+            // 文字列リテラルの先頭のアドレスをpushする
+            let label = counter.string_labels[*idx];
+            writeln!(out, "  lea rax, .LC{}[rip]", label)?;
+            writeln!(out, "  push rax")?;
         }
-        NodeKind::Deref => {
-            gen_from_node_list(now_node.lhs.unwrap(), node_list, input, counter);
-            println!("  pop rax");
-            println!("  mov rax, [rax]");
-            println!("  push rax");
-            return;
+        ExprKind::Load(lvalue) => {
+            gen_lval(lvalue, func, out, counter)?;
+            // 配列と構造体は先頭のアドレスをそのまま値とする
+            if !is_aggregate(lvalue.typ) {
+                writeln!(out, "  pop rax")?; // 左辺値のアドレスを取り出す
+                load(lvalue.typ, out)?; // 左辺値を取り出す
+                writeln!(out, "  push rax")?;
+            }
         }
-        NodeKind::App => {
-            let func_name = now_node.name.as_ref().unwrap();
-            let mut node = now_node.rhs;
-
-            // nodeがNoneになるまでループ
-            let mut arg_idx = 0;
-
-            while let Some(x) = node {
-                // 引数レジスタの制限を超えた場合
-                if arg_idx >= ARGS.len() {
-                    error::error();
+        ExprKind::Assign { lhs, rhs } => {
+            gen_lval(lhs, func, out, counter)?;
+            gen_expr(rhs, func, out, counter)?;
+            writeln!(out, "  pop rdi")?; // 右辺値を取り出す
+            writeln!(out, "  pop rax")?; // 左辺値のアドレスを取り出す
+            if is_aggregate(lhs.typ) {
+                // 構造体の代入は、右辺の構造体の中身をrep movsbでまとめてコピーする
+                writeln!(out, "  mov rsi, rdi")?;
+                writeln!(out, "  mov rdi, rax")?;
+                writeln!(
+                    out,
+                    "  mov rcx, {}",
+                    get_size(lhs.typ, &counter.program.structs)
+                )?;
+                writeln!(out, "  rep movsb")?;
+                // 代入した値として、コピー先の構造体のアドレスを残す
+                writeln!(out, "  mov rdi, rax")?;
+            } else {
+                store(lhs.typ, out)?;
+            }
+            writeln!(out, "  push rdi")?; // 代入した値をpushしておく
+        }
+        ExprKind::Addr(lvalue) => {
+            gen_lval(lvalue, func, out, counter)?;
+        }
+        ExprKind::Cast(operand) => {
+            gen_expr(operand, func, out, counter)?;
+            // 小さい型に変換するときは、上位のビットを捨てて符号拡張し直す
+            match expr.typ {
+                Type::Char(0) => {
+                    writeln!(out, "  pop rax")?;
+                    writeln!(out, "  movsx rax, al")?;
+                    writeln!(out, "  push rax")?;
                 }
-
-                // nodeがNoneでなかったので、lhsに引数がある
-                let expr = node_list.nodes[x].lhs.unwrap();
-                gen_from_node_list(expr, node_list, input, counter); // exprを計算するコードを出力
-                arg_idx += 1;
-                node = node_list.nodes[x].rhs;
+                Type::Int(0) => {
+                    writeln!(out, "  pop rax")?;
+                    writeln!(out, "  movsxd rax, eax")?;
+                    writeln!(out, "  push rax")?;
+                }
+                _ => {}
+            }
+        }
+        ExprKind::Call { callee, args } => {
+            // 引数レジスタの制限を超えた場合
+            if args.len() > ARGS.len() {
+                return Err(Diagnostic::error(
+                    Code::TooManyArgs,
+                    expr.span,
+                    Message::TooManyArgs(ARGS.len()),
+                ));
+            }
+            for arg in args.iter() {
+                gen_expr(arg, func, out, counter)?; // 引数を計算するコードを出力
             }
-            for i in (0..arg_idx).rev() {
-                println!("  pop {}", ARGS[i]); // ABIに従ったレジスタに引数を登録
+            for reg in ARGS[..args.len()].iter().rev() {
+                writeln!(out, "  pop {}", reg)?; // ABIに従ったレジスタに引数を登録
             }
 
             // 関数呼び出しの際はrspが16の倍数になっていなければならないことに注意しながら、関数を呼び出す
             let label_name = counter.new_label();
-            println!("  mov rax, rsp");
-            println!("  and rax, 15");
-            println!("  cmp rax, 0");
-            println!("  je .LskipAlign{}", label_name);
-            println!("  sub rsp, 8");
-            println!("  call {}", func_name);
-            println!("  add rsp, 8");
-            println!("  jmp .LendAlign{}", label_name);
-            println!(".LskipAlign{}:", label_name);
-            println!("  call {}", func_name);
-            println!(".LendAlign{}:", label_name);
-
-            println!("  push rax"); // 関数の返り値をpush
-            return;
+            writeln!(out, "  mov rax, rsp")?;
+            writeln!(out, "  and rax, 15")?;
+            writeln!(out, "  cmp rax, 0")?;
+            writeln!(out, "  je .LskipAlign{}", label_name)?;
+            writeln!(out, "  sub rsp, 8")?;
+            // 可変長引数の関数のために、ベクタレジスタで渡す引数がないことをalで伝える
+            writeln!(out, "  mov rax, 0")?;
+            writeln!(out, "  call {}", callee)?;
+            writeln!(out, "  add rsp, 8")?;
+            writeln!(out, "  jmp .LendAlign{}", label_name)?;
+            writeln!(out, ".LskipAlign{}:", label_name)?;
+            writeln!(out, "  mov rax, 0")?;
+            writeln!(out, "  call {}", callee)?;
+            writeln!(out, ".LendAlign{}:", label_name)?;
+
+            // int型とchar型の返り値は、raxの上位のビットが不定なので符号拡張する
+            match expr.typ {
+                Type::Char(0) => writeln!(out, "  movsx rax, al")?,
+                Type::Int(0) => writeln!(out, "  movsxd rax, eax")?,
+                _ => {}
+            }
+            writeln!(out, "  push rax")?; // 関数の返り値をpush
         }
-        _ => (),
-    }
+        ExprKind::Binary { op, lhs, rhs } => {
+            gen_expr(lhs, func, out, counter)?;
+            gen_expr(rhs, func, out, counter)?;
 
-    gen_from_node_list(now_node.lhs.unwrap(), node_list, input, counter);
-    gen_from_node_list(now_node.rhs.unwrap(), node_list, input, counter);
+            writeln!(out, "  pop rdi")?; // rhs
+            writeln!(out, "  pop rax")?; // lhs
 
-    println!("  pop rdi"); // rhs
-    println!("  pop rax"); // lhs
+            match op {
+                BinOp::Add => {
+                    adjust_pointer(lhs.typ, rhs.typ, &counter.program.structs, out)?;
+                    writeln!(out, "  add rax, rdi")?;
+                }
+                BinOp::Sub => {
+                    adjust_pointer(lhs.typ, rhs.typ, &counter.program.structs, out)?;
+                    writeln!(out, "  sub rax, rdi")?;
+                }
+                BinOp::Mul => {
+                    writeln!(out, "  imul rax, rdi")?;
+                }
+                BinOp::Div => {
+                    writeln!(out, "  cqo")?;
+                    writeln!(out, "  idiv rdi")?;
+                }
+                BinOp::Eq => {
+                    writeln!(out, "  cmp rax, rdi")?;
+                    writeln!(out, "  sete al")?;
+                    writeln!(out, "  movzb rax, al")?;
+                }
+                BinOp::Lt => {
+                    writeln!(out, "  cmp rax, rdi")?;
+                    writeln!(out, "  setl al")?;
+                    writeln!(out, "  movzb rax, al")?;
+                }
+                BinOp::Le => {
+                    writeln!(out, "  cmp rax, rdi")?;
+                    writeln!(out, "  setle al")?;
+                    writeln!(out, "  movzb rax, al")?;
+                }
+                BinOp::Ne => {
+                    writeln!(out, "  cmp rax, rdi")?;
+                    writeln!(out, "  setne al")?;
+                    writeln!(out, "  movzb rax, al")?;
+                }
+            }
 
-    match now_node.kind {
-        NodeKind::Add => {
-            adjust_pointer(
-                now_node.typ,
-                node_list.nodes[now_node.lhs.unwrap()].typ,
-                node_list.nodes[now_node.rhs.unwrap()].typ,
-            );
-            println!("  add rax, rdi");
-        }
-        NodeKind::Sub => {
-            adjust_pointer(
-                now_node.typ,
-                node_list.nodes[now_node.lhs.unwrap()].typ,
-                node_list.nodes[now_node.rhs.unwrap()].typ,
-            );
-            println!("  sub rax, rdi");
-        }
-        NodeKind::Mul => {
-            println!("  imul rax, rdi");
-        }
-        NodeKind::Div => {
-            println!("  cqo");
-            println!("  idiv rdi");
-        }
-        NodeKind::Eq => {
-            println!("  cmp rax, rdi");
-            println!("  sete al");
-            println!("  movzb rax, al");
-        }
-        NodeKind::Lt => {
-            println!("  cmp rax, rdi");
-            println!("  setl al");
-            println!("  movzb rax, al");
-        }
-        NodeKind::Le => {
-            println!("  cmp rax, rdi");
-            println!("  setle al");
-            println!("  movzb rax, al");
-        }
-        NodeKind::Ne => {
-            println!("  cmp rax, rdi");
-            println!("  setne al");
-            println!("  movzb rax, al");
-        }
-        _ => {
-            unreachable!()
+            writeln!(out, "  push rax")?;
         }
     }
+    Ok(())
+}
+// 配列か構造体であるか判定する。これらの型の値は、レジスタに載せずにアドレスで扱う
+fn is_aggregate(typ: Type) -> bool {
+    matches!(
+        typ,
+        Type::IntArr(_) | Type::CharArr(_) | Type::StructArr { .. } | Type::Struct { level: 0, .. }
+    )
+}
+
+// raxが指すtyp型の値をraxに読み出す。intとcharは符号拡張して64bitにする
+fn load(typ: Type, out: &mut dyn Write) -> Result<(), Diagnostic> {
+    match typ {
+        Type::Char(0) => writeln!(out, "  movsx rax, byte ptr [rax]")?,
+        Type::Int(0) => writeln!(out, "  movsxd rax, dword ptr [rax]")?,
+        _ => writeln!(out, "  mov rax, [rax]")?,
+    }
+    Ok(())
+}
 
-    println!("  push rax");
+// rdiの値をtyp型としてraxが指す場所に書き込む
+fn store(typ: Type, out: &mut dyn Write) -> Result<(), Diagnostic> {
+    match typ {
+        Type::Char(0) => writeln!(out, "  mov [rax], dil")?,
+        Type::Int(0) => writeln!(out, "  mov [rax], edi")?,
+        _ => writeln!(out, "  mov [rax], rdi")?,
+    }
+    Ok(())
 }
 
 // ポインタの加算と減算を調整する関数
-fn adjust_pointer(now_typ: Type, lhs_typ: Type, rhs_typ: Type) {
-    match now_typ {
-        Type::Int(x) if x > 0 => {
-            // ポインタの加算と減算は、型のサイズ分動く
-            // 二項演算がポインタ型の場合、typ::binary_calc_typeにより、片方がポインタ型、もう片方がint, unknownのどちらかであることが確定していることを利用する
-            match (lhs_typ, rhs_typ) {
-                (Type::Int(y), Type::Unknown) | (Type::Int(y), Type::Int(0)) => {
-                    // rhsを調整する必要がある
-                    if y > 1 {
-                        // Type::Int(y)はポインタへのポインタであるので、型のサイズは8で調整
-                        println!("  imul rdi, 8");
-                    } else {
-                        // Type::Int(1)はintへのポインタであるので、型のサイズは4で調整
-                        println!("  imul rdi, 4");
-                    }
-                }
-                (Type::Unknown, Type::Int(y)) | (Type::Int(0), Type::Int(y)) => {
-                    // lhsを調整する必要がある
-                    if y > 1 {
-                        // Type::Int(y)はポインタへのポインタであるので、型のサイズは8で調整
-                        println!("  imul rax, 8");
-                    } else {
-                        // Type::Int(1)はintへのポインタであるので、型のサイズは4で調整
-                        println!("  imul rax, 4");
-                    }
-                }
-                _ => unreachable!(),
-            }
+// 二項演算がポインタ型の場合、typ::binary_calc_typeにより、片方がポインタ型(か配列)、もう片方がint, unknownのどちらかであることが確定していることを利用する
+fn adjust_pointer(
+    lhs_typ: Type,
+    rhs_typ: Type,
+    structs: &StructList,
+    out: &mut dyn Write,
+) -> Result<(), Diagnostic> {
+    // ポインタの加算と減算は、指す先の型のサイズ分動く
+    match (pointee(lhs_typ), pointee(rhs_typ)) {
+        (Some(typ), _) => {
+            // rhsを調整する必要がある
+            writeln!(out, "  imul rdi, {}", get_size(typ, structs))?;
+        }
+        (_, Some(typ)) => {
+            // lhsを調整する必要がある
+            writeln!(out, "  imul rax, {}", get_size(typ, structs))?;
         }
         _ => (),
     }
+    Ok(())
 }
@@ -1,2 +1,101 @@
-pub type Line = Vec<char>; // 入力されたプログラムの1行のことをLineと呼ぶ
-pub type Input = Vec<Line>; // Lineの集まりであるプログラムをInputと呼ぶ
+use std::rc::Rc;
+
+// 入力の中の範囲。file番目のソースファイルの、startバイト目からendバイト目の直前までを表す
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub file: u32,
+    pub start: u32,
+    pub end: u32,
+}
+impl Span {
+    // file番目のソースファイルのstartバイト目からendバイト目の直前までを表すSpanを返す
+    pub fn new(file: u32, start: usize, end: usize) -> Span {
+        Span {
+            file,
+            start: start as u32,
+            end: end as u32,
+        }
+    }
+
+    // selfの始まりからotherの終わりまでを表すSpanを返す
+    pub fn to(&self, other: Span) -> Span {
+        Span {
+            file: self.file,
+            start: self.start,
+            end: other.end,
+        }
+    }
+
+    // selfの直後の、長さ0のSpanを返す
+    pub fn end_point(&self) -> Span {
+        Span {
+            file: self.file,
+            start: self.end,
+            end: self.end,
+        }
+    }
+}
+
+// 名前のついたソースファイル
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: Rc<str>,
+    line_starts: Vec<usize>, // 各行の先頭のバイト位置
+}
+impl SourceFile {
+    pub fn new(name: &str, text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        SourceFile {
+            name: name.to_string(),
+            text: Rc::from(text),
+            line_starts,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    // row行目の中身を返す。CRLFの改行も1つの改行として扱い、行末の'\r'は含めない
+    pub fn line(&self, row: usize) -> &str {
+        let start = self.line_starts[row];
+        let end = self
+            .line_starts
+            .get(row + 1)
+            .map_or(self.text.len(), |&next| next - 1);
+        let line = &self.text[start..end];
+        line.strip_suffix('\r').unwrap_or(line)
+    }
+
+    // offsetバイト目が何行目の何文字目にあたるかを返す。どちらも0始まり
+    pub fn line_col(&self, offset: u32) -> (usize, usize) {
+        let offset = (offset as usize).min(self.text.len());
+        let row = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let col = self.text[self.line_starts[row]..offset].chars().count();
+        (row, col)
+    }
+}
+
+// コンパイル中に読み込んだソースファイルの集まり。SpanのfileはこのSourceMapの中の番号を表す
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap { files: vec![] }
+    }
+
+    // fileを追加し、その番号を返す
+    pub fn add(&mut self, file: SourceFile) -> u32 {
+        self.files.push(file);
+        (self.files.len() - 1) as u32
+    }
+
+    pub fn get(&self, file: u32) -> Option<&SourceFile> {
+        self.files.get(file as usize)
+    }
+}
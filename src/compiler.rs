@@ -0,0 +1,196 @@
+use std::io::Write;
+
+use crate::{
+    codegen,
+    common::{SourceFile, SourceMap},
+    error::{Code, Diagnostic, Diagnostics, Severity},
+    lint,
+    message::Message,
+    parser::parse_program,
+    preprocess::Preprocessor,
+    vfs::{FileSystem, RealFileSystem},
+    warning::WarningOptions,
+};
+
+// コンパイル結果として出力する中間表現の種類
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Emit {
+    Tokens, // 字句解析の結果
+    Ast,    // 構文解析の結果
+    Asm,    // コード生成の結果
+}
+
+// コンパイラの設定
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    pub emit: Emit,
+    pub error_limit: usize, // 報告するエラーの最大数。0のときは制限しない
+    pub warnings: WarningOptions,
+    pub include_paths: Vec<String>, // -Iで指定された、#includeするファイルを探すディレクトリ
+    pub defines: Vec<(String, Option<String>)>, // -Dは(名前, Some(値))、-Uは(名前, None)として指定された順に並ぶ
+}
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            emit: Emit::Asm,
+            error_limit: 20,
+            warnings: WarningOptions::default(),
+            include_paths: vec![],
+            defines: vec![],
+        }
+    }
+}
+
+// コンパイル結果
+#[derive(Debug)]
+pub struct Output {
+    pub text: String,          // optionsのemitで指定された段階の出力
+    pub warnings: Diagnostics, // コンパイル中に見つかった警告
+}
+
+pub struct Compiler {
+    options: CompileOptions,
+    fs: Box<dyn FileSystem>,
+}
+impl Compiler {
+    // 実際のファイルシステムからソースファイルを読み込むコンパイラを作る
+    pub fn new(options: CompileOptions) -> Self {
+        Self::with_file_system(options, Box::new(RealFileSystem))
+    }
+
+    // fsからソースファイルを読み込むコンパイラを作る
+    pub fn with_file_system(options: CompileOptions, fs: Box<dyn FileSystem>) -> Self {
+        Compiler { options, fs }
+    }
+
+    pub fn options(&self) -> &CompileOptions {
+        &self.options
+    }
+
+    // ソースコードをコンパイルし、その結果を返す
+    pub fn compile(&self, source: &str) -> Result<Output, Diagnostics> {
+        self.compile_named("<input>", source)
+    }
+
+    // ファイルシステム上のpathにあるソースファイルをコンパイルし、その結果を返す
+    pub fn compile_file(&self, path: &str) -> Result<Output, Diagnostics> {
+        let source = self.fs.read_to_string(path).map_err(|e| {
+            let diagnostic = Diagnostic::error_without_span(
+                Code::Io,
+                Message::ReadFailed {
+                    path: path.to_string(),
+                    reason: e.to_string(),
+                },
+            );
+            Diagnostics::new(vec![diagnostic], SourceMap::new())
+        })?;
+        self.compile_named(path, &source)
+    }
+
+    // nameという名前のソースコードをコンパイルし、その結果を返す
+    pub fn compile_named(&self, name: &str, source: &str) -> Result<Output, Diagnostics> {
+        let mut text = vec![];
+        let warnings = self.compile_to(name, source, &mut text)?;
+        Ok(Output {
+            text: String::from_utf8(text).unwrap(),
+            warnings,
+        })
+    }
+
+    // nameという名前のソースコードをコンパイルし、その結果をoutに書き出す。成功した場合は警告を返す
+    pub fn compile_to(
+        &self,
+        name: &str,
+        source: &str,
+        out: &mut dyn Write,
+    ) -> Result<Diagnostics, Diagnostics> {
+        let mut sources = SourceMap::new();
+        let file = sources.add(SourceFile::new(name, source));
+        // -Werrorで失敗する場合に途中までの出力が残らないよう、結果は一旦バッファに書き出す
+        let mut buffer = vec![];
+        let (succeeded, diagnostics) = match self.compile_source(&mut sources, file, &mut buffer) {
+            Ok(warnings) => (true, warnings),
+            Err(diagnostics) => (false, diagnostics),
+        };
+        // -Werrorによって警告がエラーになった場合もコンパイルは失敗する
+        let mut diagnostics = self.options.warnings.apply(diagnostics);
+        let has_error = diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error);
+        if succeeded && !has_error {
+            if let Err(e) = out.write_all(&buffer) {
+                diagnostics.push(e.into());
+                return Err(Diagnostics::new(diagnostics, sources));
+            }
+            Ok(Diagnostics::new(diagnostics, sources))
+        } else {
+            Err(Diagnostics::new(self.limit_errors(diagnostics), sources))
+        }
+    }
+
+    // エラーの数をoptionsのerror_limitまでに制限する。警告は数えない
+    fn limit_errors(&self, mut diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        let limit = self.options.error_limit;
+        if limit == 0 {
+            return diagnostics;
+        }
+        // 上限を超えた最初のエラーの位置
+        let over = diagnostics
+            .iter()
+            .enumerate()
+            .filter(|(_, diagnostic)| diagnostic.severity == Severity::Error)
+            .nth(limit)
+            .map(|(idx, _)| idx);
+        if let Some(idx) = over {
+            diagnostics.truncate(idx);
+            diagnostics.push(Diagnostic::error_without_span(
+                Code::TooManyErrors,
+                Message::TooManyErrors(limit),
+            ));
+        }
+        diagnostics
+    }
+
+    fn compile_source(
+        &self,
+        sources: &mut SourceMap,
+        file: u32,
+        out: &mut dyn Write,
+    ) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        // 前処理と字句解析
+        let mut token_list = Preprocessor::new(&*self.fs, sources, &self.options.include_paths)
+            .run(file, &self.options.defines)?;
+        if self.options.emit == Emit::Tokens {
+            for token in token_list.tokens.iter() {
+                let source = sources.get(token.span.file).unwrap();
+                let (row, col) = source.line_col(token.span.start);
+                writeln!(
+                    out,
+                    "{}:{}:{}\t{}",
+                    source.name,
+                    row + 1,
+                    col + 1,
+                    token_list.describe(&token.kind)
+                )
+                .map_err(|e| vec![e.into()])?;
+            }
+            return Ok(vec![]);
+        }
+
+        // 構文解析
+        let (program, mut diagnostics) = parse_program(&mut token_list)?;
+        diagnostics.extend(lint::check_program(&program.funcs));
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.map(|span| (span.file, span.start)));
+        if self.options.emit == Emit::Ast {
+            writeln!(out, "{:#?}", program).map_err(|e| vec![e.into()])?;
+            return Ok(diagnostics);
+        }
+
+        // コード生成
+        if let Err(e) = codegen::gen_program(&program, out) {
+            diagnostics.push(e);
+            return Err(diagnostics);
+        }
+        Ok(diagnostics)
+    }
+}
@@ -0,0 +1,387 @@
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+use dcc::{
+    common::SourceMap,
+    error::{Code, Diagnostic},
+    message::Message,
+    CompileOptions, Compiler, Diagnostics, Emit, Lang, Warning,
+};
+
+// コンパイルをどの段階で止めるか
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Stage {
+    Assemble, // -S: アセンブリを出力する
+    Object,   // -c: オブジェクトファイルを出力する
+    Link,     // 指定なし: 実行ファイルまで作る
+}
+
+// 診断をどの形式で表示するか
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ErrorFormat {
+    Human, // 人間が読むための形式
+    Json,  // 1行に1つのJSON
+}
+impl ErrorFormat {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+
+    // コマンドライン引数の解釈に失敗したときのために、引数から直接表示形式を探す
+    pub fn from_args(args: &[String]) -> Self {
+        args.iter()
+            .rev()
+            .filter_map(|arg| arg.strip_prefix("--error-format="))
+            .find_map(Self::parse)
+            .unwrap_or(ErrorFormat::Human)
+    }
+}
+
+// コマンドライン引数を解釈した結果
+#[derive(Debug)]
+pub struct Options {
+    pub inputs: Vec<String>,    // 入力ファイル。"-"は標準入力を表す
+    pub output: Option<String>, // -oで指定された出力先
+    pub stage: Stage,
+    pub emit: Option<Emit>,
+    pub error_format: ErrorFormat,
+    pub lang: Lang,                      // 診断を表示する言語
+    pub compile_options: CompileOptions, // コンパイラに渡す設定
+}
+
+impl Options {
+    // コマンドライン引数(プログラム名を除く)からOptionsを作る
+    pub fn parse(args: &[String]) -> Result<Self, Diagnostics> {
+        let mut inputs = vec![];
+        let mut output = None;
+        let mut stage = Stage::Link;
+        let mut emit = None;
+        let mut error_format = ErrorFormat::Human;
+        let mut lang = lang_from_args(args);
+        let mut compile_options = CompileOptions::default();
+
+        let mut idx = 0;
+        while idx < args.len() {
+            let arg = args[idx].as_str();
+            if arg == "-S" {
+                stage = Stage::Assemble;
+            } else if arg == "-c" {
+                stage = Stage::Object;
+            } else if arg == "-o" {
+                idx += 1;
+                if idx >= args.len() {
+                    return Err(driver_error(
+                        Code::InvalidOption,
+                        Message::MissingOutputName,
+                    ));
+                }
+                output = Some(args[idx].clone());
+            } else if let Some(path) = arg.strip_prefix("-o") {
+                output = Some(path.to_string());
+            } else if let Some(option @ ("-I" | "-D" | "-U")) = arg.get(..2) {
+                // -I<dir>と-I <dir>のどちらの形でも指定できる
+                let value = if arg.len() > 2 {
+                    arg[2..].to_string()
+                } else {
+                    idx += 1;
+                    args.get(idx).cloned().ok_or_else(|| {
+                        driver_error(
+                            Code::InvalidOption,
+                            Message::MissingOptionValue(option.to_string()),
+                        )
+                    })?
+                };
+                match option {
+                    "-I" => compile_options.include_paths.push(value),
+                    // -D<name>は<name>を1と定義する
+                    "-D" => compile_options.defines.push(match value.split_once('=') {
+                        Some((name, body)) => (name.to_string(), Some(body.to_string())),
+                        None => (value, Some("1".to_string())),
+                    }),
+                    _ => compile_options.defines.push((value, None)),
+                }
+            } else if let Some(kind) = arg.strip_prefix("--emit=") {
+                emit = Some(match kind {
+                    "tokens" => Emit::Tokens,
+                    "ast" => Emit::Ast,
+                    "asm" => Emit::Asm,
+                    _ => {
+                        return Err(driver_error(
+                            Code::InvalidOption,
+                            Message::InvalidEmit(kind.to_string()),
+                        ))
+                    }
+                });
+            } else if let Some(format) = arg.strip_prefix("--error-format=") {
+                error_format = ErrorFormat::parse(format).ok_or_else(|| {
+                    driver_error(
+                        Code::InvalidOption,
+                        Message::InvalidErrorFormat(format.to_string()),
+                    )
+                })?;
+            } else if let Some(limit) = arg.strip_prefix("-ferror-limit=") {
+                compile_options.error_limit = limit.parse().map_err(|_| {
+                    driver_error(
+                        Code::InvalidOption,
+                        Message::InvalidErrorLimit(limit.to_string()),
+                    )
+                })?;
+            } else if let Some(name) = arg.strip_prefix("--lang=") {
+                lang = Lang::parse(name).ok_or_else(|| {
+                    driver_error(Code::InvalidOption, Message::InvalidLang(name.to_string()))
+                })?;
+            } else if arg == "-Wall" {
+                compile_options.warnings.enable_all();
+            } else if arg == "-Werror" {
+                compile_options.warnings.werror = true;
+            } else if let Some(name) = arg.strip_prefix("-W") {
+                let (name, enable) = match name.strip_prefix("no-") {
+                    Some(name) => (name, false),
+                    None => (name, true),
+                };
+                let warning = Warning::from_name(name).ok_or_else(|| {
+                    driver_error(
+                        Code::InvalidOption,
+                        Message::UnknownWarning(name.to_string()),
+                    )
+                })?;
+                if enable {
+                    compile_options.warnings.enable(warning);
+                } else {
+                    compile_options.warnings.disable(warning);
+                }
+            } else if arg == "-h" || arg == "--help" {
+                println!("{}", Message::Usage.text(lang));
+                process::exit(0);
+            } else if arg != "-" && arg.starts_with('-') {
+                return Err(driver_error(
+                    Code::InvalidOption,
+                    Message::UnknownOption(arg.to_string()),
+                ));
+            } else {
+                inputs.push(arg.to_string());
+            }
+            idx += 1;
+        }
+
+        if inputs.is_empty() {
+            return Err(driver_error(Code::InvalidOption, Message::NoInputFiles));
+        }
+
+        Ok(Options {
+            inputs,
+            output,
+            stage,
+            emit,
+            error_format,
+            lang,
+            compile_options,
+        })
+    }
+}
+
+// 言語を--langで指定されたもの、なければ環境変数から決める
+pub fn lang_from_args(args: &[String]) -> Lang {
+    args.iter()
+        .rev()
+        .filter_map(|arg| arg.strip_prefix("--lang="))
+        .find_map(Lang::parse)
+        .unwrap_or_else(Lang::from_env)
+}
+
+// 診断を標準エラー出力に表示する。端末に出力するときは色をつける
+pub fn report(diagnostics: &Diagnostics, format: ErrorFormat, lang: Lang) {
+    match format {
+        ErrorFormat::Human => {
+            let color = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+            eprint!("{}", diagnostics.render(color, lang));
+        }
+        ErrorFormat::Json => eprint!("{}", diagnostics.render_json(lang)),
+    }
+}
+
+// ドライバで起きたエラーをDiagnosticsにする
+fn driver_error(code: Code, msg: Message) -> Diagnostics {
+    Diagnostics::new(
+        vec![Diagnostic::error_without_span(code, msg)],
+        SourceMap::new(),
+    )
+}
+
+// 入力をコンパイルし、その結果を返す。"-"の場合は標準入力からソースコードを読み込む
+fn compile(input: &str, emit: Emit, options: &Options) -> Result<String, Diagnostics> {
+    let compiler = Compiler::new(CompileOptions {
+        emit,
+        ..options.compile_options.clone()
+    });
+    let output = if input == "-" {
+        let mut source = String::new();
+        io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|e| driver_error(Code::Io, Message::ReadStdinFailed(e.to_string())))?;
+        compiler.compile_named("<stdin>", &source)
+    } else {
+        compiler.compile_file(input)
+    };
+    output.map(|output| {
+        report(&output.warnings, options.error_format, options.lang);
+        output.text
+    })
+}
+
+// コンパイル結果をoutputに書き出す
+fn write_output(output: Option<&str>, text: &str) -> Result<(), Diagnostics> {
+    let mut out = open_output(output)?;
+    out.write_all(text.as_bytes())
+        .and_then(|_| out.flush())
+        .map_err(|e| driver_error(Code::Io, Message::WriteFailed(e.to_string())))
+}
+
+// 出力先を開く。Noneか"-"の場合は標準出力に書き込む
+fn open_output(output: Option<&str>) -> Result<Box<dyn Write>, Diagnostics> {
+    match output {
+        None | Some("-") => Ok(Box::new(io::stdout())),
+        Some(path) => fs::File::create(path)
+            .map(|f| Box::new(io::BufWriter::new(f)) as Box<dyn Write>)
+            .map_err(|e| {
+                driver_error(
+                    Code::Io,
+                    Message::CreateFailed {
+                        path: path.to_string(),
+                        reason: e.to_string(),
+                    },
+                )
+            }),
+    }
+}
+
+// 入力ファイル名の拡張子をextに置き換えたパスを返す
+fn with_extension(input: &str, ext: &str) -> String {
+    let stem = Path::new(input)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "a".to_string());
+    format!("{}.{}", stem, ext)
+}
+
+// 一時ファイルのパスを作る
+fn temp_path(idx: usize, ext: &str) -> PathBuf {
+    env::temp_dir().join(format!("dcc-{}-{}.{}", process::id(), idx, ext))
+}
+
+// 入力をコンパイルしたアセンブリをpathに書き出す
+fn compile_to_file(input: &str, path: &str, options: &Options) -> Result<(), Diagnostics> {
+    let asm = compile(input, Emit::Asm, options)?;
+    write_output(Some(path), &asm)
+}
+
+// Cコンパイラドライバ(cc)を呼び出してアセンブル・リンクを行う
+fn run_cc(args: &[String]) -> Result<(), Diagnostics> {
+    let status = Command::new("cc")
+        .args(args)
+        .status()
+        .map_err(|e| driver_error(Code::ToolFailed, Message::CcNotRunnable(e.to_string())))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(driver_error(
+            Code::ToolFailed,
+            Message::CcFailed(status.to_string()),
+        ))
+    }
+}
+
+// アセンブラ・リンカにそのまま渡す入力かどうか
+fn is_passthrough(input: &str) -> bool {
+    [".s", ".S", ".o", ".a"]
+        .iter()
+        .any(|ext| input != "-" && input.ends_with(ext))
+}
+
+pub fn run(options: &Options) -> Result<(), Diagnostics> {
+    // 各段階の中間表現を出力する場合は、全ての入力の結果を出力先にまとめて書き出す
+    if let Some(emit) = options.emit {
+        let mut text = String::new();
+        for input in options.inputs.iter() {
+            text += &compile(input, emit, options)?;
+        }
+        return write_output(options.output.as_deref(), &text);
+    }
+
+    if options.output.is_some() && options.inputs.len() > 1 && options.stage != Stage::Link {
+        return Err(driver_error(
+            Code::InvalidOption,
+            Message::OutputWithMultipleInputs,
+        ));
+    }
+
+    match options.stage {
+        Stage::Assemble => {
+            for input in options.inputs.iter() {
+                let output = match &options.output {
+                    Some(output) => output.clone(),
+                    None if input == "-" => "-".to_string(),
+                    None => with_extension(input, "s"),
+                };
+                compile_to_file(input, &output, options)?;
+            }
+            Ok(())
+        }
+        Stage::Object => {
+            for (idx, input) in options.inputs.iter().enumerate() {
+                let output = match &options.output {
+                    Some(output) => output.clone(),
+                    None if input == "-" => "a.o".to_string(),
+                    None => with_extension(input, "o"),
+                };
+                let asm = temp_path(idx, "s").to_string_lossy().to_string();
+                let res = compile_to_file(input, &asm, options).and_then(|_| {
+                    run_cc(&["-c".to_string(), asm.clone(), "-o".to_string(), output])
+                });
+                let _ = fs::remove_file(&asm);
+                res?;
+            }
+            Ok(())
+        }
+        Stage::Link => {
+            let mut cc_args = vec![];
+            let mut temps = vec![];
+            let mut res = Ok(());
+            for (idx, input) in options.inputs.iter().enumerate() {
+                if is_passthrough(input) {
+                    cc_args.push(input.clone());
+                    continue;
+                }
+                let asm = temp_path(idx, "s").to_string_lossy().to_string();
+                temps.push(asm.clone());
+                cc_args.push(asm.clone());
+                res = compile_to_file(input, &asm, options);
+                if res.is_err() {
+                    break;
+                }
+            }
+            if res.is_ok() {
+                cc_args.push("-o".to_string());
+                cc_args.push(
+                    options
+                        .output
+                        .clone()
+                        .unwrap_or_else(|| "a.out".to_string()),
+                );
+                res = run_cc(&cc_args);
+            }
+            for temp in temps.iter() {
+                let _ = fs::remove_file(temp);
+            }
+            res
+        }
+    }
+}
@@ -1,32 +1,252 @@
-use crate::common::Input;
+use std::{fmt, io};
 
-pub struct Error<'a> {
-    input: &'a Input,
+use crate::{
+    common::{SourceMap, Span},
+    message::{Lang, Message},
+    render::{self, Renderer},
+};
+
+// 診断の重大度
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+// 診断の種類を表すコード
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum Code {
+    Io,                           // 入出力に失敗した
+    InvalidToken,                 // トークナイズできない文字がある
+    ExpectedToken,                // 期待しているトークンがない
+    ExpectedIdent,                // 識別子がない
+    ExpectedNumber,               // 数字がない
+    UndefinedVariable,            // 定義されていない変数を使った
+    InvalidOperands,              // 二項演算の型が正しくない
+    AssignMismatch,               // 代入の両辺の型が合わない
+    NotAssignable,                // 左辺値でないものに代入した
+    DerefNonPointer,              // ポインタでないものを参照外しした
+    InvalidAddrOf,                // アドレスを取れないものに&を使った
+    UnknownSize,                  // 大きさがわからない型にsizeofを使った
+    TooManyArgs,                  // 引数レジスタに収まらない数の引数
+    InvalidOption,                // コマンドライン引数が正しくない
+    ToolFailed,                   // アセンブラ・リンカの実行に失敗した
+    TooManyErrors,                // エラーの数が上限に達した
+    UnterminatedComment,          // 閉じられていないブロックコメント
+    InvalidEscape,                // 不正なエスケープシーケンス
+    UnterminatedLiteral,          // 閉じられていない文字定数・文字列リテラル
+    InvalidCharLiteral,           // 空、または2文字以上の文字定数
+    InvalidNumber,                // 整数定数の数字や接尾辞が正しくない
+    IntegerTooLarge,              // どの整数型でも表せない整数定数
+    Unsupported,                  // まだサポートしていない構文
+    IncludeNotFound,              // #includeするファイルが見つからない
+    InvalidDirective,             // 前処理指令が正しくない
+    UnterminatedConditional,      // #endifで閉じられていない#if
+    ErrorDirective,               // #errorに到達した
+    InvalidConditionalExpression, // #ifの条件式が正しくない
+    MacroExpansion,               // マクロの呼び出しが正しくない
+    Redefinition,                 // 同じ名前のものを定義し直した
+    NotConstant,                  // 定数式でなければならないところに定数式でない式がある
+    InvalidMemberAccess,          // 構造体のメンバの参照が正しくない
+    IncompleteType,               // 不完全型の値を使った
+    InvalidArraySize,             // 配列の大きさが正しくない
+    InvalidCast,                  // 変換できない型へのキャスト
+    ArgCountMismatch,             // 関数呼び出しの引数の数が宣言と合わない
+    MissingReturnValue,           // 値を返す関数で、値のないreturnを使った
+    EnumOutOfRange,               // int型で表せない値の列挙定数
+    Internal,                     // コンパイラ内部のエラー
+
+    // 警告
+    UnusedVariable,              // 使われていない変数
+    UnreachableCode,             // 実行されない文
+    ImplicitFunctionDeclaration, // 宣言されていない関数の呼び出し
+    ReturnType,                  // 値を返さずに関数の終わりに到達する
+    AssignInCondition,           // 条件式としての代入
+}
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::Io => "E0001",
+            Code::InvalidToken => "E0002",
+            Code::ExpectedToken => "E0003",
+            Code::ExpectedIdent => "E0004",
+            Code::ExpectedNumber => "E0005",
+            Code::UndefinedVariable => "E0006",
+            Code::InvalidOperands => "E0007",
+            Code::AssignMismatch => "E0008",
+            Code::NotAssignable => "E0009",
+            Code::DerefNonPointer => "E0010",
+            Code::InvalidAddrOf => "E0011",
+            Code::UnknownSize => "E0012",
+            Code::TooManyArgs => "E0013",
+            Code::InvalidOption => "E0014",
+            Code::ToolFailed => "E0015",
+            Code::TooManyErrors => "E0016",
+            Code::UnterminatedComment => "E0017",
+            Code::InvalidEscape => "E0018",
+            Code::UnterminatedLiteral => "E0019",
+            Code::InvalidCharLiteral => "E0020",
+            Code::InvalidNumber => "E0021",
+            Code::IntegerTooLarge => "E0022",
+            Code::Unsupported => "E0023",
+            Code::IncludeNotFound => "E0024",
+            Code::InvalidDirective => "E0025",
+            Code::UnterminatedConditional => "E0026",
+            Code::ErrorDirective => "E0027",
+            Code::InvalidConditionalExpression => "E0028",
+            Code::MacroExpansion => "E0029",
+            Code::Redefinition => "E0030",
+            Code::NotConstant => "E0031",
+            Code::InvalidMemberAccess => "E0032",
+            Code::IncompleteType => "E0033",
+            Code::InvalidArraySize => "E0034",
+            Code::InvalidCast => "E0035",
+            Code::ArgCountMismatch => "E0036",
+            Code::MissingReturnValue => "E0037",
+            Code::EnumOutOfRange => "E0038",
+            Code::Internal => "E0999",
+            Code::UnusedVariable => "W0001",
+            Code::UnreachableCode => "W0002",
+            Code::ImplicitFunctionDeclaration => "W0003",
+            Code::ReturnType => "W0004",
+            Code::AssignInCondition => "W0005",
+        }
+    }
+}
+
+// 診断に付属する補足情報
+#[derive(Debug, Clone)]
+pub enum SubDiagnostic {
+    // 診断の原因に関係する箇所につけるラベル
+    Label {
+        span: Span,
+        message: Message,
+    },
+    // 注記
+    Note(Message),
+    // 診断を解消するための修正案。spanをreplacementで置き換える
+    Suggestion {
+        span: Span,
+        replacement: String,
+        message: Message,
+    },
+}
+
+// コンパイラが報告する1つの診断
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub span: Option<Span>, // 診断の原因となった箇所。入力と関係ない診断ではNone
+    pub message: Message,
+    pub label: Option<Message>,       // spanにつけるラベル
+    pub children: Vec<SubDiagnostic>, // 付け加えた順に並んだ補足情報
 }
-impl Error<'_> {
-    pub fn new(input: &Input) -> Self {
-        Self { input }
+impl Diagnostic {
+    fn new(severity: Severity, code: Code, span: Option<Span>, message: Message) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            span,
+            message,
+            label: None,
+            children: vec![],
+        }
+    }
+
+    // spanの位置にあるエラーを作る
+    pub fn error(code: Code, span: Span, message: Message) -> Self {
+        Self::new(Severity::Error, code, Some(span), message)
+    }
+
+    // spanの位置にある警告を作る
+    pub fn warning(code: Code, span: Span, message: Message) -> Self {
+        Self::new(Severity::Warning, code, Some(span), message)
+    }
+
+    // 入力の位置と関係しないエラーを作る
+    pub fn error_without_span(code: Code, message: Message) -> Self {
+        Self::new(Severity::Error, code, None, message)
+    }
+
+    // spanにラベルをつける
+    pub fn with_primary_label(mut self, label: Message) -> Self {
+        self.label = Some(label);
+        self
     }
 
-    pub fn lexer_error(&self, row: usize, col_start: usize, col_end: usize, msg: &String) {
-        eprintln!("エラー：{}", msg);
-        eprintln!("{} |", " ".repeat((row + 1).to_string().len()));
-        eprintln!(
-            "{} | {}",
-            row + 1,
-            self.input[row].iter().collect::<String>()
-        );
-        eprintln!(
-            "{} | {}{}",
-            " ".repeat((row + 1).to_string().len()),
-            " ".repeat(col_start.to_string().len()),
-            "^".repeat((col_end - col_start).to_string().len())
-        );
+    // span以外の関係する箇所にラベルをつける
+    pub fn with_label(mut self, span: Span, message: Message) -> Self {
+        self.children.push(SubDiagnostic::Label { span, message });
+        self
+    }
+
+    // 注記を付け加える
+    pub fn with_note(mut self, note: Message) -> Self {
+        self.children.push(SubDiagnostic::Note(note));
+        self
+    }
+
+    // 修正案を付け加える
+    pub fn with_suggestion(mut self, span: Span, replacement: &str, message: Message) -> Self {
+        self.children.push(SubDiagnostic::Suggestion {
+            span,
+            replacement: replacement.to_string(),
+            message,
+        });
+        self
+    }
+
+    // 診断をsourcesの該当箇所とともにlangの言語で文字列にする。colorが真のときは端末向けに色をつける
+    pub fn render(&self, sources: &SourceMap, color: bool, lang: Lang) -> String {
+        Renderer::new(sources, color, lang).render(self)
+    }
+
+    // 診断をlangの言語で1行のJSONにする
+    pub fn render_json(&self, sources: &SourceMap, lang: Lang) -> String {
+        render::render_json(self, sources, lang)
+    }
+}
+impl From<io::Error> for Diagnostic {
+    fn from(e: io::Error) -> Self {
+        Diagnostic::error_without_span(Code::Io, Message::WriteFailed(e.to_string()))
     }
 }
 
-pub fn error() {
-    eprintln!("エラーが発生しました");
+// コンパイルに失敗したときに呼び出し元へ返す診断の集まり
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    pub diagnostics: Vec<Diagnostic>,
+    pub sources: SourceMap, // 診断の対象となったソースファイル
+}
+impl Diagnostics {
+    pub fn new(diagnostics: Vec<Diagnostic>, sources: SourceMap) -> Self {
+        Diagnostics {
+            diagnostics,
+            sources,
+        }
+    }
+}
+impl Diagnostics {
+    // 全ての診断をlangの言語で文字列にする。colorが真のときは端末向けに色をつける
+    pub fn render(&self, color: bool, lang: Lang) -> String {
+        self.diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.render(&self.sources, color, lang))
+            .collect()
+    }
 
-    std::process::exit(1);
+    // 全ての診断を、langの言語で1行に1つずつJSONにする
+    pub fn render_json(&self, lang: Lang) -> String {
+        self.diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.render_json(&self.sources, lang))
+            .collect()
+    }
+}
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false, Lang::default()))
+    }
 }
@@ -1,277 +1,773 @@
-use std::vec;
+use std::rc::Rc;
 
 use crate::{
-    common::{Input, Line},
-    error::Error,
+    common::Span,
+    error::{Code, Diagnostic},
+    message::Message,
+    symbol::{HideSet, Symbol, SymbolTable},
 };
 
+// C11の記号。最長一致で切り出せるように、長いものから順に並べる
+const PUNCTUATORS: [&str; 54] = [
+    "%:%:", // 4文字
+    "...", "<<=", ">>=", // 3文字
+    "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=", "%=", "+=", "-=",
+    "&=", "^=", "|=", "##", "<:", ":>", "<%", "%>", "%:", // 2文字
+    "[", "]", "(", ")", "{", "}", ".", "&", "*", "+", "-", "~", "!", "/", "%", "<", ">", "^", "|",
+    "?", ":", ";", "=", ",", "#", // 1文字
+];
+
+// 二重音字を対応する記号に置き換える
+fn canonical_sign(sign: &'static str) -> &'static str {
+    match sign {
+        "<:" => "[",
+        ":>" => "]",
+        "<%" => "{",
+        "%>" => "}",
+        "%:" => "#",
+        "%:%:" => "##",
+        _ => sign,
+    }
+}
+
+// キーワードの表。wordがC11のキーワードであれば対応するトークンの種類を返す
+fn keyword(word: &[u8]) -> Option<TokenKind> {
+    let kind = match word {
+        b"auto" => TokenKind::Auto,
+        b"break" => TokenKind::Break,
+        b"case" => TokenKind::Case,
+        b"char" => TokenKind::Char,
+        b"const" => TokenKind::Const,
+        b"continue" => TokenKind::Continue,
+        b"default" => TokenKind::Default,
+        b"do" => TokenKind::Do,
+        b"double" => TokenKind::Double,
+        b"else" => TokenKind::Else,
+        b"enum" => TokenKind::Enum,
+        b"extern" => TokenKind::Extern,
+        b"float" => TokenKind::Float,
+        b"for" => TokenKind::For,
+        b"goto" => TokenKind::Goto,
+        b"if" => TokenKind::If,
+        b"inline" => TokenKind::Inline,
+        b"int" => TokenKind::Int,
+        b"long" => TokenKind::Long,
+        b"register" => TokenKind::Register,
+        b"restrict" => TokenKind::Restrict,
+        b"return" => TokenKind::Return,
+        b"short" => TokenKind::Short,
+        b"signed" => TokenKind::Signed,
+        b"sizeof" => TokenKind::Sizeof,
+        b"static" => TokenKind::Static,
+        b"struct" => TokenKind::Struct,
+        b"switch" => TokenKind::Switch,
+        b"typedef" => TokenKind::Typedef,
+        b"union" => TokenKind::Union,
+        b"unsigned" => TokenKind::Unsigned,
+        b"void" => TokenKind::Void,
+        b"volatile" => TokenKind::Volatile,
+        b"while" => TokenKind::While,
+        b"_Alignas" => TokenKind::Alignas,
+        b"_Alignof" => TokenKind::Alignof,
+        b"_Atomic" => TokenKind::Atomic,
+        b"_Bool" => TokenKind::Bool,
+        b"_Complex" => TokenKind::Complex,
+        b"_Generic" => TokenKind::Generic,
+        b"_Imaginary" => TokenKind::Imaginary,
+        b"_Noreturn" => TokenKind::Noreturn,
+        b"_Static_assert" => TokenKind::StaticAssert,
+        b"_Thread_local" => TokenKind::ThreadLocal,
+        _ => return None,
+    };
+    Some(kind)
+}
+
 // トークンの種類
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum TokenKind {
-    Reserved { sign: String }, // 記号
-    ID { name: String },       // 識別子
-    Num { val: isize },        // 整数トークン
-    If,                        // if
-    Else,                      // else
-    While,                     // while
-    For,                       // for
-    Return,                    // リターン
-    Sizeof,                    // sizeof
+    Reserved { sign: &'static str },  // 記号
+    ID { name: Symbol },              // 識別子。名前はTokenListの記号表にある
+    Num { val: isize, typ: NumType }, // 整数トークン
+    CharLit { val: isize },           // 文字定数
+    Str { val: Vec<u8> }, // 文字列リテラル。エスケープシーケンスは展開済みで、末尾の'\0'は含まない
+
+    // キーワード
+    Auto,         // auto
+    Break,        // break
+    Case,         // case
+    Char,         // char
+    Const,        // const
+    Continue,     // continue
+    Default,      // default
+    Do,           // do
+    Double,       // double
+    Else,         // else
+    Enum,         // enum
+    Extern,       // extern
+    Float,        // float
+    For,          // for
+    Goto,         // goto
+    If,           // if
+    Inline,       // inline
+    Int,          // int
+    Long,         // long
+    Register,     // register
+    Restrict,     // restrict
+    Return,       // return
+    Short,        // short
+    Signed,       // signed
+    Sizeof,       // sizeof
+    Static,       // static
+    Struct,       // struct
+    Switch,       // switch
+    Typedef,      // typedef
+    Union,        // union
+    Unsigned,     // unsigned
+    Void,         // void
+    Volatile,     // volatile
+    While,        // while
+    Alignas,      // _Alignas
+    Alignof,      // _Alignof
+    Atomic,       // _Atomic
+    Bool,         // _Bool
+    Complex,      // _Complex
+    Generic,      // _Generic
+    Imaginary,    // _Imaginary
+    Noreturn,     // _Noreturn
+    StaticAssert, // _Static_assert
+    ThreadLocal,  // _Thread_local
+
+    Eof,
+}
+impl TokenKind {
+    // 記号signであるか
+    pub fn is_sign(&self, sign: &str) -> bool {
+        matches!(self, TokenKind::Reserved { sign: s } if *s == sign)
+    }
+
+    // キーワードであれば、ソースコード上での綴りを返す
+    pub fn keyword(&self) -> Option<&'static str> {
+        let word = match self {
+            TokenKind::Auto => "auto",
+            TokenKind::Break => "break",
+            TokenKind::Case => "case",
+            TokenKind::Char => "char",
+            TokenKind::Const => "const",
+            TokenKind::Continue => "continue",
+            TokenKind::Default => "default",
+            TokenKind::Do => "do",
+            TokenKind::Double => "double",
+            TokenKind::Else => "else",
+            TokenKind::Enum => "enum",
+            TokenKind::Extern => "extern",
+            TokenKind::Float => "float",
+            TokenKind::For => "for",
+            TokenKind::Goto => "goto",
+            TokenKind::If => "if",
+            TokenKind::Inline => "inline",
+            TokenKind::Int => "int",
+            TokenKind::Long => "long",
+            TokenKind::Register => "register",
+            TokenKind::Restrict => "restrict",
+            TokenKind::Return => "return",
+            TokenKind::Short => "short",
+            TokenKind::Signed => "signed",
+            TokenKind::Sizeof => "sizeof",
+            TokenKind::Static => "static",
+            TokenKind::Struct => "struct",
+            TokenKind::Switch => "switch",
+            TokenKind::Typedef => "typedef",
+            TokenKind::Union => "union",
+            TokenKind::Unsigned => "unsigned",
+            TokenKind::Void => "void",
+            TokenKind::Volatile => "volatile",
+            TokenKind::While => "while",
+            TokenKind::Alignas => "_Alignas",
+            TokenKind::Alignof => "_Alignof",
+            TokenKind::Atomic => "_Atomic",
+            TokenKind::Bool => "_Bool",
+            TokenKind::Complex => "_Complex",
+            TokenKind::Generic => "_Generic",
+            TokenKind::Imaginary => "_Imaginary",
+            TokenKind::Noreturn => "_Noreturn",
+            TokenKind::StaticAssert => "_Static_assert",
+            TokenKind::ThreadLocal => "_Thread_local",
+            _ => return None,
+        };
+        Some(word)
+    }
+}
+
+// 整数定数の型。intは32bit、long, long longは64bit
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum NumType {
     Int,
-    Char,
-    EOF,
+    UInt,
+    Long,
+    ULong,
+    LongLong,
+    ULongLong,
 }
+impl NumType {
+    // この型で表せる最大の値
+    fn max(&self) -> u64 {
+        match self {
+            NumType::Int => i32::MAX as u64,
+            NumType::UInt => u32::MAX as u64,
+            NumType::Long | NumType::LongLong => i64::MAX as u64,
+            NumType::ULong | NumType::ULongLong => u64::MAX,
+        }
+    }
+
+    // Cでの型名
+    pub fn name(&self) -> &'static str {
+        match self {
+            NumType::Int => "int",
+            NumType::UInt => "unsigned int",
+            NumType::Long => "long",
+            NumType::ULong => "unsigned long",
+            NumType::LongLong => "long long",
+            NumType::ULongLong => "unsigned long long",
+        }
+    }
+
+    // 符号なしの型か
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, NumType::UInt | NumType::ULong | NumType::ULongLong)
+    }
+
+    // 接尾辞と基数から、整数定数の型の候補を順に返す
+    fn candidates(unsigned: bool, longs: usize, decimal: bool) -> &'static [NumType] {
+        use NumType::*;
+        match (unsigned, longs, decimal) {
+            (false, 0, true) => &[Int, Long, LongLong],
+            (false, 0, false) => &[Int, UInt, Long, ULong, LongLong, ULongLong],
+            (true, 0, _) => &[UInt, ULong, ULongLong],
+            (false, 1, true) => &[Long, LongLong],
+            (false, 1, false) => &[Long, ULong, LongLong, ULongLong],
+            (true, 1, _) => &[ULong, ULongLong],
+            (false, _, true) => &[LongLong],
+            (false, _, false) => &[LongLong, ULongLong],
+            (true, _, _) => &[ULongLong],
+        }
+    }
+}
+
 // トークン型
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token {
-    pub row: usize,
-    pub col_start: usize,
-    pub col_end: usize,
+    pub span: Span,
     pub kind: TokenKind,
+    pub bol: bool,        // 行の先頭にあるか。前処理指令を見分けるのに使う
+    pub space: bool,      // 直前に空白があるか。マクロの引数を文字列にするときに使う
+    pub hideset: HideSet, // このトークンをもう展開してはいけないマクロの名前
 }
 
-// プログラムを表すトークン列
-#[derive(Debug)]
-pub struct TokenList {
-    pub now: usize, // 今着目しているトークンのindex
-    pub input: Input,
-    pub tokens: Vec<Token>,
+// textの中の'\'と改行の組の位置を、(取り除いた後の位置, そこまでに取り除いたバイト数)の列で返す
+fn find_splices(text: &str) -> Vec<(usize, usize)> {
+    let src = text.as_bytes();
+    let mut splices = vec![];
+    let mut removed = 0;
+    let mut idx = 0;
+    while idx < src.len() {
+        let len = match (src[idx], src.get(idx + 1), src.get(idx + 2)) {
+            (b'\\', Some(b'\n'), _) => 2,
+            (b'\\', Some(b'\r'), Some(b'\n')) => 3,
+            _ => 0,
+        };
+        if len == 0 {
+            idx += 1;
+            continue;
+        }
+        removed += len;
+        idx += len;
+        splices.push((idx - removed, removed));
+    }
+    splices
 }
-impl TokenList {
-    fn new(input: Input) -> Self {
-        TokenList {
-            now: 0,
-            input,
-            tokens: vec![],
+
+// 行末の'\'で分けられた行をつなげた文字列を返す
+pub fn remove_splices(text: &str) -> String {
+    let mut spliced = String::with_capacity(text.len());
+    let (mut prev, mut prev_removed) = (0, 0);
+    for (pos, removed) in find_splices(text) {
+        let orig = pos + removed;
+        spliced.push_str(&text[prev..orig - (removed - prev_removed)]);
+        (prev, prev_removed) = (orig, removed);
+    }
+    spliced.push_str(&text[prev..]);
+    spliced
+}
+
+// ソースコードを先頭から1度だけ走査して、トークンを1つずつ切り出す字句解析器。
+// 行末の'\'による行の連結は、読み始める前にまとめて済ませておく
+pub struct Lexer {
+    text: Rc<str>,                // 行を連結した後のソースコード
+    splices: Vec<(usize, usize)>, // 連結した位置。spanを元のソースコードの位置に戻すのに使う
+    pos: usize,                   // 次に読むバイトの位置
+    file: u32,                    // 読んでいるソースファイルの番号
+    bol: bool,                    // 最後に読んだトークンの後に改行があったか
+}
+impl Lexer {
+    pub fn new(file: u32, text: Rc<str>) -> Self {
+        let splices = find_splices(&text);
+        let text = if splices.is_empty() {
+            text
+        } else {
+            Rc::from(remove_splices(&text))
+        };
+        Lexer {
+            text,
+            splices,
+            pos: 0,
+            file,
+            bol: true,
         }
     }
 
-    // lineにおいて、line[idx]からwordというTokenを作ることができるか判定し、作れるときtoken_listに足す。
-    // Tokenを作った場合、Some(new_idx)、そうでないときNoneを返す
-    fn can_tokenize_word(
-        &mut self,
-        line: &Line,
-        row: usize,
-        idx: usize,
-        word: &String,
-        kind: TokenKind,
-    ) -> Option<usize> {
-        let len = word.len();
-        if idx + (len - 1) < line.len()
-            && line[idx..(idx + len)].iter().collect::<String>().eq(word)
-            && (idx + len >= line.len()
-                || !matches!(line[idx + len], 'a'..='z' | 'A'..='Z' | '_' | '0'..='9'))
-        {
-            self.tokens.push(Token {
-                row,
-                col_start: idx,
-                col_end: idx + len,
-                kind,
-            });
-            Some(idx + len)
+    fn src(&self) -> &[u8] {
+        self.text.as_bytes()
+    }
+
+    // 連結した後の位置startからendまでを、元のソースコードのspanにする
+    fn span(&self, start: usize, end: usize) -> Span {
+        // startは直前の連結の後、endは直後の連結の前を指すようにする
+        let orig = |idx: usize| self.splices.get(idx.wrapping_sub(1)).map_or(0, |s| s.1);
+        let start = start + orig(self.splices.partition_point(|s| s.0 <= start));
+        let end = end + orig(self.splices.partition_point(|s| s.0 < end));
+        Span::new(self.file, start, end.max(start))
+    }
+
+    // pos+nバイト目を返す。入力の終わりより後ろでは0を返す
+    fn peek(&self, n: usize) -> u8 {
+        self.src().get(self.pos + n).copied().unwrap_or(0)
+    }
+
+    // idxバイト目を含む行の終わり(改行とその前の'\r'を除く)の位置を返す
+    fn line_end(&self, idx: usize) -> usize {
+        let end = self.src()[idx..]
+            .iter()
+            .position(|&c| c == b'\n')
+            .map_or(self.src().len(), |len| idx + len);
+        if end > idx && self.src()[end - 1] == b'\r' {
+            end - 1
         } else {
-            None
+            end
         }
     }
 
-    pub fn tokenize(p: &Vec<char>) -> (Self, Error) {
-        // 文字列からInput型を生成する
-        let mut input: Input = vec![];
-        let mut line: Line = vec![];
-        for c in p.iter() {
-            if *c == '\n' {
-                input.push(line);
-                line = vec![];
-            } else {
-                line.push(*c);
+    // 空白文字、コメントを読み飛ばす。newlineが真のときは改行も読み飛ばす
+    fn skip_whitespace(&mut self, newline: bool) -> Result<(), Diagnostic> {
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (b' ' | b'\t' | b'\r' | b'\x0b' | b'\x0c', _) => self.pos += 1,
+                (b'\n', _) if newline => {
+                    self.pos += 1;
+                    self.bol = true;
+                }
+                // 行コメントは行末までスキップ
+                (b'/', b'/') => {
+                    while self.pos < self.src().len() && self.src()[self.pos] != b'\n' {
+                        self.pos += 1;
+                    }
+                }
+                // ブロックコメントは"*/"までスキップ。ブロックコメントは複数行にまたがることがある
+                (b'/', b'*') => {
+                    let start = self.pos;
+                    match self.src()[start + 2..].windows(2).position(|w| w == b"*/") {
+                        Some(len) => self.pos = start + 2 + len + 2,
+                        None => {
+                            let end = self.src().len();
+                            return Err(Diagnostic::error(
+                                Code::UnterminatedComment,
+                                self.span(end, end),
+                                Message::UnterminatedComment,
+                            )
+                            .with_label(self.span(start, start + 2), Message::CommentStartsHere));
+                        }
+                    }
+                }
+                _ => return Ok(()),
             }
         }
-        input.push(line);
+    }
 
-        // Error型の作成
-        let error = Error::new(&input);
+    // 次のトークンを切り出す。入力の終わりではEofトークンを返す
+    pub fn next_token(&mut self, symbols: &mut SymbolTable) -> Result<Token, Diagnostic> {
+        let before = self.pos;
+        self.skip_whitespace(true)?;
+        let start = self.pos;
+        let bol = std::mem::replace(&mut self.bol, false);
+        let space = bol || start > before;
+        if start >= self.src().len() {
+            return Ok(Token {
+                span: self.span(start, start),
+                kind: TokenKind::Eof,
+                bol,
+                space,
+                hideset: HideSet::default(),
+            });
+        }
 
-        // 新しいTokenList型の作成
-        let mut token_list = Self::new(input);
+        let c = self.src()[start];
+        let kind = match c {
+            // 識別子かキーワード
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+                while matches!(self.peek(0), b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'0'..=b'9') {
+                    self.pos += 1;
+                }
+                let word = &self.src()[start..self.pos];
+                keyword(word).unwrap_or_else(|| TokenKind::ID {
+                    name: symbols.intern(&self.text[start..self.pos]),
+                })
+            }
 
-        for (row, line) in token_list.input.iter().enumerate() {
-            let mut idx = 0;
+            // 数字
+            b'0'..=b'9' => {
+                let (val, typ) = self.read_number()?;
+                TokenKind::Num { val, typ }
+            }
 
-            while idx < line.len() {
-                // 空白文字はスキップ
-                if line[idx] == ' ' {
-                    idx += 1;
-                    continue;
+            // 文字定数
+            b'\'' => {
+                let bytes = self.read_literal(b'\'')?;
+                let span = self.span(start, self.pos);
+                let val = match bytes.len() {
+                    0 => Err(Message::EmptyCharLiteral),
+                    1 => Ok(bytes[0] as i8 as isize), // charは符号付き
+                    _ => Err(Message::MultiCharLiteral),
                 }
+                .map_err(|msg| Diagnostic::error(Code::InvalidCharLiteral, span, msg))?;
+                TokenKind::CharLit { val }
+            }
 
-                // if
-                if let Some(new_idx) =
-                    token_list.can_tokenize_word(line, row, idx, &"if".to_string(), TokenKind::If)
-                {
-                    idx = new_idx;
-                    continue;
-                }
+            // 文字列リテラル
+            b'"' => TokenKind::Str {
+                val: self.read_literal(b'"')?,
+            },
 
-                // else
-                if let Some(new_idx) = token_list.can_tokenize_word(
-                    line,
-                    row,
-                    idx,
-                    &"else".to_string(),
-                    TokenKind::Else,
-                ) {
-                    idx = new_idx;
-                    continue;
+            // 記号。先頭の1バイトで候補を絞り、最も長く一致するものを選ぶ
+            _ => {
+                let rest = &self.src()[start..];
+                let sign = PUNCTUATORS
+                    .iter()
+                    .find(|sign| sign.as_bytes()[0] == c && rest.starts_with(sign.as_bytes()))
+                    .ok_or_else(|| {
+                        let len = self.text[start..].chars().next().map_or(1, char::len_utf8);
+                        Diagnostic::error(
+                            Code::InvalidToken,
+                            self.span(start, start + len),
+                            Message::InvalidToken,
+                        )
+                    })?;
+                self.pos += sign.len();
+                TokenKind::Reserved {
+                    sign: canonical_sign(sign),
                 }
+            }
+        };
+        Ok(Token {
+            span: self.span(start, self.pos),
+            kind,
+            bol,
+            space,
+            hideset: HideSet::default(),
+        })
+    }
 
-                // for
-                if let Some(new_idx) =
-                    token_list.can_tokenize_word(line, row, idx, &"for".to_string(), TokenKind::For)
-                {
-                    idx = new_idx;
-                    continue;
-                }
+    // 今の行に残っているトークンを1つ切り出す。行末に達したときはNoneを返す
+    pub fn next_token_in_line(
+        &mut self,
+        symbols: &mut SymbolTable,
+    ) -> Result<Option<Token>, Diagnostic> {
+        let before = self.pos;
+        self.skip_whitespace(false)?;
+        if self.pos >= self.src().len() || self.peek(0) == b'\n' {
+            return Ok(None);
+        }
+        let space = self.pos > before;
+        let mut token = self.next_token(symbols)?;
+        token.space |= space;
+        Ok(Some(token))
+    }
 
-                // while
-                if let Some(new_idx) = token_list.can_tokenize_word(
-                    line,
-                    row,
-                    idx,
-                    &"while".to_string(),
-                    TokenKind::While,
-                ) {
-                    idx = new_idx;
-                    continue;
-                }
+    // 今の行の残りを、前後の空白を除いて読み飛ばし、その範囲を返す
+    pub fn rest_of_line(&mut self) -> Result<Span, Diagnostic> {
+        self.skip_whitespace(false)?;
+        let start = self.pos;
+        let mut end = start;
+        while end < self.src().len() && self.src()[end] != b'\n' {
+            end += 1;
+        }
+        self.pos = end;
+        while end > start && self.src()[end - 1].is_ascii_whitespace() {
+            end -= 1;
+        }
+        Ok(self.span(start, end))
+    }
 
-                // int
-                if let Some(new_idx) =
-                    token_list.can_tokenize_word(line, row, idx, &"int".to_string(), TokenKind::Int)
-                {
-                    idx = new_idx;
-                    continue;
+    // 前処理指令で始まる行か入力の終わりまで、トークンに切り出さずに読み飛ばす。
+    // 読み飛ばす部分には、閉じられていない引用符のようなトークンにできないものがあってもよい
+    pub fn skip_group(&mut self) -> Result<(), Diagnostic> {
+        loop {
+            self.skip_whitespace(true)?;
+            if self.pos >= self.src().len()
+                || self.peek(0) == b'#'
+                || (self.peek(0), self.peek(1)) == (b'%', b':')
+            {
+                return Ok(());
+            }
+            // 行末まで読み飛ばす。ブロックコメントは閉じられるまで読み飛ばす
+            while self.pos < self.src().len() && self.peek(0) != b'\n' {
+                if (self.peek(0), self.peek(1)) == (b'/', b'*') {
+                    self.skip_whitespace(false)?;
+                } else {
+                    self.pos += 1;
                 }
+            }
+        }
+    }
 
-                // return
-                if let Some(new_idx) = token_list.can_tokenize_word(
-                    line,
-                    row,
-                    idx,
-                    &"return".to_string(),
-                    TokenKind::Return,
-                ) {
-                    idx = new_idx;
-                    continue;
-                }
+    // spanが指すソースコードの文字列を、行を連結した後の形で返す
+    pub fn slice(&self, span: Span) -> &str {
+        let pos = |orig: usize| {
+            let idx = self.splices.partition_point(|s| s.0 + s.1 <= orig);
+            orig - idx.checked_sub(1).map_or(0, |i| self.splices[i].1)
+        };
+        &self.text[pos(span.start as usize)..pos(span.end as usize)]
+    }
 
-                // sizeof
-                if let Some(new_idx) = token_list.can_tokenize_word(
-                    line,
-                    row,
-                    idx,
-                    &"sizeof".to_string(),
-                    TokenKind::Sizeof,
-                ) {
-                    idx = new_idx;
-                    continue;
-                }
+    // posの'\'から始まるエスケープシーケンスを読み、その値を返す
+    fn read_escape(&mut self) -> Result<u8, Diagnostic> {
+        let start = self.pos;
+        let c = self.peek(1);
+        let simple = match c {
+            b'n' => Some(b'\n'),
+            b't' => Some(b'\t'),
+            b'r' => Some(b'\r'),
+            b'a' => Some(0x07),
+            b'b' => Some(0x08),
+            b'f' => Some(0x0c),
+            b'v' => Some(0x0b),
+            b'\\' | b'\'' | b'"' | b'?' => Some(c),
+            _ => None,
+        };
+        if let Some(val) = simple {
+            self.pos += 2;
+            return Ok(val);
+        }
 
-                // 2文字の記号
-                if idx + 1 < line.len() {
-                    if let Some(&sign) = ["<=", ">=", "==", "!="]
-                        .iter()
-                        .find(|&&c| c.to_string() == line[idx..=idx + 1].iter().collect::<String>())
-                    {
-                        token_list.tokens.push(Token {
-                            row,
-                            col_start: idx,
-                            col_end: idx + 2,
-                            kind: TokenKind::Reserved {
-                                sign: sign.to_string(),
-                            },
-                        });
-                        idx += 2;
-                        continue;
-                    }
-                }
+        // 8進数は3桁まで、16進数は続く限り読む
+        let (radix, digits_start, max_len) = match c {
+            b'0'..=b'7' => (8, start + 1, 3),
+            b'x' => (16, start + 2, usize::MAX),
+            _ => {
+                let c = self.text[start + 1..].chars().next().unwrap();
+                return Err(Diagnostic::error(
+                    Code::InvalidEscape,
+                    self.span(start, start + 1 + c.len_utf8()),
+                    Message::InvalidEscape(c),
+                ));
+            }
+        };
+        let mut end = digits_start;
+        let mut val: u32 = 0;
+        while end < self.src().len() && end - digits_start < max_len {
+            match (self.src()[end] as char).to_digit(radix) {
+                Some(digit) => val = val.saturating_mul(radix).saturating_add(digit),
+                None => break,
+            }
+            end += 1;
+        }
+        let span = self.span(start, end);
+        if end == digits_start {
+            // "\x"の後に16進数の数字がない
+            return Err(Diagnostic::error(
+                Code::InvalidEscape,
+                span,
+                Message::InvalidEscape(c as char),
+            ));
+        }
+        let val = u8::try_from(val)
+            .map_err(|_| Diagnostic::error(Code::InvalidEscape, span, Message::EscapeOutOfRange))?;
+        self.pos = end;
+        Ok(val)
+    }
 
-                // 1文字の記号
-                if idx + 1 < line.len() {
-                    if let Some(sign) = "+-*/()<>=;{},&[]".chars().find(|&c| c == line[idx]) {
-                        token_list.tokens.push(Token {
-                            row,
-                            col_start: idx,
-                            col_end: idx + 1,
-                            kind: TokenKind::Reserved {
-                                sign: sign.to_string(),
-                            },
-                        });
-                        idx += 1;
-                        continue;
-                    }
-                }
+    // posのquoteから始まる文字定数・文字列リテラルを読み、その中身のバイト列を返す
+    fn read_literal(&mut self, quote: u8) -> Result<Vec<u8>, Diagnostic> {
+        let start = self.pos;
+        let mut bytes = vec![];
+        self.pos += 1;
+        loop {
+            let c = self.peek(0);
+            // 改行より前で閉じられていない
+            if self.pos >= self.src().len() || c == b'\n' || (c == b'\\' && self.peek(1) == 0) {
+                let msg = if quote == b'\'' {
+                    Message::UnterminatedChar
+                } else {
+                    Message::UnterminatedString
+                };
+                return Err(Diagnostic::error(
+                    Code::UnterminatedLiteral,
+                    self.span(start, self.line_end(start)),
+                    msg,
+                ));
+            }
+            if c == quote {
+                self.pos += 1;
+                return Ok(bytes);
+            }
+            if c == b'\\' {
+                bytes.push(self.read_escape()?);
+            } else {
+                // ASCII以外の文字はUTF-8のバイト列のまま埋め込む
+                bytes.push(c);
+                self.pos += 1;
+            }
+        }
+    }
 
-                // 識別子
-                if matches!(line[idx], 'a'..='z' | 'A'..='Z') {
-                    // アルファベットが終わるところまでループ
-                    let mut alpha_idx = idx + 1;
-                    while alpha_idx < line.len()
-                        && matches!(line[alpha_idx], 'a'..='z' | 'A'..='Z' | '_' | '0'..='9')
-                    {
-                        alpha_idx += 1;
-                    }
-                    token_list.tokens.push(Token {
-                        row,
-                        col_start: idx,
-                        col_end: idx + alpha_idx,
-                        kind: TokenKind::ID {
-                            name: line[idx..=idx + alpha_idx].iter().collect::<String>(),
-                        },
-                    });
-                    idx = alpha_idx;
-                    continue;
-                }
+    // posから始まる整数定数を読み、その値と型を返す
+    fn read_number(&mut self) -> Result<(isize, NumType), Diagnostic> {
+        // 英数字が続くところまでを1つの整数定数とみなす
+        let idx = self.pos;
+        let mut end = idx;
+        while end < self.src().len()
+            && (self.src()[end].is_ascii_alphanumeric() || self.src()[end] == b'_')
+        {
+            end += 1;
+        }
+        let text = &self.src()[idx..end];
+        let span = self.span(idx, end);
 
-                // 数字
-                if (line[idx]).is_numeric() {
-                    // 数字が終わるところまでループ
-                    let mut digit_idx = idx + 1;
-                    while digit_idx < line.len() && line[digit_idx].is_numeric() {
-                        digit_idx += 1;
-                    }
-                    token_list.tokens.push(Token {
-                        row,
-                        col_start: idx,
-                        col_end: idx + digit_idx,
-                        kind: TokenKind::Num {
-                            val: line[idx..=idx + digit_idx]
-                                .iter()
-                                .collect::<String>()
-                                .parse()
-                                .unwrap(),
-                        },
-                    });
-                    idx = digit_idx;
-                    continue;
-                }
+        // 接頭辞から基数を決める
+        let prefix = if text.len() >= 2 { text[1] } else { b' ' };
+        let (radix, start) = match (text[0], prefix) {
+            (b'0', b'x' | b'X') => (16, 2),
+            (b'0', b'b' | b'B') => (2, 2),
+            (b'0', _) if text.len() > 1 => (8, 1),
+            _ => (10, 0),
+        };
+        let mut digits_end = start;
+        while digits_end < text.len()
+            && (text[digits_end].is_ascii_digit()
+                || (radix == 16 && text[digits_end].is_ascii_hexdigit()))
+        {
+            digits_end += 1;
+        }
+        if digits_end == start && radix != 8 {
+            return Err(Diagnostic::error(
+                Code::InvalidNumber,
+                span,
+                Message::NoDigits(radix),
+            ));
+        }
+
+        let mut val: Option<u64> = Some(0);
+        for (i, &c) in text[start..digits_end].iter().enumerate() {
+            let digit = (c as char).to_digit(radix).ok_or_else(|| {
+                let col = idx + start + i;
+                Diagnostic::error(
+                    Code::InvalidNumber,
+                    self.span(col, col + 1),
+                    Message::InvalidDigit(c as char, radix),
+                )
+            })?;
+            val = val
+                .and_then(|v| v.checked_mul(radix as u64))
+                .and_then(|v| v.checked_add(digit as u64));
+        }
 
-                error.lexer_error(row, idx, idx + 1, &"トークナイズできません".to_string());
+        // 接尾辞はu, l, llの組み合わせ
+        let suffix = &self.text[idx + digits_end..end];
+        let (unsigned, longs) = match suffix {
+            "" => (false, 0),
+            "u" | "U" => (true, 0),
+            "l" | "L" => (false, 1),
+            "ul" | "uL" | "Ul" | "UL" | "lu" | "lU" | "Lu" | "LU" => (true, 1),
+            "ll" | "LL" => (false, 2),
+            "ull" | "uLL" | "Ull" | "ULL" | "llu" | "llU" | "LLu" | "LLU" => (true, 2),
+            _ => {
+                return Err(Diagnostic::error(
+                    Code::InvalidNumber,
+                    self.span(idx + digits_end, end),
+                    Message::InvalidSuffix(suffix.to_string()),
+                ));
             }
+        };
 
-            token_list.tokens.push(Token {
-                row,
-                col_start: idx,
-                col_end: idx,
-                kind: TokenKind::EOF,
-            });
+        // 値を表せる最初の型を選ぶ
+        let too_large = || Diagnostic::error(Code::IntegerTooLarge, span, Message::IntegerTooLarge);
+        let val = val.ok_or_else(too_large)?;
+        let typ = *NumType::candidates(unsigned, longs, radix == 10)
+            .iter()
+            .find(|typ| val <= typ.max())
+            .ok_or_else(too_large)?;
+        self.pos = end;
+        Ok((val as i64 as isize, typ))
+    }
+}
+
+// プログラムを表すトークン列
+#[derive(Debug)]
+pub struct TokenList {
+    pub now: usize, // 今着目しているトークンのindex
+    pub tokens: Vec<Token>,
+    pub symbols: SymbolTable, // 識別子の名前の記号表
+}
+impl TokenList {
+    pub fn new(tokens: Vec<Token>, symbols: SymbolTable) -> Self {
+        TokenList {
+            now: 0,
+            tokens,
+            symbols,
+        }
+    }
+
+    // file番目のソースファイルの中身textを、前処理をせずにトークン列にする
+    pub fn tokenize(file: u32, text: &str) -> Result<Self, Diagnostic> {
+        let mut lexer = Lexer::new(file, Rc::from(text));
+        let mut symbols = SymbolTable::new();
+        let mut tokens = vec![];
+        loop {
+            let token = lexer.next_token(&mut symbols)?;
+            let eof = token.kind == TokenKind::Eof;
+            tokens.push(token);
+            if eof {
+                break;
+            }
         }
-        (token_list, error)
+        Ok(TokenList::new(tokens, symbols))
     }
 
-    fn get_now_token(&self) -> &Token {
+    // トークンの種類を、識別子の名前を含めて表示用の文字列にする
+    pub fn describe(&self, kind: &TokenKind) -> String {
+        match kind {
+            TokenKind::ID { name } => format!("ID {{ name: {:?} }}", self.symbols.name(*name)),
+            kind => format!("{:?}", kind),
+        }
+    }
+
+    pub fn get_now_token(&self) -> &Token {
         &(self.tokens[self.now])
     }
 
+    // 今着目しているトークンの位置を返す
+    pub fn now_span(&self) -> Span {
+        self.get_now_token().span
+    }
+
+    // start番目のトークンから直前に読んだトークンまでの範囲を返す
+    pub fn span_from(&self, start: usize) -> Span {
+        let end = if self.now > start {
+            self.now - 1
+        } else {
+            start
+        };
+        self.tokens[start].span.to(self.tokens[end].span)
+    }
+
     // 次のトークンが期待しているものだったときには、トークンを1つ読み進めてtrueを返す。それ以外はfalseを返す。
     pub fn consume(&mut self, kind: TokenKind) -> bool {
         if self.get_now_token().kind == kind {
@@ -282,68 +778,145 @@ impl TokenList {
         }
     }
 
-    // 次のトークンがローカル変数の場合、トークンを1つ読み進めてそのローカル変数に対応するトークンとtrueを返す。それ以外はfalseを返す。
-    pub fn consume_ident(&mut self) -> (Option<&Token>, bool) {
-        let now_token = self.get_now_token();
-        match now_token.kind {
-            TokenKind::ID { name } => {
-                self.now += 1;
-                (Some(now_token), true)
-            }
-            _ => (None, false),
+    // 次のトークンが記号signだったときには、トークンを1つ読み進めてtrueを返す。それ以外はfalseを返す。
+    pub fn consume_sign(&mut self, sign: &str) -> bool {
+        if self.get_now_token().kind.is_sign(sign) {
+            self.now += 1;
+            true
+        } else {
+            false
         }
     }
 
-    // 次のトークンが期待しているものだったときには、トークンを1つ読み進める。それ以外はエラーになる。
-    pub fn expect(&mut self, kind: TokenKind, error: Error) {
-        let now_token = self.get_now_token();
-        if now_token.kind == kind {
+    // 次のトークンが識別子の場合、トークンを1つ読み進めてその名前を返す。それ以外はNoneを返す。
+    pub fn consume_ident(&mut self) -> Option<String> {
+        if let TokenKind::ID { name } = self.get_now_token().kind {
             self.now += 1;
+            Some(self.symbols.name(name).to_string())
         } else {
-            error.lexer_error(
-                now_token.row,
-                now_token.col_start,
-                now_token.col_end,
-                &format!("{:?}が期待されています", now_token.kind),
-            )
+            None
         }
     }
 
-    // 次のトークンがIDの場合、トークンを1つ読み進めてその名前を返す。それ以外はエラーになる。
-    pub fn expect_ident(&mut self, error: Error) -> String {
-        let now_token = self.get_now_token();
-        if let TokenKind::ID { name } = now_token.kind {
+    // 次のトークンが文字定数の場合、トークンを1つ読み進めてその値を返す。それ以外はNoneを返す。
+    pub fn consume_char_literal(&mut self) -> Option<isize> {
+        if let TokenKind::CharLit { val } = self.get_now_token().kind {
             self.now += 1;
-            name
+            Some(val)
         } else {
-            error.lexer_error(
-                now_token.row,
-                now_token.col_start,
-                now_token.col_end,
-                &"識別子が期待されています".to_string(),
-            );
-            unreachable!()
+            None
         }
     }
 
-    // 次のトークンが数値の場合、トークンを1つ読み進めてその数値を返す。それ以外はエラーになる。
-    pub fn expect_number(&mut self, error: Error) -> isize {
-        let now_token = self.get_now_token();
-        if let TokenKind::Num { val } = now_token.kind {
+    // 次のトークンが文字列リテラルの場合、トークンを1つ読み進めてその中身を返す。それ以外はNoneを返す。
+    pub fn consume_string(&mut self) -> Option<Vec<u8>> {
+        if let TokenKind::Str { val } = &self.get_now_token().kind {
+            let val = val.clone();
             self.now += 1;
-            val
+            Some(val)
         } else {
-            error.lexer_error(
-                now_token.row,
-                now_token.col_start,
-                now_token.col_end,
-                &"数字が期待されています".to_string(),
+            None
+        }
+    }
+
+    // 今着目しているトークンの位置のエラーを作る
+    pub fn error_at_now(&self, code: Code, msg: Message) -> Diagnostic {
+        Diagnostic::error(code, self.now_span(), msg)
+    }
+
+    // 次のトークンが期待しているものだったときには、トークンを1つ読み進める。それ以外はエラーになる。
+    pub fn expect(&mut self, kind: TokenKind) -> Result<(), Diagnostic> {
+        if self.get_now_token().kind == kind {
+            self.now += 1;
+            Ok(())
+        } else {
+            let expected = match kind.keyword() {
+                Some(word) => format!("'{}'", word),
+                None => format!("{:?}", kind),
+            };
+            Err(self.error_at_now(Code::ExpectedToken, Message::Expected(expected)))
+        }
+    }
+
+    // 次のトークンが記号signだったときには、トークンを1つ読み進める。それ以外はエラーになる。
+    pub fn expect_sign(&mut self, sign: &str) -> Result<(), Diagnostic> {
+        if self.consume_sign(sign) {
+            Ok(())
+        } else {
+            let e = self.error_at_now(
+                Code::ExpectedToken,
+                Message::Expected(format!("'{}'", sign)),
             );
-            unreachable!()
+            if self.now == 0 {
+                return Err(e);
+            }
+            // 直前のトークンの直後に挿入する修正案をつける
+            let at = self.tokens[self.now - 1].span.end_point();
+            Err(e.with_suggestion(at, sign, Message::InsertToken(format!("'{}'", sign))))
+        }
+    }
+
+    // 次のトークンがIDの場合、トークンを1つ読み進めてその名前を返す。それ以外はエラーになる。
+    pub fn expect_ident(&mut self) -> Result<String, Diagnostic> {
+        self.consume_ident()
+            .ok_or_else(|| self.error_at_now(Code::ExpectedIdent, Message::ExpectedIdent))
+    }
+
+    // 次のトークンが数値の場合、トークンを1つ読み進めてその数値と型を返す。それ以外はエラーになる。
+    pub fn expect_number(&mut self) -> Result<(isize, NumType), Diagnostic> {
+        if let TokenKind::Num { val, typ } = self.get_now_token().kind {
+            self.now += 1;
+            Ok((val, typ))
+        } else {
+            Err(self.error_at_now(Code::ExpectedNumber, Message::ExpectedNumber))
+        }
+    }
+
+    // エラーから回復するため、文の終わりまでトークンを読み飛ばす。
+    // ';'か、読み飛ばしている途中に開いた'{'に対応する'}'まで読み進める。対応する'{'がない'}'は読み進めない
+    pub fn skip_to_stmt_end(&mut self) {
+        let mut depth = 0;
+        while !self.at_eof() {
+            if self.consume_sign("{") {
+                depth += 1;
+            } else if depth == 0 && self.consume_sign(";") {
+                return;
+            } else if self.get_now_token().kind.is_sign("}") {
+                if depth == 0 {
+                    return;
+                }
+                self.now += 1;
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            } else {
+                self.now += 1;
+            }
+        }
+    }
+
+    // エラーから回復するため、関数や大域変数の宣言の終わりまでトークンを読み飛ばす。
+    // 関数本体の'{'に対応する'}'か、関数本体が始まる前の';'まで読み進める
+    pub fn skip_to_func_end(&mut self) {
+        let mut depth = 0;
+        while !self.at_eof() {
+            if self.consume_sign("{") {
+                depth += 1;
+            } else if self.consume_sign("}") {
+                if depth <= 1 {
+                    return;
+                }
+                depth -= 1;
+            } else if depth == 0 && self.consume_sign(";") {
+                return;
+            } else {
+                self.now += 1;
+            }
         }
     }
 
     pub fn at_eof(&self) -> bool {
-        self.get_now_token().kind == TokenKind::EOF
+        self.get_now_token().kind == TokenKind::Eof
     }
 }
@@ -0,0 +1,26 @@
+// Diagnosticはラベルや修正案を持つため大きいが、エラーのときにしか作られないのでResultのエラー側が大きくなることは許容する
+#![allow(clippy::result_large_err)]
+
+pub mod ast;
+pub mod codegen;
+pub mod common;
+pub mod compiler;
+pub mod error;
+pub mod lexer;
+pub mod lint;
+pub mod message;
+pub mod parser;
+pub mod preprocess;
+pub mod render;
+pub mod symbol;
+pub mod typ;
+pub mod vfs;
+pub mod warning;
+
+pub use crate::{
+    compiler::{CompileOptions, Compiler, Emit, Output},
+    error::Diagnostics,
+    message::Lang,
+    vfs::{FileSystem, MemoryFileSystem, RealFileSystem},
+    warning::{Warning, WarningOptions},
+};
@@ -0,0 +1,156 @@
+use crate::{
+    ast::{ExprKind, Stmt, StmtKind},
+    common::Span,
+    error::{Code, Diagnostic},
+    message::Message,
+    parser::{eval_const, Func, NameKind, Type},
+};
+
+// 構文解析が終わったプログラムを調べ、警告を返す
+pub fn check_program(func_list: &[Func]) -> Vec<Diagnostic> {
+    let mut warnings = vec![];
+    // すでに暗黙に宣言された関数の名前
+    let mut declared: Vec<&str> = vec![];
+    for func in func_list.iter() {
+        check_unused_variables(func, &mut warnings);
+        check_unreachable(&func.body, &mut warnings);
+        check_implicit_declarations(func, &mut declared, &mut warnings);
+        check_return(func, &mut warnings);
+    }
+    warnings
+}
+
+// 宣言されたが一度も参照されていないローカル変数を探す
+fn check_unused_variables(func: &Func, warnings: &mut Vec<Diagnostic>) {
+    // sizeofの被演算子は定数に置き換えられて本体に残らないので、構文解析のときに記録しておいたものを使う
+    let mut used = func
+        .lvar_list
+        .iter()
+        .map(|lvar| lvar.in_sizeof)
+        .collect::<Vec<bool>>();
+    for stmt in func.body.iter() {
+        stmt.walk_exprs(&mut |expr| {
+            if let Some(id) = expr.local_var() {
+                used[id] = true;
+            }
+        });
+    }
+    // 引数は最初に登録されているので読み飛ばす。列挙定数は変数ではない
+    for (lvar, _) in func
+        .lvar_list
+        .iter()
+        .zip(used)
+        .skip(func.args.len())
+        .filter(|(lvar, used)| !used && lvar.kind == NameKind::Var)
+    {
+        warnings.push(Diagnostic::warning(
+            Code::UnusedVariable,
+            lvar.span,
+            Message::UnusedVariable(lvar.name.clone()),
+        ));
+    }
+}
+
+// 文の列stmtsの中で、returnの後にある文を探す
+fn check_unreachable(stmts: &[Stmt], warnings: &mut Vec<Diagnostic>) {
+    let mut return_span: Option<Span> = None;
+    for stmt in stmts.iter() {
+        if let Some(span) = return_span {
+            // 変数の宣言は実行されるものではないので対象にしない
+            if !matches!(stmt.kind, StmtKind::Decl(_)) {
+                warnings.push(
+                    Diagnostic::warning(Code::UnreachableCode, stmt.span, Message::UnreachableCode)
+                        .with_label(span, Message::ReturnHere),
+                );
+                return;
+            }
+            continue;
+        }
+        check_unreachable_in(stmt, warnings);
+        if matches!(stmt.kind, StmtKind::Return(_)) {
+            return_span = Some(stmt.span);
+        }
+    }
+}
+
+// 文stmtの中にある文の列を調べる
+fn check_unreachable_in(stmt: &Stmt, warnings: &mut Vec<Diagnostic>) {
+    match &stmt.kind {
+        StmtKind::Block(stmts) => check_unreachable(stmts, warnings),
+        StmtKind::If { then, els, .. } => {
+            check_unreachable_in(then, warnings);
+            if let Some(els) = els {
+                check_unreachable_in(els, warnings);
+            }
+        }
+        StmtKind::While { body, .. } | StmtKind::For { body, .. } => {
+            check_unreachable_in(body, warnings)
+        }
+        _ => {}
+    }
+}
+
+// 定義も宣言もされていない関数の呼び出しを探す
+// 呼び出しより前に宣言された関数は、構文解析のときに返り値の型が決まっている
+fn check_implicit_declarations<'a>(
+    func: &'a Func,
+    declared: &mut Vec<&'a str>,
+    warnings: &mut Vec<Diagnostic>,
+) {
+    let mut calls = vec![];
+    for stmt in func.body.iter() {
+        stmt.walk_exprs(&mut |expr| {
+            if let ExprKind::Call { callee, .. } = &expr.kind {
+                if expr.typ == Type::Unknown {
+                    calls.push((callee.as_str(), expr.span));
+                }
+            }
+        });
+    }
+    // 式は外側から順に渡されるので、ソースコード上の順に並べ直す
+    calls.sort_by_key(|(_, span)| (span.file, span.start));
+    for (name, span) in calls {
+        if !declared.contains(&name) {
+            // 最初の呼び出しで暗黙に宣言されたものとして、以降は警告しない
+            declared.push(name);
+            warnings.push(Diagnostic::warning(
+                Code::ImplicitFunctionDeclaration,
+                span,
+                Message::ImplicitFunctionDeclaration(name.to_string()),
+            ));
+        }
+    }
+}
+
+// 関数の終わりまで到達してしまう可能性がないか調べる
+fn check_return(func: &Func, warnings: &mut Vec<Diagnostic>) {
+    // mainは終わりまで到達すると0を返す
+    if func.name == "main" {
+        return;
+    }
+    if !func.body.iter().any(never_reaches_end) {
+        warnings.push(Diagnostic::warning(
+            Code::ReturnType,
+            func.end_span,
+            Message::NoReturn(func.name.clone()),
+        ));
+    }
+}
+
+// 文stmtを実行すると、必ずreturnするか終わらないか
+// breakはまだないので、条件が0でない定数のwhileと条件のないforは終わらない
+fn never_reaches_end(stmt: &Stmt) -> bool {
+    let is_true = |cond| eval_const(cond).is_some_and(|val| val != 0);
+    match &stmt.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::Block(stmts) => stmts.iter().any(never_reaches_end),
+        StmtKind::If {
+            then,
+            els: Some(els),
+            ..
+        } => never_reaches_end(then) && never_reaches_end(els),
+        StmtKind::While { cond, .. } => is_true(cond),
+        StmtKind::For { cond, .. } => cond.as_deref().is_none_or(is_true),
+        _ => false,
+    }
+}
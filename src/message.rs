@@ -0,0 +1,626 @@
+use std::env;
+
+use crate::error::Severity;
+
+// 診断を表示する言語
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum Lang {
+    En,
+    #[default]
+    Ja,
+}
+impl Lang {
+    // "en", "ja"や、"en_US.UTF-8"のようなロケール名から言語を決める
+    pub fn parse(name: &str) -> Option<Self> {
+        if name.starts_with("ja") {
+            Some(Lang::Ja)
+        } else if name.starts_with("en") || name == "C" || name.starts_with("C.") || name == "POSIX"
+        {
+            Some(Lang::En)
+        } else {
+            None
+        }
+    }
+
+    // 環境変数LC_ALL, LC_MESSAGES, LANGの順に見て言語を決める。どれも決まらなければ日本語にする
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|key| env::var(key).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::parse(&value))
+            .unwrap_or_default()
+    }
+
+    pub fn severity(&self, severity: Severity) -> &'static str {
+        match (self, severity) {
+            (Lang::Ja, Severity::Error) => "エラー",
+            (Lang::Ja, Severity::Warning) => "警告",
+            (Lang::Ja, Severity::Note) => "注記",
+            (Lang::En, Severity::Error) => "error",
+            (Lang::En, Severity::Warning) => "warning",
+            (Lang::En, Severity::Note) => "note",
+        }
+    }
+
+    pub fn help(&self) -> &'static str {
+        match self {
+            Lang::Ja => "ヘルプ",
+            Lang::En => "help",
+        }
+    }
+
+    // 見出しと本文の区切り
+    pub fn colon(&self) -> &'static str {
+        match self {
+            Lang::Ja => "：",
+            Lang::En => ": ",
+        }
+    }
+}
+
+// 診断に表示するメッセージ。表示する言語はレンダリングするときに決める
+#[derive(PartialEq, Debug, Clone)]
+pub enum Message {
+    // 入出力
+    ReadFailed {
+        path: String,
+        reason: String,
+    },
+    ReadStdinFailed(String),
+    WriteFailed(String),
+    CreateFailed {
+        path: String,
+        reason: String,
+    },
+
+    // 字句解析・構文解析
+    InvalidToken,
+    UnterminatedComment,
+    CommentStartsHere,
+    InvalidEscape(char),
+    EscapeOutOfRange,
+    UnterminatedChar,
+    UnterminatedString,
+    EmptyCharLiteral,
+    MultiCharLiteral,
+    InvalidDigit(char, u32),
+    NoDigits(u32),
+    InvalidSuffix(String),
+    IntegerTooLarge,
+    UnsupportedNumType(String),
+    Expected(String), // 期待しているトークン
+    ExpectedEither(String, String),
+    InsertToken(String),
+    ExpectedIdent,
+    ExpectedNumber,
+    UnclosedBrace,
+    UnsupportedKeyword(String),
+
+    // 前処理
+    IncludeNotFound(String),
+    ExpectedHeaderName,
+    IncludeTooDeep(usize),
+    UnknownDirective(String),
+    MissingMacroName,
+    UnterminatedConditional(String),
+    UnmatchedConditional(String),
+    DirectiveAfterElse(String),
+    ElseHere,
+    ErrorDirective(String),
+    InvalidConditionalExpression,
+    DivisionByZero,
+    InvalidMacroParams,
+    StringifyNonParam,
+    PasteAtEdge,
+    InvalidPaste(String, String),
+    UnterminatedMacroCall(String),
+    MacroArgCount {
+        name: String,
+        expected: usize,
+        given: usize,
+        variadic: bool, // expected個以上の引数をとるか
+    },
+
+    // 意味解析
+    UndefinedVariable(String),
+    InvalidOperands {
+        lhs: String,
+        rhs: String,
+    },
+    AssignMismatch {
+        from: String,
+        to: String,
+    },
+    NotAssignable,
+    AssignToArray,
+    DerefNonPointer,
+    InvalidAddrOf,
+    UnknownSize,
+    ValueOfType(String),
+    DeclaredAs {
+        name: String,
+        typ: String,
+    },
+    Redefinition(String),
+    ConflictingTypes(String),
+    PreviousDefinition,
+    NotConstant,
+    NoSuchMember {
+        name: String,
+        typ: String,
+    },
+    NotAStruct(String),
+    MemberOfRvalue,
+    StructByValue,
+    IncompleteType(String),
+    DefinedHere,
+    DeclaredHere,
+    WrongKindOfTag(String),
+    NegativeArraySize,
+    NestedArray,
+    InvalidCast {
+        from: String,
+        to: String,
+    },
+    TypeNameInExpr(String),
+    PreviousDeclaration,
+    ArgMismatch {
+        from: String,
+        to: String,
+    },
+    ArgCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    ReturnMismatch {
+        from: String,
+        to: String,
+    },
+    ReturnWithoutValue,
+    EnumOutOfRange(String),
+
+    // 警告
+    UnusedVariable(String),
+    UnreachableCode,
+    ReturnHere,
+    ImplicitFunctionDeclaration(String),
+    NoReturn(String),
+    AssignInCondition,
+    UseEqualityOperator,
+    ParenthesizeAssignment,
+    WarningFlag(String),
+    Werror,
+
+    // コード生成
+    TooManyParams(usize),
+    TooManyArgs(usize),
+
+    // ドライバ
+    Usage,
+    UnknownOption(String),
+    MissingOutputName,
+    MissingOptionValue(String),
+    InvalidEmit(String),
+    InvalidErrorFormat(String),
+    InvalidErrorLimit(String),
+    InvalidLang(String),
+    UnknownWarning(String),
+    NoInputFiles,
+    OutputWithMultipleInputs,
+    CcNotRunnable(String),
+    CcFailed(String),
+    TooManyErrors(usize),
+}
+impl Message {
+    pub fn text(&self, lang: Lang) -> String {
+        match lang {
+            Lang::Ja => self.ja(),
+            Lang::En => self.en(),
+        }
+    }
+
+    fn ja(&self) -> String {
+        match self {
+            Message::ReadFailed { path, reason } => format!("{}を読み込めません: {}", path, reason),
+            Message::ReadStdinFailed(reason) => format!("標準入力を読み込めません: {}", reason),
+            Message::WriteFailed(reason) => format!("出力に書き込めません: {}", reason),
+            Message::CreateFailed { path, reason } => {
+                format!("{}を作成できません: {}", path, reason)
+            }
+            Message::InvalidToken => "トークナイズできません".to_string(),
+            Message::UnterminatedComment => "コメントが閉じられていません".to_string(),
+            Message::CommentStartsHere => "コメントはここから始まっています".to_string(),
+            Message::InvalidEscape(c) => format!("不明なエスケープシーケンスです: \\{}", c),
+            Message::EscapeOutOfRange => "エスケープシーケンスの値が大きすぎます".to_string(),
+            Message::UnterminatedChar => "文字定数が閉じられていません".to_string(),
+            Message::UnterminatedString => "文字列リテラルが閉じられていません".to_string(),
+            Message::EmptyCharLiteral => "文字定数が空です".to_string(),
+            Message::MultiCharLiteral => "文字定数には1文字だけ書くことができます".to_string(),
+            Message::InvalidDigit(digit, radix) => {
+                format!("{}進数の定数に'{}'は使えません", radix, digit)
+            }
+            Message::NoDigits(radix) => format!("{}進数の定数に数字がありません", radix),
+            Message::InvalidSuffix(suffix) => {
+                format!("整数定数の接尾辞'{}'は正しくありません", suffix)
+            }
+            Message::IntegerTooLarge => "整数定数が大きすぎて表せる型がありません".to_string(),
+            Message::UnsupportedNumType(typ) => {
+                format!("'{}'型の整数定数はまだサポートされていません", typ)
+            }
+            Message::Expected(token) => format!("{}が期待されています", token),
+            Message::ExpectedEither(a, b) => format!("{}か{}が期待されています", a, b),
+            Message::InsertToken(token) => format!("{}を挿入してください", token),
+            Message::ExpectedIdent => "識別子が期待されています".to_string(),
+            Message::ExpectedNumber => "数字が期待されています".to_string(),
+            Message::UnclosedBrace => "この'{'が閉じられていません".to_string(),
+            Message::UnsupportedKeyword(word) => format!("'{}'はまだサポートされていません", word),
+            Message::IncludeNotFound(name) => format!("'{}'が見つかりません", name),
+            Message::ExpectedHeaderName => {
+                "#includeの後には\"ファイル名\"か<ファイル名>が必要です".to_string()
+            }
+            Message::IncludeTooDeep(max) => format!("#includeの入れ子が{}段を超えています", max),
+            Message::UnknownDirective(name) => format!("'#{}'は不明な前処理指令です", name),
+            Message::MissingMacroName => "マクロ名がありません".to_string(),
+            Message::UnterminatedConditional(name) => format!("'#{}'が閉じられていません", name),
+            Message::UnmatchedConditional(name) => {
+                format!("'#{}'に対応する'#if'がありません", name)
+            }
+            Message::DirectiveAfterElse(name) => format!("'#else'の後に'#{}'があります", name),
+            Message::ElseHere => "'#else'はここにあります".to_string(),
+            Message::ErrorDirective(text) => format!("#error {}", text),
+            Message::InvalidConditionalExpression => "#ifの条件式が正しくありません".to_string(),
+            Message::DivisionByZero => "#ifの条件式で0による除算をしています".to_string(),
+            Message::InvalidMacroParams => "マクロの仮引数リストが正しくありません".to_string(),
+            Message::StringifyNonParam => "'#'の後にはマクロの仮引数が必要です".to_string(),
+            Message::PasteAtEdge => "'##'はマクロの置き換え後の両端には置けません".to_string(),
+            Message::InvalidPaste(lhs, rhs) => {
+                format!("'{}'と'{}'をつなげても正しいトークンになりません", lhs, rhs)
+            }
+            Message::UnterminatedMacroCall(name) => {
+                format!("マクロ'{}'の引数リストが閉じられていません", name)
+            }
+            Message::MacroArgCount {
+                name,
+                expected,
+                given,
+                variadic,
+            } => format!(
+                "マクロ'{}'には{}個{}の引数が必要ですが、{}個渡されています",
+                name,
+                expected,
+                if *variadic { "以上" } else { "" },
+                given
+            ),
+            Message::UndefinedVariable(name) => format!("変数'{}'は定義されていません", name),
+            Message::InvalidOperands { lhs, rhs } => {
+                format!("'{}'型と'{}'型の間の演算はできません", lhs, rhs)
+            }
+            Message::AssignMismatch { from, to } => {
+                format!("'{}'型の値を'{}'型に代入することはできません", from, to)
+            }
+            Message::NotAssignable => "左辺値ではないものに代入しようとしています".to_string(),
+            Message::AssignToArray => "配列に代入することはできません".to_string(),
+            Message::DerefNonPointer => {
+                "ポインタ型ではない値を参照外ししようとしています".to_string()
+            }
+            Message::InvalidAddrOf => "アドレスを取ることができません".to_string(),
+            Message::UnknownSize => "大きさのわからない型にsizeofを使っています".to_string(),
+            Message::ValueOfType(typ) => format!("'{}'型の値", typ),
+            Message::DeclaredAs { name, typ } => {
+                format!("'{}'は'{}'型として宣言されています", name, typ)
+            }
+            Message::Redefinition(name) => format!("'{}'が再定義されています", name),
+            Message::ConflictingTypes(name) => {
+                format!("'{}'が前の宣言と異なる型で宣言されています", name)
+            }
+            Message::PreviousDefinition => "前の定義はここです".to_string(),
+            Message::NotConstant => "整数定数式ではありません".to_string(),
+            Message::NoSuchMember { name, typ } => {
+                format!("'{}'にメンバ'{}'はありません", typ, name)
+            }
+            Message::NotAStruct(op) => format!("構造体ではない値に'{}'を使っています", op),
+            Message::MemberOfRvalue => {
+                "左辺値ではない構造体のメンバの参照はまだサポートしていません".to_string()
+            }
+            Message::StructByValue => "構造体の値渡しはまだサポートしていません".to_string(),
+            Message::IncompleteType(typ) => format!("不完全な型'{}'は使えません", typ),
+            Message::DefinedHere => "ここで定義されています".to_string(),
+            Message::DeclaredHere => "ここで宣言されています".to_string(),
+            Message::WrongKindOfTag(typ) => {
+                format!("タグが{}として宣言されていて、種類が異なります", typ)
+            }
+            Message::NegativeArraySize => "配列の大きさが負です".to_string(),
+            Message::NestedArray => {
+                "配列へのポインタと配列の配列はまだサポートしていません".to_string()
+            }
+            Message::InvalidCast { from, to } => {
+                format!("'{}'型の値を'{}'型に変換することはできません", from, to)
+            }
+            Message::TypeNameInExpr(name) => {
+                format!("'{}'は型名なので、式の中では使えません", name)
+            }
+            Message::PreviousDeclaration => "前の宣言はここです".to_string(),
+            Message::ArgMismatch { from, to } => {
+                format!(
+                    "'{}'型の値を'{}'型の引数として渡すことはできません",
+                    from, to
+                )
+            }
+            Message::ReturnMismatch { from, to } => {
+                format!(
+                    "'{}'型を返す関数から'{}'型の値を返すことはできません",
+                    to, from
+                )
+            }
+            Message::ReturnWithoutValue => "値を返す関数では、returnに値が必要です".to_string(),
+            Message::EnumOutOfRange(name) => {
+                format!("列挙定数'{}'の値が'int'型で表せる範囲を超えています", name)
+            }
+            Message::ArgCount {
+                name,
+                expected,
+                found,
+            } => format!(
+                "関数'{}'の引数は{}個ですが、{}個の引数で呼び出しています",
+                name, expected, found
+            ),
+            Message::UnusedVariable(name) => format!("変数'{}'は使われていません", name),
+            Message::UnreachableCode => "この文は実行されません".to_string(),
+            Message::ReturnHere => "このreturnより後の文は実行されません".to_string(),
+            Message::ImplicitFunctionDeclaration(name) => {
+                format!("関数'{}'は宣言されていません", name)
+            }
+            Message::NoReturn(name) => {
+                format!(
+                    "関数'{}'が値を返さずに終わりに到達する可能性があります",
+                    name
+                )
+            }
+            Message::AssignInCondition => "条件式で代入が使われています".to_string(),
+            Message::UseEqualityOperator => {
+                "比較するつもりであれば'=='を使ってください".to_string()
+            }
+            Message::ParenthesizeAssignment => {
+                "代入が意図したものであれば、括弧で囲むと警告は出なくなります".to_string()
+            }
+            Message::WarningFlag(name) => format!("-W{}により有効になっています", name),
+            Message::Werror => "-Werrorによりエラーとして扱われます".to_string(),
+            Message::TooManyParams(max) => format!("引数は{}個までしか定義できません", max),
+            Message::TooManyArgs(max) => format!("引数は{}個までしか渡せません", max),
+            Message::Usage => format!("使い方: {}", USAGE),
+            Message::UnknownOption(arg) => format!("不明なオプションです: {}", arg),
+            Message::MissingOutputName => "-oの後に出力ファイル名が必要です".to_string(),
+            Message::MissingOptionValue(option) => format!("{}の後に値が必要です", option),
+            Message::InvalidEmit(value) => format!("--emitに不明な値が指定されました: {}", value),
+            Message::InvalidErrorFormat(value) => {
+                format!("--error-formatに不明な値が指定されました: {}", value)
+            }
+            Message::InvalidErrorLimit(value) => {
+                format!("-ferror-limitには0以上の整数を指定してください: {}", value)
+            }
+            Message::InvalidLang(value) => format!("--langに不明な値が指定されました: {}", value),
+            Message::UnknownWarning(name) => format!("不明な警告の名前です: -W{}", name),
+            Message::NoInputFiles => {
+                format!("入力ファイルが指定されていません\n{}", Message::Usage.ja())
+            }
+            Message::OutputWithMultipleInputs => {
+                "複数の入力ファイルがあるとき、-S, -cと-oは同時に指定できません".to_string()
+            }
+            Message::CcNotRunnable(reason) => format!("ccを実行できません: {}", reason),
+            Message::CcFailed(status) => format!("ccが失敗しました ({})", status),
+            Message::TooManyErrors(limit) => format!(
+                "エラーが多すぎるため、報告を中断しました (-ferror-limit={})",
+                limit
+            ),
+        }
+    }
+
+    fn en(&self) -> String {
+        match self {
+            Message::ReadFailed { path, reason } => format!("cannot read {}: {}", path, reason),
+            Message::ReadStdinFailed(reason) => format!("cannot read stdin: {}", reason),
+            Message::WriteFailed(reason) => format!("cannot write output: {}", reason),
+            Message::CreateFailed { path, reason } => {
+                format!("cannot create {}: {}", path, reason)
+            }
+            Message::InvalidToken => "invalid token".to_string(),
+            Message::UnterminatedComment => "unterminated comment".to_string(),
+            Message::CommentStartsHere => "comment starts here".to_string(),
+            Message::InvalidEscape(c) => format!("unknown escape sequence: \\{}", c),
+            Message::EscapeOutOfRange => "escape sequence out of range".to_string(),
+            Message::UnterminatedChar => "unterminated character constant".to_string(),
+            Message::UnterminatedString => "unterminated string literal".to_string(),
+            Message::EmptyCharLiteral => "empty character constant".to_string(),
+            Message::MultiCharLiteral => {
+                "character constant must contain exactly one character".to_string()
+            }
+            Message::InvalidDigit(digit, radix) => {
+                format!("invalid digit '{}' in base {} constant", digit, radix)
+            }
+            Message::NoDigits(radix) => format!("base {} constant has no digits", radix),
+            Message::InvalidSuffix(suffix) => {
+                format!("invalid suffix '{}' on integer constant", suffix)
+            }
+            Message::IntegerTooLarge => "integer constant is too large for any type".to_string(),
+            Message::UnsupportedNumType(typ) => {
+                format!("integer constants of type '{}' are not supported yet", typ)
+            }
+            Message::Expected(token) => format!("expected {}", token),
+            Message::ExpectedEither(a, b) => format!("expected {} or {}", a, b),
+            Message::InsertToken(token) => format!("insert {}", token),
+            Message::ExpectedIdent => "expected identifier".to_string(),
+            Message::ExpectedNumber => "expected number".to_string(),
+            Message::UnclosedBrace => "this '{' is not closed".to_string(),
+            Message::UnsupportedKeyword(word) => format!("'{}' is not supported yet", word),
+            Message::IncludeNotFound(name) => format!("'{}' file not found", name),
+            Message::ExpectedHeaderName => "expected \"FILENAME\" or <FILENAME>".to_string(),
+            Message::IncludeTooDeep(max) => format!("#include nested more than {} levels", max),
+            Message::UnknownDirective(name) => {
+                format!("invalid preprocessing directive '#{}'", name)
+            }
+            Message::MissingMacroName => "macro name missing".to_string(),
+            Message::UnterminatedConditional(name) => format!("unterminated '#{}'", name),
+            Message::UnmatchedConditional(name) => format!("'#{}' without '#if'", name),
+            Message::DirectiveAfterElse(name) => format!("'#{}' after '#else'", name),
+            Message::ElseHere => "'#else' is here".to_string(),
+            Message::ErrorDirective(text) => format!("#error {}", text),
+            Message::InvalidConditionalExpression => {
+                "invalid expression in preprocessor conditional".to_string()
+            }
+            Message::DivisionByZero => "division by zero in preprocessor conditional".to_string(),
+            Message::InvalidMacroParams => "invalid macro parameter list".to_string(),
+            Message::StringifyNonParam => "'#' is not followed by a macro parameter".to_string(),
+            Message::PasteAtEdge => {
+                "'##' cannot appear at either end of a macro expansion".to_string()
+            }
+            Message::InvalidPaste(lhs, rhs) => format!(
+                "pasting '{}' and '{}' does not give a valid preprocessing token",
+                lhs, rhs
+            ),
+            Message::UnterminatedMacroCall(name) => {
+                format!("unterminated argument list invoking macro '{}'", name)
+            }
+            Message::MacroArgCount {
+                name,
+                expected,
+                given,
+                variadic,
+            } => format!(
+                "macro '{}' requires {}{} arguments, but {} given",
+                name,
+                if *variadic { "at least " } else { "" },
+                expected,
+                given
+            ),
+            Message::UndefinedVariable(name) => format!("variable '{}' is not defined", name),
+            Message::InvalidOperands { lhs, rhs } => {
+                format!("invalid operands of types '{}' and '{}'", lhs, rhs)
+            }
+            Message::AssignMismatch { from, to } => {
+                format!("cannot assign a value of type '{}' to type '{}'", from, to)
+            }
+            Message::NotAssignable => "cannot assign to a non-lvalue".to_string(),
+            Message::AssignToArray => "cannot assign to an array".to_string(),
+            Message::DerefNonPointer => "cannot dereference a non-pointer value".to_string(),
+            Message::InvalidAddrOf => "cannot take the address of this value".to_string(),
+            Message::UnknownSize => "sizeof applied to a type of unknown size".to_string(),
+            Message::ValueOfType(typ) => format!("value of type '{}'", typ),
+            Message::DeclaredAs { name, typ } => {
+                format!("'{}' is declared here as type '{}'", name, typ)
+            }
+            Message::Redefinition(name) => format!("redefinition of '{}'", name),
+            Message::ConflictingTypes(name) => format!("conflicting types for '{}'", name),
+            Message::PreviousDefinition => "previous definition is here".to_string(),
+            Message::NotConstant => "expression is not an integer constant expression".to_string(),
+            Message::NoSuchMember { name, typ } => {
+                format!("'{}' has no member named '{}'", typ, name)
+            }
+            Message::NotAStruct(op) => format!("'{}' used on a value that is not a struct", op),
+            Message::MemberOfRvalue => {
+                "member access on a struct that is not an lvalue is not supported yet".to_string()
+            }
+            Message::StructByValue => "passing structs by value is not supported yet".to_string(),
+            Message::IncompleteType(typ) => {
+                format!("incomplete type '{}' cannot be used here", typ)
+            }
+            Message::DefinedHere => "defined here".to_string(),
+            Message::DeclaredHere => "declared here".to_string(),
+            Message::WrongKindOfTag(typ) => {
+                format!(
+                    "use of tag that does not match previous declaration {}",
+                    typ
+                )
+            }
+            Message::NegativeArraySize => "array has negative size".to_string(),
+            Message::NestedArray => {
+                "pointers to arrays and arrays of arrays are not supported yet".to_string()
+            }
+            Message::InvalidCast { from, to } => {
+                format!("cannot cast a value of type '{}' to type '{}'", from, to)
+            }
+            Message::TypeNameInExpr(name) => {
+                format!("unexpected type name '{}' in expression", name)
+            }
+            Message::PreviousDeclaration => "previous declaration is here".to_string(),
+            Message::ArgMismatch { from, to } => {
+                format!(
+                    "cannot pass a value of type '{}' as an argument of type '{}'",
+                    from, to
+                )
+            }
+            Message::ReturnMismatch { from, to } => {
+                format!(
+                    "cannot return a value of type '{}' from a function returning '{}'",
+                    from, to
+                )
+            }
+            Message::ReturnWithoutValue => "non-void function should return a value".to_string(),
+            Message::EnumOutOfRange(name) => {
+                format!(
+                    "value of enumerator '{}' is outside the range of 'int'",
+                    name
+                )
+            }
+            Message::ArgCount {
+                name,
+                expected,
+                found,
+            } => format!(
+                "function '{}' takes {} arguments but {} were given",
+                name, expected, found
+            ),
+            Message::UnusedVariable(name) => format!("unused variable '{}'", name),
+            Message::UnreachableCode => "unreachable statement".to_string(),
+            Message::ReturnHere => "any code following this return is unreachable".to_string(),
+            Message::ImplicitFunctionDeclaration(name) => {
+                format!("implicit declaration of function '{}'", name)
+            }
+            Message::NoReturn(name) => {
+                format!(
+                    "control may reach the end of function '{}' without returning a value",
+                    name
+                )
+            }
+            Message::AssignInCondition => "assignment used as a condition".to_string(),
+            Message::UseEqualityOperator => "use '==' to compare values".to_string(),
+            Message::ParenthesizeAssignment => {
+                "place parentheses around the assignment to silence this warning".to_string()
+            }
+            Message::WarningFlag(name) => format!("enabled by -W{}", name),
+            Message::Werror => "treated as an error because of -Werror".to_string(),
+            Message::TooManyParams(max) => {
+                format!("functions can have at most {} parameters", max)
+            }
+            Message::TooManyArgs(max) => format!("at most {} arguments can be passed", max),
+            Message::Usage => format!("usage: {}", USAGE),
+            Message::UnknownOption(arg) => format!("unknown option: {}", arg),
+            Message::MissingOutputName => "missing file name after -o".to_string(),
+            Message::MissingOptionValue(option) => format!("missing argument after {}", option),
+            Message::InvalidEmit(value) => format!("invalid value for --emit: {}", value),
+            Message::InvalidErrorFormat(value) => {
+                format!("invalid value for --error-format: {}", value)
+            }
+            Message::InvalidErrorLimit(value) => {
+                format!("-ferror-limit expects a non-negative integer: {}", value)
+            }
+            Message::InvalidLang(value) => format!("invalid value for --lang: {}", value),
+            Message::UnknownWarning(name) => format!("unknown warning option: -W{}", name),
+            Message::NoInputFiles => format!("no input files\n{}", Message::Usage.en()),
+            Message::OutputWithMultipleInputs => {
+                "cannot specify -o with -S or -c when there are multiple input files".to_string()
+            }
+            Message::CcNotRunnable(reason) => format!("cannot run cc: {}", reason),
+            Message::CcFailed(status) => format!("cc failed ({})", status),
+            Message::TooManyErrors(limit) => {
+                format!(
+                    "too many errors emitted, stopping now (-ferror-limit={})",
+                    limit
+                )
+            }
+        }
+    }
+}
+
+const USAGE: &str = "dcc [-S | -c] [-o <file>] [--emit=tokens|ast|asm] [-ferror-limit=<n>] [--error-format=human|json] [--lang=en|ja] [-I<dir>] [-D<name>[=<value>]] [-U<name>] [-Wall] [-W[no-]<name>] [-Werror] <file>...";
@@ -1,769 +1,1909 @@
 use crate::{
-    error,
-    lexer::{TokenKind, TokenList},
-    typ::{binary_calc_type, get_size, match_assign_type},
+    ast::{BinOp, Expr, ExprKind, LValue, LValueKind, Stmt, StmtKind},
+    common::Span,
+    error::{Code, Diagnostic, Severity},
+    lexer::{NumType, TokenKind, TokenList},
+    message::Message,
+    typ::{
+        align_to, binary_calc_type, get_align, get_size, is_array, is_assignable,
+        match_assign_type, pointee, pointer_to, promote, struct_layout, type_to_string,
+        union_layout,
+    },
 };
 
-// 変数の型
+// 配列の大きさと要素の型
 #[derive(PartialEq, Debug, Clone, Copy)]
-pub struct IntArr {
-    size: usize,  // 配列の大きさ
-    level: usize, // ポインタの段数をusizeで持つ。
+pub struct Array {
+    pub size: usize,  // 配列の大きさ
+    pub level: usize, // 要素のポインタの段数をusizeで持つ。例えばint *a[3]のlevelは1
 }
+// 変数の型
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Type {
-    Int(usize), // ポインタの段数をusizeで持つ。例えばInt(2)はint **型を表す
-    IntArr(IntArr),
+    Int(usize),  // ポインタの段数をusizeで持つ。例えばInt(2)はint **型を表す
+    Char(usize), // ポインタの段数をusizeで持つ。例えばChar(1)はchar *型を表す
+    // 構造体。idはStructListでのindexで、levelはポインタの段数
+    Struct { id: usize, level: usize },
+    IntArr(Array),
+    CharArr(Array),
+    StructArr { id: usize, arr: Array },
     Unknown,
-    Stmt, // 文には型がない。構文の維持のために使われるノードが持つ
+}
+
+// 構造体のメンバ
+#[derive(Debug)]
+pub struct Member {
+    pub name: String,
+    pub typ: Type,
+    pub offset: usize, // 構造体の先頭からのオフセット
+    pub span: Span,    // 宣言された位置
+}
+// 構造体・共用体・列挙型のどれであるか
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TagKind {
+    Struct,
+    Union,
+    Enum,
+}
+impl TagKind {
+    // 型を表すキーワード
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            TagKind::Struct => "struct",
+            TagKind::Union => "union",
+            TagKind::Enum => "enum",
+        }
+    }
+}
+
+// 構造体の定義。共用体と列挙型も、タグを共有するのでここで管理する
+#[derive(Debug)]
+pub struct StructDef {
+    pub kind: TagKind,
+    pub name: Option<String>, // タグ。無名の構造体ではNone
+    pub members: Vec<Member>,
+    pub size: usize,
+    pub align: usize,
+    pub defined: bool, // メンバが定義されているか。宣言されただけの構造体は不完全型
+    pub span: Span,    // 定義(なければ最初の宣言)の位置
+}
+// プログラム中の全ての構造体の定義と、今見えている構造体のタグ
+#[derive(Debug, Default)]
+pub struct StructList {
+    defs: Vec<StructDef>,
+    tags: Vec<(String, usize)>, // (タグ, 構造体のindex)。後に宣言されたものほど後ろにある
+    scope: usize,               // tagsのうち、今のスコープで宣言されたものの先頭
+}
+impl StructList {
+    // index番目の構造体を返す
+    pub fn get(&self, id: usize) -> &StructDef {
+        &self.defs[id]
+    }
+
+    // タグで構造体を検索し、そのindexを返す。in_scopeが真であれば今のスコープで宣言されたものだけを探す
+    fn find_tag(&self, name: &str, in_scope: bool) -> Option<usize> {
+        let start = if in_scope { self.scope } else { 0 };
+        self.tags[start..]
+            .iter()
+            .rfind(|(tag, _)| tag == name)
+            .map(|&(_, id)| id)
+    }
+
+    // メンバが未定義の構造体を追加し、そのindexを返す。nameがあれば今のスコープにタグとして登録する
+    fn add_new_struct(&mut self, kind: TagKind, name: Option<String>, span: Span) -> usize {
+        let id = self.defs.len();
+        if let Some(name) = &name {
+            self.tags.push((name.clone(), id));
+        }
+        self.defs.push(StructDef {
+            kind,
+            name,
+            members: vec![],
+            size: 0,
+            align: 1,
+            defined: false,
+            span,
+        });
+        id
+    }
+
+    // 新しいスコープに入る。返り値をleave_scopeに渡すと元のスコープに戻る
+    fn enter_scope(&mut self) -> usize {
+        std::mem::replace(&mut self.scope, self.tags.len())
+    }
+
+    // スコープを抜け、そのスコープで宣言されたタグを見えなくする
+    fn leave_scope(&mut self, outer: usize) {
+        self.tags.truncate(self.scope);
+        self.scope = outer;
+    }
+
+    // 構造体の型を表す文字列を返す
+    pub fn type_name(&self, id: usize) -> String {
+        let def = &self.defs[id];
+        match &def.name {
+            Some(name) => format!("{} {}", def.kind.keyword(), name),
+            None => format!("{} <anonymous>", def.kind.keyword()),
+        }
+    }
+}
+
+// 名前が表すもの。列挙定数と型名は、変数と同じ名前空間にあるが領域を持たない
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum NameKind {
+    Var,
+    EnumConst(isize), // 列挙定数とその値
+    Typedef,          // typedefで宣言された型名。表す型はtypに持つ
 }
 
 // ローカル変数の型
 #[derive(Debug)]
 pub struct LVar {
-    pub name: String,  // 名前
-    pub offset: usize, // RBPからのオフセット
-    pub typ: Type,     // 型
+    pub name: String,    // 名前
+    pub offset: usize,   // RBPからのオフセット
+    pub typ: Type,       // 型
+    pub span: Span,      // 宣言された位置
+    pub kind: NameKind,  // 変数でなければスタック上に領域を持たない
+    pub in_sizeof: bool, // sizeofの被演算子の中で使われたか
 }
 #[derive(Debug)]
 pub struct LVarList {
-    lvars: Vec<LVar>,
-    offset: usize,
+    lvars: Vec<LVar>,    // 関数の中で宣言された全ての名前。スコープを抜けても残る
+    visible: Vec<usize>, // 今見えている名前のlvarsでのindex。内側のスコープで宣言されたものほど後ろにある
+    scope: usize,        // visibleのうち、今のスコープで宣言されたものの先頭
+    offset: usize,       // 今のスコープまでで使っているスタック領域の大きさ
+    stack_size: usize,   // 関数全体で必要なスタック領域の大きさ
 }
 impl LVarList {
     fn new() -> Self {
         LVarList {
             lvars: vec![],
+            visible: vec![],
+            scope: 0,
             offset: 0,
+            stack_size: 0,
         }
     }
 
-    pub fn offset(&self) -> usize {
-        self.offset
+    // 関数全体で必要なスタック領域の大きさを返す
+    pub fn stack_size(&self) -> usize {
+        self.stack_size
     }
 
-    // 変数を名前で検索する。見つからなかった場合はfalseを返す
-    pub fn find_lvar(&self, name: &String) -> (Option<&LVar>, bool) {
-        for lvar in self.lvars.iter() {
-            if name.eq(&lvar.name) {
-                return (Some(lvar), true);
-            }
-        }
-        (None, false)
+    // 今見えている名前を検索し、そのindexを返す。見つからなかった場合はNoneを返す
+    // 内側のスコープで宣言された名前は、外側の同じ名前を隠す
+    pub fn find_lvar(&self, name: &str) -> Option<usize> {
+        self.visible
+            .iter()
+            .rev()
+            .find(|&&id| self.lvars[id].name == name)
+            .copied()
+    }
+
+    // 今のスコープで宣言された名前を検索し、そのindexを返す
+    fn find_in_scope(&self, name: &str) -> Option<usize> {
+        self.visible[self.scope..]
+            .iter()
+            .find(|&&id| self.lvars[id].name == name)
+            .copied()
+    }
+
+    // 新しいスコープに入る。返り値をleave_scopeに渡すと元のスコープに戻る
+    fn enter_scope(&mut self) -> (usize, usize) {
+        let scope = std::mem::replace(&mut self.scope, self.visible.len());
+        (scope, self.offset)
+    }
+
+    // スコープを抜け、そのスコープで宣言された名前を見えなくする
+    // スコープの中の変数の領域は、後に続く兄弟のスコープで使い回す
+    fn leave_scope(&mut self, (scope, offset): (usize, usize)) {
+        self.visible.truncate(self.scope);
+        self.scope = scope;
+        self.offset = offset;
+    }
+
+    // index番目の変数を返す
+    pub fn get(&self, id: usize) -> &LVar {
+        &self.lvars[id]
+    }
+
+    // 登録された順に変数を返す
+    pub fn iter(&self) -> impl Iterator<Item = &LVar> {
+        self.lvars.iter()
     }
 
-    // 新しい変数を追加する
-    fn add_new_lvar(&mut self, name: &String, typ: Type) {
-        let new_offset = self.offset + get_size(typ);
+    // 新しい変数を追加し、そのindexを返す
+    // 変数はRBPから下に向かって並べ、その先頭のアドレスが型のalignmentの倍数になるようにする
+    fn add_new_lvar(&mut self, name: &str, typ: Type, span: Span, structs: &StructList) -> usize {
+        let new_offset = align_to(
+            self.offset + get_size(typ, structs),
+            get_align(typ, structs),
+        );
         self.offset = new_offset;
+        self.stack_size = self.stack_size.max(new_offset);
         self.lvars.push(LVar {
-            name: name.clone(),
+            name: name.to_string(),
             offset: new_offset,
             typ,
+            span,
+            kind: NameKind::Var,
+            in_sizeof: false,
         });
+        self.visible.push(self.lvars.len() - 1);
+        self.lvars.len() - 1
+    }
+
+    // 列挙定数や型名など、領域を持たない名前を追加する
+    fn add_name(&mut self, name: &str, typ: Type, kind: NameKind, span: Span) {
+        self.lvars.push(LVar {
+            name: name.to_string(),
+            offset: 0,
+            typ,
+            span,
+            kind,
+            in_sizeof: false,
+        });
+        self.visible.push(self.lvars.len() - 1);
     }
 }
 
-// ノードの種類
-#[derive(PartialEq, Debug)]
-pub enum NodeKind {
-    Add,    // +
-    Sub,    // -
-    Mul,    // *
-    Div,    // /
-    Eq,     // ==
-    Ne,     // !=
-    Lt,     // <
-    Le,     // <=
-    Assign, // =
-    Lvar,   // local int
-    Num,    // int
-    Return, // return
-    If,     // if <- IfFlagとIfStmtをそれぞれlhs, rhsに持つ
-    IfFlag,
-    IfStmt,
-    While, // while <- flagとstmtをそれぞれlhs, rhsに持つ
-    For,   // for <- ForFstとForSndをそれぞれlhs, rhsに持つ
-    ForFst,
-    ForSnd,
-    Block, // { ... } <- lhsにはstmtからなるノードを、rhsには連続的にBlockノードを持つ
-    App,   // 関数適用 <- rhsには連続的にArgノードを持つ
-    Arg,   // lhsにexprからなるノードを、rhsに連続的にArgノードを持つ
-    Addr,  // 単項&
-    Deref, // 単項*
-    Int,   // ローカル変数定義
-}
-// ノード型
-#[derive(Debug)]
-pub struct Node {
-    pub kind: NodeKind,
-    pub input_idx: usize,      // 入力のうち、このノードが始まる場所のindex
-    pub lhs: Option<usize>,    // 左辺のノードのindex
-    pub rhs: Option<usize>,    // 左辺のノードのindex
-    pub val: Option<isize>,    // kindがNUMの時のみ利用
-    pub offset: Option<usize>, // kindがLVARの時のみ利用。ローカル変数のベースポインタからのオフセットを表す。
-    pub name: Option<String>,  // kindがLVAR, APPの時のみ利用。ローカル変数, 関数の名前を表す。
-    pub typ: Type,
+// 関数の本体を構文解析する間の状態
+struct FuncParser<'g> {
+    lvar_list: LVarList,
+    strings: Vec<Vec<u8>>,       // 文字列リテラルの中身
+    globals: &'g mut Vec<GVar>,  // ここまでに定義された大域変数と、トップレベルの列挙定数
+    structs: &'g mut StructList, // 構造体の定義。関数の中で定義された構造体も追加される
+    sigs: &'g mut Vec<FuncSig>,  // ここまでに宣言された関数
+    top_level: bool,             // 関数の外を構文解析しているか。列挙定数を追加する先が変わる
+    ret: Option<(Type, Span)>,   // 構文解析している関数の返り値の型と、関数名の位置
 }
-#[derive(Debug)]
-pub struct NodeList {
-    pub roots: Vec<usize>, // プログラムの中の各文のrootノードのindex
-    pub nodes: Vec<Node>,
-    pub lvar_list: LVarList,
-}
-impl NodeList {
-    pub fn new(args: &Vec<(String, Type)>) -> Self {
-        // 関数定義の引数として与えられた変数は、そのような変数が最初から存在するものとしてコンパイルしておく
-        let mut lvar_list = LVarList::new();
-        for (arg_name, arg_type) in args.iter() {
-            lvar_list.add_new_lvar(arg_name, *arg_type);
-        }
-
-        NodeList {
-            roots: vec![],
-            nodes: vec![],
-            lvar_list,
+impl<'g> FuncParser<'g> {
+    fn new(
+        globals: &'g mut Vec<GVar>,
+        structs: &'g mut StructList,
+        sigs: &'g mut Vec<FuncSig>,
+        top_level: bool,
+    ) -> Self {
+        FuncParser {
+            lvar_list: LVarList::new(),
+            strings: vec![],
+            globals,
+            structs,
+            sigs,
+            top_level,
+            ret: None,
         }
     }
 
-    // 新しいノードを作成し、そのindexを返す
-    fn append_new_node(
+    // 関数の型sigを登録する。同じ名前の関数が宣言済みであれば、型が同じで定義が高々1つであるかを調べてerrorsに積む
+    fn declare_func(&mut self, sig: FuncSig, errors: &mut Vec<Diagnostic>) {
+        if let Some(gvar) = self.globals.iter().find(|gvar| gvar.name == sig.name) {
+            errors.push(
+                Diagnostic::error(
+                    Code::Redefinition,
+                    sig.span,
+                    Message::Redefinition(sig.name),
+                )
+                .with_label(gvar.span, Message::PreviousDefinition),
+            );
+            return;
+        }
+        let Some(prev) = self.sigs.iter_mut().find(|prev| prev.name == sig.name) else {
+            self.sigs.push(sig);
+            return;
+        };
+        // 引数を指定しない"()"の宣言は、どの引数の型とも合う
+        let params_match = match (&prev.params, &sig.params) {
+            (Some(prev_params), Some(params)) => prev_params == params,
+            _ => true,
+        };
+        let e = if prev.ret != sig.ret || !params_match {
+            Diagnostic::error(
+                Code::Redefinition,
+                sig.span,
+                Message::ConflictingTypes(sig.name),
+            )
+            .with_label(prev.span, Message::PreviousDeclaration)
+        } else if prev.defined && sig.defined {
+            Diagnostic::error(
+                Code::Redefinition,
+                sig.span,
+                Message::Redefinition(sig.name),
+            )
+            .with_label(prev.span, Message::PreviousDefinition)
+        } else {
+            prev.defined |= sig.defined;
+            prev.params = prev.params.take().or(sig.params);
+            return;
+        };
+        errors.push(e);
+    }
+
+    // 関数sigの呼び出しの引数argsを、宣言された引数の型と照らし合わせる。spanは呼び出しの式全体の位置
+    // 引数の型は代入と同じ規則で変換できなければならない。引数が指定されていない関数では何も調べない
+    fn check_args(&self, sig: &FuncSig, args: &[Expr], span: Span) -> Result<(), Diagnostic> {
+        let Some(params) = &sig.params else {
+            return Ok(());
+        };
+        if args.len() != params.len() {
+            return Err(Diagnostic::error(
+                Code::ArgCountMismatch,
+                span,
+                Message::ArgCount {
+                    name: sig.name.clone(),
+                    expected: params.len(),
+                    found: args.len(),
+                },
+            )
+            .with_label(sig.span, Message::DeclaredHere));
+        }
+        for (arg, param) in args.iter().zip(params.iter()) {
+            if !is_assignable(*param, arg) {
+                return Err(Diagnostic::error(
+                    Code::AssignMismatch,
+                    arg.span,
+                    Message::ArgMismatch {
+                        from: type_to_string(arg.typ, self.structs),
+                        to: type_to_string(*param, self.structs),
+                    },
+                )
+                .with_label(sig.span, Message::DeclaredHere));
+            }
+        }
+        Ok(())
+    }
+
+    // "("の後ろから関数の引数を構文解析する。本体のない宣言では引数の名前を省略できるので、名前はOptionで返す
+    fn params(
         &mut self,
-        kind: NodeKind,
-        input_idx: usize,
-        lhs: Option<usize>,
-        rhs: Option<usize>,
-        name: Option<String>,
-        typ: Type,
-    ) -> usize {
-        let new_idx = self.nodes.len();
-        self.nodes.push(Node {
-            kind,
-            input_idx,
-            lhs: if let Some(_) = lhs { lhs } else { None },
-            rhs: if let Some(_) = rhs { rhs } else { None },
-            name: if let Some(_) = name { name } else { None },
-            val: None,
-            offset: None,
-            typ,
-        });
-        new_idx
+        token_list: &mut TokenList,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<(Option<String>, Type, Span)>, Diagnostic> {
+        let mut args: Vec<(Option<String>, Type, Span)> = vec![];
+        if token_list.consume_sign(")") {
+            // 引数が何もない場合はなにもしない
+        } else {
+            // 引数が1個以上ある
+            loop {
+                let arg_type = self.ty(token_list, errors)?;
+                let arg_span = token_list.now_span();
+                let arg_name = token_list.consume_ident();
+                if let Type::Struct { level: 0, .. } = arg_type {
+                    return Err(Diagnostic::error(
+                        Code::Unsupported,
+                        arg_span,
+                        Message::StructByValue,
+                    ));
+                }
+                if let Some(arg_name) = &arg_name {
+                    if let Some((_, _, prev_span)) = args
+                        .iter()
+                        .find(|(name, _, _)| name.as_ref() == Some(arg_name))
+                    {
+                        return Err(Diagnostic::error(
+                            Code::Redefinition,
+                            arg_span,
+                            Message::Redefinition(arg_name.clone()),
+                        )
+                        .with_label(*prev_span, Message::PreviousDefinition));
+                    }
+                }
+                args.push((arg_name, arg_type, arg_span));
+                if token_list.consume_sign(")") {
+                    // 引数は終わり
+                    break;
+                } else if token_list.consume_sign(",") {
+                    // 引数はまだ続く
+                    continue;
+                } else {
+                    return Err(token_list.error_at_now(
+                        Code::ExpectedToken,
+                        Message::ExpectedEither("','".to_string(), "')'".to_string()),
+                    ));
+                }
+            }
+        }
+
+        Ok(args)
     }
 
-    // 新しい数字ノードを作成し、そのindexを返す
-    fn append_new_node_num(
+    // 関数定義の引数として与えられた変数は、そのような変数が最初から存在するものとしてコンパイルしておく。
+    // 本体のある定義では、引数の名前は省略できない
+    fn define_params(
         &mut self,
-        input_idx: usize,
-        val: Option<isize>,
-        token_list: &TokenList,
-        typ: Type,
-    ) -> usize {
-        if let None = val {
-            error::error();
-        }
-        let new_idx = self.nodes.len();
-        self.nodes.push(Node {
-            kind: NodeKind::Num,
-            input_idx,
-            lhs: None,
-            rhs: None,
-            val,
-            offset: None,
-            name: None,
-            typ,
-        });
-        new_idx
+        params: Vec<(Option<String>, Type, Span)>,
+    ) -> Result<Vec<(String, Type, Span)>, Diagnostic> {
+        params
+            .into_iter()
+            .map(|(arg_name, arg_type, arg_span)| {
+                let Some(arg_name) = arg_name else {
+                    return Err(Diagnostic::error(
+                        Code::ExpectedIdent,
+                        arg_span,
+                        Message::ExpectedIdent,
+                    ));
+                };
+                self.lvar_list
+                    .add_new_lvar(&arg_name, arg_type, arg_span, self.structs);
+                Ok((arg_name, arg_type, arg_span))
+            })
+            .collect()
     }
 
-    // 新しいローカル変数ノードを作成し、そのindexを返す
-    fn append_new_node_lvar(
+    // "{" stmt* "}"
+    // 関数の本体を構文解析する。構文エラーはerrorsに積む
+    fn body(
         &mut self,
-        input_idx: usize,
-        offset: Option<usize>,
-        token_list: &TokenList,
-        var_name: &String,
-        typ: Type,
-    ) -> usize {
-        if let None = offset {
-            error::error();
-        }
-        let new_idx = self.nodes.len();
-        self.nodes.push(Node {
-            kind: NodeKind::Lvar,
-            input_idx,
-            lhs: None,
-            rhs: None,
-            val: None,
-            offset,
-            name: Some(var_name.clone()),
-            typ,
-        });
-        new_idx
+        token_list: &mut TokenList,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<Stmt>, Diagnostic> {
+        let brace_token_idx = token_list.now;
+        token_list.expect_sign("{")?;
+        // 関数の本体の一番外側のブロックは、引数と同じスコープにある
+        self.block(token_list, brace_token_idx, errors)
     }
 
     /*
     stmt    = expr ";"
             | "{" stmt* "}"
-            | "int" "*"* ident ("[" num "]")? ";"
+            | ty (ident ("[" const_expr "]")?)? ";"
+            | typedef
             | "if" "(" expr ")" stmt ("else" stmt)?
             | "while" "(" expr ")" stmt
             | "for" "(" expr? ";" expr? ";" expr? ")" stmt
             | "return" expr ";"
     */
-    fn stmt(&mut self, token_list: &mut TokenList) -> usize {
-        let idx;
-        let input_idx = token_list.tokens[token_list.now].input_idx;
-        if token_list.consume(TokenKind::Reserved, Some("{")) {
+    // 文の中で構文エラーが起きた場合はerrorsに積み、次の文から構文解析を再開する。警告もerrorsに積む
+    fn stmt(
+        &mut self,
+        token_list: &mut TokenList,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<Stmt, Diagnostic> {
+        let token_idx = token_list.now;
+        let kind = if token_list.consume_sign("{") {
             // compound statement
-            let mut block_node_idx = self.append_new_node(
-                NodeKind::Block,
-                token_list.tokens[token_list.now].input_idx,
-                None,
-                None,
-                None,
-                Type::Stmt,
-            );
-            idx = block_node_idx; // 一番上のblockノードを最終的に返す
-            while !token_list.consume(TokenKind::Reserved, Some("}")) {
-                let lhs = self.stmt(token_list);
-                let prev_block_node_idx = block_node_idx;
-                block_node_idx = self.append_new_node(
-                    NodeKind::Block,
-                    token_list.tokens[token_list.now].input_idx,
-                    None,
-                    None,
-                    None,
-                    Type::Stmt,
-                );
-                self.nodes[prev_block_node_idx].lhs = Some(lhs);
-                self.nodes[prev_block_node_idx].rhs = Some(block_node_idx);
-            }
-        } else if token_list.consume(TokenKind::Return, None) {
+            // ブロックの中で宣言された名前とタグは、ブロックの外からは見えない
+            let outer = self.enter_scope();
+            let stmts = self.block(token_list, token_idx, errors);
+            self.leave_scope(outer);
+            StmtKind::Block(stmts?)
+        } else if token_list.consume(TokenKind::Return) {
             // return
-            let (lhs, _) = self.expr(token_list);
-            idx = self.append_new_node(
-                NodeKind::Return,
-                input_idx,
-                Some(lhs),
-                None,
-                None,
-                Type::Stmt,
-            );
-            token_list.expect(TokenKind::Reserved, Some(";"));
-        } else if token_list.consume(TokenKind::Int, None) {
-            // intの変数定義
-            let mut nst = 0;
-            while token_list.consume(TokenKind::Reserved, Some("*")) {
-                nst += 1;
-            }
-            let (ident, res) = token_list.consume_ident();
-            if !res {
-                error::error();
-                unreachable!()
-            }
-            let ident = ident.unwrap();
-            let token_ident_idx = ident.input_idx;
-            let token_ident_len = ident.len;
-            let var_name: String = token_list.input
-                [token_ident_idx..(token_ident_idx + token_ident_len)]
-                .iter()
-                .collect();
-            self.lvar_list.add_new_lvar(&var_name, Type::Int(nst));
-            idx = self.append_new_node(
-                NodeKind::Int,
-                token_list.tokens[token_list.now].input_idx,
-                None,
-                None,
-                Some(var_name),
-                Type::Stmt,
-            );
-            token_list.expect(TokenKind::Reserved, Some(";"));
-        } else if token_list.consume(TokenKind::If, None) {
+            // returnは関数の本体の中にしか現れない
+            let (ret, func_span) = self.ret.unwrap();
+            // void型はまだないので、全ての関数は値を返さなければならない
+            if token_list.get_now_token().kind.is_sign(";") {
+                return Err(Diagnostic::error(
+                    Code::MissingReturnValue,
+                    token_list.span_from(token_idx),
+                    Message::ReturnWithoutValue,
+                )
+                .with_label(func_span, Message::DeclaredHere));
+            }
+            let expr = self.expr(token_list)?;
+            if !is_assignable(ret, &expr) {
+                return Err(Diagnostic::error(
+                    Code::AssignMismatch,
+                    expr.span,
+                    Message::ReturnMismatch {
+                        from: type_to_string(expr.typ, self.structs),
+                        to: type_to_string(ret, self.structs),
+                    },
+                )
+                .with_label(func_span, Message::DeclaredHere));
+            }
+            let span = token_list.span_from(token_idx);
+            token_list.expect_sign(";")?;
+            return Ok(Stmt {
+                kind: StmtKind::Return(expr),
+                span,
+            });
+        } else if token_list.consume(TokenKind::Typedef) {
+            // 型名の宣言
+            self.typedef(token_list, errors)?;
+            return Ok(Stmt {
+                kind: StmtKind::Block(vec![]),
+                span: token_list.span_from(token_idx),
+            });
+        } else if self.is_type_name(token_list, token_list.now) {
+            // 変数定義
+            let base = self.ty(token_list, errors)?;
+            if token_list.consume_sign(";") {
+                // 構造体・共用体・列挙型の宣言だけで、実行するものはない
+                StmtKind::Block(vec![])
+            } else {
+                let var_span = token_list.now_span();
+                let var_name = token_list.expect_ident()?;
+                let typ = self.array_suffix(token_list, base)?;
+                check_complete(typ, self.structs, var_span)?;
+                self.check_redeclaration(&var_name, var_span)?;
+                let id = self
+                    .lvar_list
+                    .add_new_lvar(&var_name, typ, var_span, self.structs);
+                let span = token_list.span_from(token_idx);
+                token_list.expect_sign(";")?;
+                return Ok(Stmt {
+                    kind: StmtKind::Decl(id),
+                    span,
+                });
+            }
+        } else if token_list.consume(TokenKind::If) {
             // if
-            token_list.expect(TokenKind::Reserved, Some("("));
-            let (flag, _) = self.expr(token_list);
-            let input_idx_inner = token_list.tokens[token_list.now].input_idx; // '('
-            let lhs = self.append_new_node(
-                NodeKind::IfFlag,
-                input_idx_inner,
-                Some(flag),
-                None,
-                None,
-                Type::Stmt,
-            );
-            token_list.expect(TokenKind::Reserved, Some(")"));
-            let input_idx_inner = token_list.tokens[token_list.now].input_idx; // ')'
-            let stmt_left = Some(self.stmt(token_list));
-            let mut stmt_right = None;
-            if token_list.consume(TokenKind::Else, None) {
+            token_list.expect_sign("(")?;
+            let cond_token_idx = token_list.now;
+            let cond = self.expr(token_list)?;
+            self.check_condition(token_list, cond_token_idx, &cond, errors);
+            token_list.expect_sign(")")?;
+            let then = Box::new(self.stmt(token_list, errors)?);
+            let mut els = None;
+            if token_list.consume(TokenKind::Else) {
                 // else
-                stmt_right = Some(self.stmt(token_list));
-            }
-            let rhs = self.append_new_node(
-                NodeKind::IfStmt,
-                input_idx_inner + 1,
-                stmt_left,
-                stmt_right,
-                None,
-                Type::Stmt,
-            );
-            idx = self.append_new_node(
-                NodeKind::If,
-                input_idx,
-                Some(lhs),
-                Some(rhs),
-                None,
-                Type::Stmt,
-            );
-        } else if token_list.consume(TokenKind::While, None) {
+                els = Some(Box::new(self.stmt(token_list, errors)?));
+            }
+            StmtKind::If { cond, then, els }
+        } else if token_list.consume(TokenKind::While) {
             // while
-            token_list.expect(TokenKind::Reserved, Some("("));
-            let (expr, _) = self.expr(token_list);
-            token_list.expect(TokenKind::Reserved, Some(")"));
-            let stmt = self.stmt(token_list);
-            idx = self.append_new_node(
-                NodeKind::While,
-                input_idx,
-                Some(expr),
-                Some(stmt),
-                None,
-                Type::Stmt,
-            );
-        } else if token_list.consume(TokenKind::For, None) {
+            token_list.expect_sign("(")?;
+            let cond_token_idx = token_list.now;
+            let cond = self.expr(token_list)?;
+            self.check_condition(token_list, cond_token_idx, &cond, errors);
+            token_list.expect_sign(")")?;
+            let body = Box::new(self.stmt(token_list, errors)?);
+            StmtKind::While { cond, body }
+        } else if token_list.consume(TokenKind::For) {
             // for
-            token_list.expect(TokenKind::Reserved, Some("("));
-            // '('
-            let forfst_lhs_input_idx = token_list.now;
-            let mut forfst_lhs = None;
-            let mut forfst_rhs = None;
-            let mut forsnd_lhs = None;
-            let forsnd_rhs;
+            token_list.expect_sign("(")?;
+            let mut init = None;
+            let mut cond = None;
+            let mut inc = None;
             // 1つ目のexpr
-            if !token_list.consume(TokenKind::Reserved, Some(";")) {
-                forfst_lhs = Some(self.expr(token_list).0);
-                token_list.consume(TokenKind::Reserved, Some(";"));
+            if !token_list.consume_sign(";") {
+                init = Some(Box::new(self.expr(token_list)?));
+                token_list.expect_sign(";")?;
             }
             // 2つ目のexpr
-            if !token_list.consume(TokenKind::Reserved, Some(";")) {
-                forfst_rhs = Some(self.expr(token_list).0);
-                token_list.consume(TokenKind::Reserved, Some(";"));
+            if !token_list.consume_sign(";") {
+                let cond_token_idx = token_list.now;
+                let expr = self.expr(token_list)?;
+                self.check_condition(token_list, cond_token_idx, &expr, errors);
+                cond = Some(Box::new(expr));
+                token_list.expect_sign(";")?;
             }
-            // 2つめの';'
-            let forsnd_lhs_input_idx = token_list.now;
             // 3つ目のexpr
-            if !token_list.consume(TokenKind::Reserved, Some(")")) {
-                forsnd_lhs = Some(self.expr(token_list).0);
-                token_list.consume(TokenKind::Reserved, Some(")"));
-            }
-            forsnd_rhs = Some(self.stmt(token_list));
-            let lhs = self.append_new_node(
-                NodeKind::ForFst,
-                forfst_lhs_input_idx,
-                forfst_lhs,
-                forfst_rhs,
-                None,
-                Type::Stmt,
-            );
-            let rhs = self.append_new_node(
-                NodeKind::ForSnd,
-                forsnd_lhs_input_idx,
-                forsnd_lhs,
-                forsnd_rhs,
-                None,
-                Type::Stmt,
-            );
-            idx = self.append_new_node(
-                NodeKind::For,
-                input_idx,
-                Some(lhs),
-                Some(rhs),
-                None,
-                Type::Stmt,
-            );
+            if !token_list.consume_sign(")") {
+                inc = Some(Box::new(self.expr(token_list)?));
+                token_list.expect_sign(")")?;
+            }
+            let body = Box::new(self.stmt(token_list, errors)?);
+            StmtKind::For {
+                init,
+                cond,
+                inc,
+                body,
+            }
         } else {
-            let res = self.expr(token_list);
-            idx = res.0;
-            token_list.expect(TokenKind::Reserved, Some(";"));
+            let expr = self.expr(token_list)?;
+            let span = expr.span;
+            token_list.expect_sign(";")?;
+            return Ok(Stmt {
+                kind: StmtKind::Expr(expr),
+                span,
+            });
+        };
+        Ok(Stmt {
+            kind,
+            span: token_list.span_from(token_idx),
+        })
+    }
+
+    // "{"を読んだ後から、ブロックの中の文を"}"まで構文解析する。open_token_idxは"{"のindex
+    fn block(
+        &mut self,
+        token_list: &mut TokenList,
+        open_token_idx: usize,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<Stmt>, Diagnostic> {
+        let mut stmts = vec![];
+        while !token_list.consume_sign("}") {
+            if token_list.at_eof() {
+                return Err(unclosed_brace_error(token_list, open_token_idx));
+            }
+            match self.stmt(token_list, errors) {
+                Ok(stmt) => stmts.push(stmt),
+                Err(e) => {
+                    errors.push(e);
+                    token_list.skip_to_stmt_end();
+                }
+            }
         }
-        idx
+        Ok(stmts)
+    }
+
+    // 新しいスコープに入る。返り値をleave_scopeに渡すと元のスコープに戻る
+    fn enter_scope(&mut self) -> ((usize, usize), usize) {
+        (self.lvar_list.enter_scope(), self.structs.enter_scope())
     }
 
-    // expr以降は、nodeのindexだけではなく、型も返す
+    // スコープを抜け、そのスコープで宣言された名前とタグを見えなくする
+    fn leave_scope(&mut self, (lvars, tags): ((usize, usize), usize)) {
+        self.lvar_list.leave_scope(lvars);
+        self.structs.leave_scope(tags);
+    }
+
+    // 今のスコープで既にnameが宣言されていればエラーにする。spanは新しく宣言する位置
+    fn check_redeclaration(&self, name: &str, span: Span) -> Result<(), Diagnostic> {
+        match self.lvar_list.find_in_scope(name) {
+            Some(id) => Err(Diagnostic::error(
+                Code::Redefinition,
+                span,
+                Message::Redefinition(name.to_string()),
+            )
+            .with_label(self.lvar_list.get(id).span, Message::PreviousDefinition)),
+            None => Ok(()),
+        }
+    }
+
+    // start番目のトークンから始まる条件式condが括弧で囲まれていない代入であれば警告する
+    fn check_condition(
+        &self,
+        token_list: &TokenList,
+        start: usize,
+        cond: &Expr,
+        errors: &mut Vec<Diagnostic>,
+    ) {
+        let ExprKind::Assign { lhs, .. } = &cond.kind else {
+            return;
+        };
+        let is_sign = |idx: usize, sign: &str| token_list.tokens[idx].kind.is_sign(sign);
+        if is_sign(start, "(") && is_sign(token_list.now - 1, ")") {
+            return;
+        }
+        let mut warning = Diagnostic::warning(
+            Code::AssignInCondition,
+            cond.span,
+            Message::AssignInCondition,
+        );
+        // 左辺の直後にある'='を'=='に置き換える修正案をつける
+        if let Some(token) = token_list.tokens[start..token_list.now]
+            .iter()
+            .find(|token| token.span.start >= lhs.span.end && token.kind.is_sign("="))
+        {
+            warning = warning.with_suggestion(token.span, "==", Message::UseEqualityOperator);
+        }
+        errors.push(warning.with_note(Message::ParenthesizeAssignment));
+    }
+
+    // lvalueが変数であれば、その変数が宣言された位置をラベルとしてdiagnosticに付け加える
+    fn with_decl_label(&self, diagnostic: Diagnostic, lvalue: &LValue) -> Diagnostic {
+        let (name, typ, span) = match lvalue.kind {
+            LValueKind::Var(id) => {
+                let lvar = self.lvar_list.get(id);
+                (&lvar.name, lvar.typ, lvar.span)
+            }
+            LValueKind::Global(id) => {
+                let gvar = &self.globals[id];
+                (&gvar.name, gvar.typ, gvar.span)
+            }
+            LValueKind::Deref(_) | LValueKind::Member { .. } => return diagnostic,
+        };
+        diagnostic.with_label(
+            span,
+            Message::DeclaredAs {
+                name: name.clone(),
+                typ: type_to_string(typ, self.structs),
+            },
+        )
+    }
 
     // expr       = assign
-    fn expr(&mut self, token_list: &mut TokenList) -> (usize, Type) {
+    fn expr(&mut self, token_list: &mut TokenList) -> Result<Expr, Diagnostic> {
         self.assign(token_list)
     }
 
     // assign     = equality ("=" assign)?
-    fn assign(&mut self, token_list: &mut TokenList) -> (usize, Type) {
-        let (mut idx, mut typ) = self.equality(token_list);
-        if token_list.consume(TokenKind::Reserved, Some("=")) {
-            let (rhs, ty) = self.assign(token_list);
-
-            match_assign_type(typ, ty, token_list);
-
-            typ = ty;
-            let input_idx = token_list.tokens[token_list.now].input_idx;
-            idx = self.append_new_node(
-                NodeKind::Assign,
-                input_idx,
-                Some(idx),
-                Some(rhs),
-                None,
-                ty, // 代入演算子の返り値は代入した値そのもの
-            );
+    fn assign(&mut self, token_list: &mut TokenList) -> Result<Expr, Diagnostic> {
+        let token_idx = token_list.now;
+        let lhs = self.equality(token_list)?;
+        if !token_list.consume_sign("=") {
+            return Ok(lhs);
         }
-        (idx, typ)
+        // 左辺は変数か参照外しでなければならない
+        let ExprKind::Load(lhs) = lhs.kind else {
+            return Err(Diagnostic::error(
+                Code::NotAssignable,
+                lhs.span,
+                Message::NotAssignable,
+            ));
+        };
+        // 配列そのものには代入できない
+        if is_array(lhs.typ) {
+            let e = Diagnostic::error(Code::NotAssignable, lhs.span, Message::AssignToArray);
+            return Err(self.with_decl_label(e, &lhs));
+        }
+
+        let rhs = self.assign(token_list)?;
+        let span = token_list.span_from(token_idx);
+
+        match_assign_type(lhs.typ, &rhs, span, self.structs)
+            .map_err(|e| self.with_decl_label(e, &lhs))?;
+
+        // 代入演算子の返り値は代入した値そのもの
+        let typ = rhs.typ;
+        Ok(Expr {
+            kind: ExprKind::Assign {
+                lhs,
+                rhs: Box::new(rhs),
+            },
+            span,
+            typ,
+        })
     }
 
     // equality   = relational ("==" relational | "!=" relational)*
-    fn equality(&mut self, token_list: &mut TokenList) -> (usize, Type) {
-        let (mut idx, mut typ) = self.relational(token_list);
+    fn equality(&mut self, token_list: &mut TokenList) -> Result<Expr, Diagnostic> {
+        let token_idx = token_list.now;
+        let mut expr = self.relational(token_list)?;
 
-        let input_idx = token_list.tokens[token_list.now].input_idx;
         loop {
-            if token_list.consume(TokenKind::Reserved, Some("==")) {
-                let (rhs, ty) = self.relational(token_list);
-                typ = ty;
-                idx = self.append_new_node(
-                    NodeKind::Eq,
-                    input_idx,
-                    Some(idx),
-                    Some(rhs),
-                    None,
-                    Type::Int(0), // 比較演算子の返り値は1or0のINT
-                );
-            } else if token_list.consume(TokenKind::Reserved, Some("!=")) {
-                let (rhs, ty) = self.relational(token_list);
-                typ = ty;
-                idx = self.append_new_node(
-                    NodeKind::Ne,
-                    input_idx,
-                    Some(idx),
-                    Some(rhs),
-                    None,
-                    Type::Int(0),
-                );
+            // 比較演算子の返り値は1or0のINT
+            if token_list.consume_sign("==") {
+                let rhs = self.relational(token_list)?;
+                let span = token_list.span_from(token_idx);
+                expr = binary(BinOp::Eq, expr, rhs, span, Type::Int(0));
+            } else if token_list.consume_sign("!=") {
+                let rhs = self.relational(token_list)?;
+                let span = token_list.span_from(token_idx);
+                expr = binary(BinOp::Ne, expr, rhs, span, Type::Int(0));
             } else {
                 break;
             }
         }
-        (idx, typ)
+        Ok(expr)
     }
 
     // relational = add ("<" add | "<=" add | ">" add | ">=" add)*
-    fn relational(&mut self, token_list: &mut TokenList) -> (usize, Type) {
-        let (mut idx, mut typ) = self.add(token_list);
+    fn relational(&mut self, token_list: &mut TokenList) -> Result<Expr, Diagnostic> {
+        let token_idx = token_list.now;
+        let mut expr = self.add(token_list)?;
 
-        let input_idx = token_list.tokens[token_list.now].input_idx;
         loop {
-            if token_list.consume(TokenKind::Reserved, Some("<")) {
-                let (rhs, ty) = self.add(token_list);
-                typ = ty;
-                idx = self.append_new_node(
-                    NodeKind::Lt,
-                    input_idx,
-                    Some(idx),
-                    Some(rhs),
-                    None,
-                    Type::Int(0),
-                );
-            } else if token_list.consume(TokenKind::Reserved, Some("<=")) {
-                let (rhs, ty) = self.add(token_list);
-                typ = ty;
-                idx = self.append_new_node(
-                    NodeKind::Le,
-                    input_idx,
-                    Some(idx),
-                    Some(rhs),
-                    None,
-                    Type::Int(0),
-                );
-            } else if token_list.consume(TokenKind::Reserved, Some(">")) {
-                let (lhs, ty) = self.add(token_list);
-                typ = ty;
-                idx = self.append_new_node(
-                    NodeKind::Lt,
-                    input_idx,
-                    Some(lhs),
-                    Some(idx),
-                    None,
-                    Type::Int(0),
-                );
-            } else if token_list.consume(TokenKind::Reserved, Some(">=")) {
-                let (lhs, ty) = self.add(token_list);
-                typ = ty;
-                idx = self.append_new_node(
-                    NodeKind::Le,
-                    input_idx,
-                    Some(lhs),
-                    Some(idx),
-                    None,
-                    Type::Int(0),
-                );
+            if token_list.consume_sign("<") {
+                let rhs = self.add(token_list)?;
+                let span = token_list.span_from(token_idx);
+                expr = binary(BinOp::Lt, expr, rhs, span, Type::Int(0));
+            } else if token_list.consume_sign("<=") {
+                let rhs = self.add(token_list)?;
+                let span = token_list.span_from(token_idx);
+                expr = binary(BinOp::Le, expr, rhs, span, Type::Int(0));
+            } else if token_list.consume_sign(">") {
+                let lhs = self.add(token_list)?;
+                let span = token_list.span_from(token_idx);
+                expr = binary(BinOp::Lt, lhs, expr, span, Type::Int(0));
+            } else if token_list.consume_sign(">=") {
+                let lhs = self.add(token_list)?;
+                let span = token_list.span_from(token_idx);
+                expr = binary(BinOp::Le, lhs, expr, span, Type::Int(0));
             } else {
                 break;
             }
         }
-        (idx, typ)
+        Ok(expr)
     }
 
     // add        = mul ("+" mul | "-" mul)*
-    fn add(&mut self, token_list: &mut TokenList) -> (usize, Type) {
-        let (mut idx, mut typ) = self.mul(token_list);
+    fn add(&mut self, token_list: &mut TokenList) -> Result<Expr, Diagnostic> {
+        let token_idx = token_list.now;
+        let mut expr = self.mul(token_list)?;
 
-        let input_idx = token_list.tokens[token_list.now].input_idx;
         loop {
-            if token_list.consume(TokenKind::Reserved, Some("+")) {
-                let (rhs, ty) = self.mul(token_list);
-                typ = binary_calc_type(typ, ty, token_list);
-                idx =
-                    self.append_new_node(NodeKind::Add, input_idx, Some(idx), Some(rhs), None, typ);
-            } else if token_list.consume(TokenKind::Reserved, Some("-")) {
-                let (rhs, ty) = self.mul(token_list);
-                typ = binary_calc_type(typ, ty, token_list);
-                idx =
-                    self.append_new_node(NodeKind::Sub, input_idx, Some(idx), Some(rhs), None, typ);
+            let op = if token_list.consume_sign("+") {
+                BinOp::Add
+            } else if token_list.consume_sign("-") {
+                BinOp::Sub
             } else {
                 break;
-            }
+            };
+            let rhs = self.mul(token_list)?;
+            let span = token_list.span_from(token_idx);
+            let typ = binary_calc_type(expr.typ, rhs.typ, span, self.structs)?;
+            expr = binary(op, expr, rhs, span, typ);
         }
-        (idx, typ)
+        Ok(expr)
     }
 
     // mul     = unary ("*" unary | "/" unary)*
-    fn mul(&mut self, token_list: &mut TokenList) -> (usize, Type) {
-        let (mut idx, mut typ) = self.unary(token_list);
+    fn mul(&mut self, token_list: &mut TokenList) -> Result<Expr, Diagnostic> {
+        let token_idx = token_list.now;
+        let mut expr = self.unary(token_list)?;
 
-        let input_idx = token_list.tokens[token_list.now].input_idx;
         loop {
-            if token_list.consume(TokenKind::Reserved, Some("*")) {
-                let (rhs, ty) = self.unary(token_list);
-                typ = binary_calc_type(typ, ty, token_list);
-                idx =
-                    self.append_new_node(NodeKind::Mul, input_idx, Some(idx), Some(rhs), None, typ);
-            } else if token_list.consume(TokenKind::Reserved, Some("/")) {
-                let (rhs, ty) = self.unary(token_list);
-                typ = binary_calc_type(typ, ty, token_list);
-                idx =
-                    self.append_new_node(NodeKind::Div, input_idx, Some(idx), Some(rhs), None, typ);
+            let op = if token_list.consume_sign("*") {
+                BinOp::Mul
+            } else if token_list.consume_sign("/") {
+                BinOp::Div
             } else {
                 break;
-            }
+            };
+            let rhs = self.unary(token_list)?;
+            let span = token_list.span_from(token_idx);
+            let typ = binary_calc_type(expr.typ, rhs.typ, span, self.structs)?;
+            expr = binary(op, expr, rhs, span, typ);
         }
-        (idx, typ)
+        Ok(expr)
     }
 
-    // unary   = "sizeof" unary | ("+" | "-")? primary | "*" unary | "&" unary
-    fn unary(&mut self, token_list: &mut TokenList) -> (usize, Type) {
-        if token_list.consume(TokenKind::Sizeof, None) {
+    // unary   = "sizeof" "(" type_name ")" | "sizeof" unary | "(" type_name ")" unary
+    //         | ("+" | "-")? postfix | "*" unary | "&" unary
+    fn unary(&mut self, token_list: &mut TokenList) -> Result<Expr, Diagnostic> {
+        let token_idx = token_list.now;
+        if token_list.consume(TokenKind::Sizeof) {
             // sizeof
-            let (_idx, typ) = self.unary(token_list);
-            let input_idx = token_list.tokens[token_list.now].input_idx;
-            match typ {
-                Type::Int(0) => (
-                    self.append_new_node_num(input_idx, Some(4), token_list, Type::Int(0)),
-                    Type::Int(0),
-                ),
-                Type::Int(x) if x > 0 => (
-                    self.append_new_node_num(input_idx, Some(8), token_list, Type::Int(0)),
-                    Type::Int(0),
-                ),
-                _ => unreachable!(),
-            }
-        } else if token_list.consume(TokenKind::Reserved, Some("+")) {
+            let typ = match self.paren_type_name(token_list)? {
+                Some(typ) => typ,
+                None => {
+                    // 配列はポインタとみなさず、配列全体の大きさを返す
+                    let operand = self.unary(token_list)?;
+                    // 被演算子は評価されずに消えるので、その中の変数は使われたものとして記録しておく
+                    operand.walk(&mut |expr| {
+                        if let Some(id) = expr.local_var() {
+                            self.lvar_list.lvars[id].in_sizeof = true;
+                        }
+                    });
+                    if operand.typ == Type::Unknown {
+                        return Err(Diagnostic::error(
+                            Code::UnknownSize,
+                            token_list.span_from(token_idx),
+                            Message::UnknownSize,
+                        ));
+                    }
+                    operand.typ
+                }
+            };
+            let span = token_list.span_from(token_idx);
+            check_complete(typ, self.structs, span)?;
+            Ok(num(get_size(typ, self.structs) as isize, span))
+        } else if let Some(typ) = self.paren_type_name(token_list)? {
+            // cast
+            let operand = self.unary(token_list)?;
+            let span = token_list.span_from(token_idx);
+            cast(operand, typ, span, self.structs)
+        } else if token_list.consume_sign("+") {
             // +
-            self.primary(token_list)
-        } else if token_list.consume(TokenKind::Reserved, Some("-")) {
+            self.postfix(token_list)
+        } else if token_list.consume_sign("-") {
             // -
             // -nは0-nに置き換える
-            let input_idx = token_list.tokens[token_list.now].input_idx;
-            let zero = self.append_new_node_num(input_idx, Some(0), token_list, Type::Int(0));
-            let (rhs, typ) = self.primary(token_list);
-            (
-                self.append_new_node(NodeKind::Sub, input_idx, Some(zero), Some(rhs), None, typ),
-                typ,
-            )
-        } else if token_list.consume(TokenKind::Reserved, Some("*")) {
+            let zero = num(0, token_list.span_from(token_idx));
+            let rhs = self.postfix(token_list)?;
+            let span = token_list.span_from(token_idx);
+            let typ = binary_calc_type(zero.typ, rhs.typ, span, self.structs)?;
+            Ok(binary(BinOp::Sub, zero, rhs, span, typ))
+        } else if token_list.consume_sign("*") {
             // deref
-            let input_idx = token_list.tokens[token_list.now].input_idx;
-            let (lhs, mut typ) = self.unary(token_list);
-            match typ {
-                Type::Int(x) if x >= 1 => typ = Type::Int(x - 1),
-                _ => error::error(),
-            }
-            (
-                self.append_new_node(NodeKind::Deref, input_idx, Some(lhs), None, None, typ),
-                typ,
-            )
-        } else if token_list.consume(TokenKind::Reserved, Some("&")) {
+            let operand = self.unary(token_list)?;
+            let span = token_list.span_from(token_idx);
+            self.deref(operand, span)
+        } else if token_list.consume_sign("&") {
             // addr
-            let input_idx = token_list.tokens[token_list.now].input_idx;
-            let (lhs, mut typ) = self.unary(token_list);
-            match typ {
-                Type::Int(x) => typ = Type::Int(x + 1),
-                _ => error::error(),
-            }
-            (
-                self.append_new_node(NodeKind::Addr, input_idx, Some(lhs), None, None, typ),
-                typ,
-            )
+            let operand = self.unary(token_list)?;
+            let span = token_list.span_from(token_idx);
+            // アドレスを取れるのは配列でない変数か参照外し
+            if let ExprKind::Load(lvalue) = operand.kind {
+                if let Type::Int(_) | Type::Char(_) | Type::Struct { .. } = lvalue.typ {
+                    let typ = pointer_to(lvalue.typ);
+                    return Ok(Expr {
+                        kind: ExprKind::Addr(lvalue),
+                        span,
+                        typ,
+                    });
+                }
+            }
+            Err(Diagnostic::error(
+                Code::InvalidAddrOf,
+                span,
+                Message::InvalidAddrOf,
+            ))
         } else {
-            self.primary(token_list)
+            self.postfix(token_list)
+        }
+    }
+
+    // 括弧で囲まれた型名があれば読んでその型を返す。'('の次が型名でなければ何も読まずにNoneを返す
+    fn paren_type_name(&mut self, token_list: &mut TokenList) -> Result<Option<Type>, Diagnostic> {
+        if !(token_list.get_now_token().kind.is_sign("(")
+            && self.is_type_name(token_list, token_list.now + 1))
+        {
+            return Ok(None);
         }
+        token_list.expect_sign("(")?;
+        let typ = self.type_name(token_list)?;
+        token_list.expect_sign(")")?;
+        Ok(Some(typ))
     }
 
-    // primary    = num | ident ("(" expr* ")")? | "(" expr ")"
-    fn primary(&mut self, token_list: &mut TokenList) -> (usize, Type) {
-        let input_idx = token_list.tokens[token_list.now].input_idx;
-        if token_list.consume(TokenKind::Reserved, Some("(")) {
+    // operandを参照外しした左辺値の値を読み出す式を作る。spanは参照外しの式全体の位置
+    fn deref(&self, operand: Expr, span: Span) -> Result<Expr, Diagnostic> {
+        let typ = match pointee(operand.typ) {
+            Some(typ) => typ,
+            None => {
+                let e = Diagnostic::error(Code::DerefNonPointer, span, Message::DerefNonPointer)
+                    .with_label(
+                        operand.span,
+                        Message::ValueOfType(type_to_string(operand.typ, self.structs)),
+                    );
+                return Err(match &operand.kind {
+                    ExprKind::Load(lvalue) => self.with_decl_label(e, lvalue),
+                    _ => e,
+                });
+            }
+        };
+        Ok(load(LValueKind::Deref(Box::new(operand)), span, typ))
+    }
+
+    // postfix    = primary ("[" expr "]" | "." ident | "->" ident)*
+    fn postfix(&mut self, token_list: &mut TokenList) -> Result<Expr, Diagnostic> {
+        let token_idx = token_list.now;
+        let mut expr = self.primary(token_list)?;
+
+        loop {
+            if token_list.consume_sign("[") {
+                // a[i]は*(a+i)に置き換える
+                let index = self.expr(token_list)?;
+                token_list.expect_sign("]")?;
+                let span = token_list.span_from(token_idx);
+                let typ = binary_calc_type(expr.typ, index.typ, span, self.structs)?;
+                let addr = binary(BinOp::Add, expr, index, span, typ);
+                expr = self.deref(addr, span)?;
+            } else if token_list.consume_sign(".") {
+                expr = self.member(token_list, token_idx, expr, ".")?;
+            } else if token_list.consume_sign("->") {
+                // p->mは(*p).mに置き換える
+                let span = token_list.span_from(token_idx);
+                let base = match pointee(expr.typ) {
+                    Some(Type::Struct { level: 0, .. }) => self.deref(expr, span)?,
+                    _ => return Err(not_a_struct(&expr, span, "->", self.structs)),
+                };
+                expr = self.member(token_list, token_idx, base, "->")?;
+            } else {
+                break;
+            }
+        }
+        Ok(expr)
+    }
+
+    // 構造体の値baseの、次のトークンで指定されたメンバを読み出す式を作る。opはメンバの参照に使った演算子
+    fn member(
+        &self,
+        token_list: &mut TokenList,
+        token_idx: usize,
+        base: Expr,
+        op: &str,
+    ) -> Result<Expr, Diagnostic> {
+        let name_span = token_list.now_span();
+        let name = token_list.expect_ident()?;
+        let span = token_list.span_from(token_idx);
+        let Type::Struct { id, level: 0 } = base.typ else {
+            return Err(not_a_struct(&base, span, op, self.structs));
+        };
+        check_complete(base.typ, self.structs, span)?;
+        let Some(member) = self.structs.get(id).members.iter().find(|m| m.name == name) else {
+            return Err(Diagnostic::error(
+                Code::InvalidMemberAccess,
+                name_span,
+                Message::NoSuchMember {
+                    name,
+                    typ: self.structs.type_name(id),
+                },
+            )
+            .with_label(self.structs.get(id).span, Message::DefinedHere));
+        };
+        let (offset, typ) = (member.offset, member.typ);
+        // 構造体の値は、代入式の結果でなければ左辺値から読み出したもの
+        let ExprKind::Load(lvalue) = base.kind else {
+            return Err(Diagnostic::error(
+                Code::Unsupported,
+                span,
+                Message::MemberOfRvalue,
+            ));
+        };
+        Ok(load(
+            LValueKind::Member {
+                base: Box::new(lvalue),
+                offset,
+            },
+            span,
+            typ,
+        ))
+    }
+
+    // primary    = num | char | str+ | ident ("(" expr* ")")? | "(" expr ")"
+    fn primary(&mut self, token_list: &mut TokenList) -> Result<Expr, Diagnostic> {
+        let token_idx = token_list.now;
+        if token_list.consume_sign("(") {
             // 次のトークンが'('なら'(expr)'
-            let (idx, typ) = self.expr(token_list);
-            token_list.expect(TokenKind::Reserved, Some(")"));
-            (idx, typ)
-        } else if let (Some(token_ident), true) = token_list.consume_ident() {
+            let expr = self.expr(token_list)?;
+            token_list.expect_sign(")")?;
+            Ok(expr)
+        } else if let Some(var_name) = token_list.consume_ident() {
             // ident
-            let token_ident_idx = token_ident.input_idx;
-            let token_ident_len = token_ident.len;
-            let var_name: String = token_list.input
-                [token_ident_idx..(token_ident_idx + token_ident_len)]
-                .iter()
-                .collect();
-            let ret;
-            let typ;
-            if let (Some(lvar), true) = self.lvar_list.find_lvar(&var_name) {
+            let span = token_list.span_from(token_idx);
+            if let Some(id) = self.lvar_list.find_lvar(&var_name) {
                 // 今までに使われたことがあるローカル変数
-                typ = lvar.typ;
-                ret = self.append_new_node_lvar(
-                    input_idx,
-                    Some(lvar.offset),
-                    token_list,
-                    &var_name,
-                    typ,
-                );
-            } else if token_list.consume(TokenKind::Reserved, Some("(")) {
-                // 関数呼び出し
-                ret = self.append_new_node(
-                    NodeKind::App,
-                    input_idx,
-                    None,
-                    None,
-                    Some(var_name),
-                    Type::Unknown,
-                );
-                let mut node = ret;
-                typ = Type::Unknown;
-                if token_list.consume(TokenKind::Reserved, Some(")")) {
-                    // 引数がない場合はなにもしない
-                } else {
-                    // 引数が1個以上ある
-                    loop {
-                        // 引数が続く
-                        let (expr, typ) = self.expr(token_list);
-                        let arg = self.append_new_node(
-                            NodeKind::Arg,
-                            token_list.tokens[token_list.now].input_idx,
-                            Some(expr),
-                            None,
-                            None,
-                            typ,
-                        );
-                        self.nodes[node].rhs = Some(arg);
-                        node = arg;
-                        if token_list.consume(TokenKind::Reserved, Some(")")) {
-                            // 引数は終わり
-                            break;
-                        } else if token_list.consume(TokenKind::Reserved, Some(",")) {
-                            // 引数はまだ続く
-                            continue;
-                        } else {
-                            // ここには辿り着かないはずなのでparseが失敗している
-                            error::error();
-                        }
+                let lvar = self.lvar_list.get(id);
+                return name_expr(lvar.kind, LValueKind::Var(id), span, lvar.typ, var_name);
+            }
+            if let Some(id) = self.globals.iter().position(|gvar| gvar.name == var_name) {
+                // 大域変数
+                let gvar = &self.globals[id];
+                return name_expr(gvar.kind, LValueKind::Global(id), span, gvar.typ, var_name);
+            }
+            if !token_list.consume_sign("(") {
+                return Err(Diagnostic::error(
+                    Code::UndefinedVariable,
+                    token_list.span_from(token_idx),
+                    Message::UndefinedVariable(var_name),
+                ));
+            }
+
+            // 関数呼び出し
+            let mut args = vec![];
+            if token_list.consume_sign(")") {
+                // 引数がない場合はなにもしない
+            } else {
+                // 引数が1個以上ある
+                loop {
+                    // 引数が続く
+                    let arg = self.expr(token_list)?;
+                    if let Type::Struct { level: 0, .. } = arg.typ {
+                        return Err(Diagnostic::error(
+                            Code::Unsupported,
+                            arg.span,
+                            Message::StructByValue,
+                        ));
+                    }
+                    args.push(arg);
+                    if token_list.consume_sign(")") {
+                        // 引数は終わり
+                        break;
+                    } else if token_list.consume_sign(",") {
+                        // 引数はまだ続く
+                        continue;
+                    } else {
+                        return Err(token_list.error_at_now(
+                            Code::ExpectedToken,
+                            Message::ExpectedEither("','".to_string(), "')'".to_string()),
+                        ));
                     }
                 }
-            } else {
-                error::error();
-                unreachable!();
             }
-
-            (ret, typ)
+            let span = token_list.span_from(token_idx);
+            // 宣言されていない関数の返り値の型はわからない
+            let typ = match self.sigs.iter().find(|sig| sig.name == var_name) {
+                Some(sig) => {
+                    self.check_args(sig, &args, span)?;
+                    sig.ret
+                }
+                None => Type::Unknown,
+            };
+            Ok(Expr {
+                kind: ExprKind::Call {
+                    callee: var_name,
+                    args,
+                },
+                span,
+                typ,
+            })
+        } else if let Some(val) = token_list.consume_char_literal() {
+            // 文字定数はint型
+            Ok(num(val, token_list.span_from(token_idx)))
+        } else if let Some(mut bytes) = token_list.consume_string() {
+            // 隣接する文字列リテラルは連結する
+            while let Some(next) = token_list.consume_string() {
+                bytes.extend(next);
+            }
+            // 末尾の'\0'の分だけ長い配列になる
+            let typ = Type::CharArr(Array {
+                size: bytes.len() + 1,
+                level: 0,
+            });
+            self.strings.push(bytes);
+            Ok(Expr {
+                kind: ExprKind::Str(self.strings.len() - 1),
+                span: token_list.span_from(token_idx),
+                typ,
+            })
+        } else if let Some(e) = unsupported_keyword(token_list) {
+            Err(e)
         } else {
             // num
-            (
-                self.append_new_node_num(
-                    input_idx,
-                    token_list.expect_number(),
-                    token_list,
-                    Type::Int(0),
-                ),
-                Type::Int(0),
-            )
+            let (val, typ) = token_list.expect_number()?;
+            let span = token_list.span_from(token_idx);
+            // intより大きい整数型はまだないので、intと同じ大きさの型の定数だけを扱う。
+            // unsigned intの定数は、同じビット列のintとして扱う
+            if !matches!(typ, NumType::Int | NumType::UInt) {
+                return Err(Diagnostic::error(
+                    Code::Unsupported,
+                    span,
+                    Message::UnsupportedNumType(typ.name().to_string()),
+                ));
+            }
+            Ok(num(val as i32 as isize, span))
         }
     }
 }
 
+// int型の数の式を作る
+fn num(val: isize, span: Span) -> Expr {
+    Expr {
+        kind: ExprKind::Num(val),
+        span,
+        typ: Type::Int(0),
+    }
+}
+
+// operandをtyp型に変換する式を作る。spanはキャストの式全体の位置
+// 変換できるのは、配列と構造体を除く型の間だけ
+fn cast(operand: Expr, typ: Type, span: Span, structs: &StructList) -> Result<Expr, Diagnostic> {
+    let from = promote(operand.typ);
+    if is_array(typ)
+        || matches!(typ, Type::Struct { level: 0, .. })
+        || matches!(from, Type::Struct { level: 0, .. })
+    {
+        return Err(Diagnostic::error(
+            Code::InvalidCast,
+            span,
+            Message::InvalidCast {
+                from: type_to_string(operand.typ, structs),
+                to: type_to_string(typ, structs),
+            },
+        ));
+    }
+    Ok(Expr {
+        kind: ExprKind::Cast(Box::new(operand)),
+        span,
+        typ,
+    })
+}
+
+// kindの名前を使った式を作る。変数であればlvalueの値を読み出し、列挙定数であればその値になる
+fn name_expr(
+    kind: NameKind,
+    lvalue: LValueKind,
+    span: Span,
+    typ: Type,
+    name: String,
+) -> Result<Expr, Diagnostic> {
+    match kind {
+        NameKind::Var => Ok(load(lvalue, span, typ)),
+        // 列挙定数はint型の定数
+        NameKind::EnumConst(val) => Ok(num(val, span)),
+        NameKind::Typedef => Err(Diagnostic::error(
+            Code::ExpectedToken,
+            span,
+            Message::TypeNameInExpr(name),
+        )),
+    }
+}
+
+// 二項演算の式を作る
+fn binary(op: BinOp, lhs: Expr, rhs: Expr, span: Span, typ: Type) -> Expr {
+    Expr {
+        kind: ExprKind::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+        span,
+        typ,
+    }
+}
+
+// 左辺値の値を読み出す式を作る
+fn load(kind: LValueKind, span: Span, typ: Type) -> Expr {
+    Expr {
+        kind: ExprKind::Load(LValue { kind, span, typ }),
+        span,
+        typ,
+    }
+}
+
 #[derive(Debug)]
 pub struct Func {
-    pub program: NodeList,         // 関数をNodeListを用いて表現する
-    pub args: Vec<(String, Type)>, // (関数の引数名, 型)
-    pub name: String,              // 関数の名前
+    pub body: Vec<Stmt>,                 // 関数の本体の文
+    pub lvar_list: LVarList,             // 引数を含むローカル変数
+    pub strings: Vec<Vec<u8>>,           // 関数内の文字列リテラルの中身
+    pub args: Vec<(String, Type, Span)>, // (関数の引数名, 型, 引数名の位置)
+    pub name: String,                    // 関数の名前
+    pub span: Span,                      // 関数名の位置
+    pub end_span: Span,                  // 関数の本体を閉じる'}'の位置
 }
 impl Func {
-    // func    = "int" "*"* ident "(" ("int" "*"* ident)* ")" "{" stmt* "}"
-    pub fn new(token_list: &mut TokenList) -> Self {
-        token_list.expect(TokenKind::Int, None);
+    // func    = ty ident "(" (ty ident?)* ")" ("{" stmt* "}" | ";")
+    // 関数名func_nameと'('までを読んだ後から構文解析する。retは返り値の型で、spanは関数名の位置。
+    // 関数の型はprogramに登録し、本体のない関数の宣言であればNoneを返す。
+    // 関数の中で定義された構造体はprogramに追加されるが、そのタグは関数の外からは見えない。
+    // 関数の本体の文で起きた構文エラーはerrorsに積み、関数の宣言部分や閉じられていない本体の構文エラーは返り値として返す
+    pub fn new(
+        token_list: &mut TokenList,
+        func_name: String,
+        ret: Type,
+        span: Span,
+        program: &mut Program,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<Option<Self>, Diagnostic> {
+        let outer = program.structs.enter_scope();
+        let mut parser = FuncParser::new(
+            &mut program.globals,
+            &mut program.structs,
+            &mut program.sigs,
+            false,
+        );
+        parser.ret = Some((ret, span));
+        let result = parser.params(token_list, errors).and_then(|params| {
+            // 再帰呼び出しできるように、本体より先に関数の型を登録する
+            let defined = !token_list.consume_sign(";");
+            // 本体のない"()"の宣言は、引数を指定しない
+            let unspecified = !defined && params.is_empty();
+            let sig = FuncSig {
+                name: func_name.clone(),
+                ret,
+                params: (!unspecified).then(|| params.iter().map(|(_, typ, _)| *typ).collect()),
+                span,
+                defined,
+            };
+            parser.declare_func(sig, errors);
+            if !defined {
+                return Ok(None);
+            }
+            let args = parser.define_params(params)?;
+            Ok(Some((args, parser.body(token_list, errors)?)))
+        });
+        let FuncParser {
+            lvar_list, strings, ..
+        } = parser;
+        program.structs.leave_scope(outer);
+        let Some((args, body)) = result? else {
+            return Ok(None);
+        };
+
+        // 直前に読んだトークンは関数の本体を閉じる'}'
+        let end_span = token_list.tokens[token_list.now - 1].span;
 
-        // TODO: 関数の返り値の型の利用
-        let mut _nst = 0;
-        while token_list.consume(TokenKind::Reserved, Some("*")) {
-            _nst += 1;
+        Ok(Some(Func {
+            body,
+            lvar_list,
+            strings,
+            args,
+            name: func_name,
+            span,
+            end_span,
+        }))
+    }
+}
+
+// 関数の型
+#[derive(Debug)]
+pub struct FuncSig {
+    pub name: String,
+    pub ret: Type,                 // 返り値の型
+    pub params: Option<Vec<Type>>, // 引数の型。"()"で宣言されて引数が指定されていなければNone
+    pub span: Span,                // 最初に宣言された位置
+    pub defined: bool,             // 本体が定義されているか
+}
+
+// 大域変数
+#[derive(Debug)]
+pub struct GVar {
+    pub name: String,
+    pub typ: Type,
+    pub span: Span,          // 名前の位置
+    pub init: Option<isize>, // 初期値。ない場合は0で初期化される
+    pub kind: NameKind,      // 変数でなければ領域を持たない
+}
+
+// プログラム全体
+#[derive(Debug, Default)]
+pub struct Program {
+    pub funcs: Vec<Func>,
+    pub globals: Vec<GVar>,
+    pub structs: StructList,
+    pub sigs: Vec<FuncSig>, // 宣言された全ての関数の型
+}
+impl Program {
+    // program = (func | gvar | ty ";" | typedef)*
+    // gvar    = ty ident ("[" const_expr "]")? ("=" expr)? ";"
+    // トップレベルの宣言を1つ構文解析する
+    fn top_level(
+        &mut self,
+        token_list: &mut TokenList,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<(), Diagnostic> {
+        let mut parser =
+            FuncParser::new(&mut self.globals, &mut self.structs, &mut self.sigs, true);
+        if token_list.consume(TokenKind::Typedef) {
+            return parser.typedef(token_list, errors);
+        }
+        let base = parser.ty(token_list, errors)?;
+        if token_list.consume_sign(";") {
+            // 構造体・共用体・列挙型の宣言だけ
+            return Ok(());
         }
 
-        let input_idx = token_list.tokens[token_list.now].input_idx;
+        let span = token_list.now_span();
+        let name = token_list.expect_ident()?;
+        if token_list.consume_sign("(") {
+            if let Type::Struct { level: 0, .. } = base {
+                return Err(Diagnostic::error(
+                    Code::Unsupported,
+                    span,
+                    Message::StructByValue,
+                ));
+            }
+            if let Some(func) = Func::new(token_list, name, base, span, self, errors)? {
+                self.funcs.push(func);
+            }
+            return Ok(());
+        }
 
-        let func_name = token_list.expect_ident();
-        token_list.expect(TokenKind::Reserved, Some("("));
+        // 大域変数の初期値は定数式でなければならない
+        let mut parser =
+            FuncParser::new(&mut self.globals, &mut self.structs, &mut self.sigs, true);
+        let typ = parser.array_suffix(token_list, base)?;
+        check_complete(typ, parser.structs, span)?;
+        let mut init = None;
+        if token_list.consume_sign("=") {
+            let token_idx = token_list.now;
+            let expr = parser.expr(token_list)?;
+            let span = token_list.span_from(token_idx);
+            // 初期値が正しくなくても変数は登録し、使った場所で未定義のエラーが続かないようにする
+            match match_assign_type(typ, &expr, span, parser.structs).and(const_value(&expr)) {
+                Ok(val) => init = Some(convert_const(val, typ)),
+                Err(err) => errors.push(err),
+            }
+        }
+        token_list.expect_sign(";")?;
 
-        let mut args = vec![];
-        if token_list.consume(TokenKind::Reserved, Some(")")) {
-            // 引数が何もない場合はなにもしない
+        if let Some(prev) = self.sigs.iter().find(|sig| sig.name == name) {
+            errors.push(
+                Diagnostic::error(Code::Redefinition, span, Message::Redefinition(name))
+                    .with_label(prev.span, Message::PreviousDeclaration),
+            );
+            return Ok(());
+        }
+        // 同じ名前の大域変数は、型が同じで初期値が高々1つであれば同じ変数とみなす
+        if let Some(prev) = self.globals.iter_mut().find(|gvar| gvar.name == name) {
+            let message = if prev.typ != typ {
+                Message::ConflictingTypes(name)
+            } else if prev.kind != NameKind::Var || (prev.init.is_some() && init.is_some()) {
+                Message::Redefinition(name)
+            } else {
+                prev.init = prev.init.or(init);
+                return Ok(());
+            };
+            // 宣言は最後まで読み終えているので、読み飛ばさずに続きから構文解析する
+            errors.push(
+                Diagnostic::error(Code::Redefinition, span, message)
+                    .with_label(prev.span, Message::PreviousDefinition),
+            );
+            return Ok(());
+        }
+        self.globals.push(GVar {
+            name,
+            typ,
+            span,
+            init,
+            kind: NameKind::Var,
+        });
+        Ok(())
+    }
+}
+
+// 型の構文解析
+impl<'g> FuncParser<'g> {
+    // token_idx番目のトークンが型名の始まりであるか判定する
+    fn is_type_name(&self, token_list: &TokenList, token_idx: usize) -> bool {
+        match token_list.tokens[token_idx].kind {
+            TokenKind::Int
+            | TokenKind::Char
+            | TokenKind::Struct
+            | TokenKind::Union
+            | TokenKind::Enum => true,
+            _ => self.typedef_at(token_list, token_idx).is_some(),
+        }
+    }
+
+    // token_idx番目のトークンがtypedefで宣言された型名であれば、その型を返す。
+    // 変数や列挙定数と同じ名前空間にあるので、内側のスコープで同じ名前の変数が宣言されていれば型名ではない
+    fn typedef_at(&self, token_list: &TokenList, token_idx: usize) -> Option<Type> {
+        let TokenKind::ID { name } = token_list.tokens[token_idx].kind else {
+            return None;
+        };
+        let name = token_list.symbols.name(name);
+        let (kind, typ) = match self.lvar_list.find_lvar(name) {
+            Some(id) => (self.lvar_list.get(id).kind, self.lvar_list.get(id).typ),
+            None => {
+                let gvar = self.globals.iter().find(|gvar| gvar.name == name)?;
+                (gvar.kind, gvar.typ)
+            }
+        };
+        (kind == NameKind::Typedef).then_some(typ)
+    }
+
+    // ty      = ("int" | "char" | struct_decl | enum_decl) "*"*
+    // 構造体・共用体の定義があればstructsに、列挙定数があれば今のスコープに追加する。メンバの構文エラーはerrorsに積む
+    fn ty(
+        &mut self,
+        token_list: &mut TokenList,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<Type, Diagnostic> {
+        let mut typ = if token_list.consume(TokenKind::Int) {
+            Type::Int(0)
+        } else if token_list.consume(TokenKind::Char) {
+            Type::Char(0)
+        } else if token_list.consume(TokenKind::Struct) {
+            self.struct_decl(token_list, TagKind::Struct, errors)?
+        } else if token_list.consume(TokenKind::Union) {
+            self.struct_decl(token_list, TagKind::Union, errors)?
+        } else if token_list.consume(TokenKind::Enum) {
+            self.enum_decl(token_list, errors)?
+        } else if let Some(typ) = self.typedef_at(token_list, token_list.now) {
+            token_list.now += 1;
+            typ
         } else {
-            // 引数が1個以上ある
-            loop {
-                token_list.expect(TokenKind::Int, None);
-                let mut nst = 0;
-                while token_list.consume(TokenKind::Reserved, Some("*")) {
-                    nst += 1;
+            return Err(unsupported_keyword(token_list).unwrap_or_else(|| {
+                token_list.error_at_now(
+                    Code::ExpectedToken,
+                    Message::ExpectedEither("'int'".to_string(), "'char'".to_string()),
+                )
+            }));
+        };
+        while token_list.consume_sign("*") {
+            if is_array(typ) {
+                return Err(Diagnostic::error(
+                    Code::Unsupported,
+                    token_list.span_from(token_list.now - 1),
+                    Message::NestedArray,
+                ));
+            }
+            typ = pointer_to(typ);
+        }
+        Ok(typ)
+    }
+
+    // type_name = ty ("[" const_expr "]")?
+    // キャストとsizeofに書かれた、名前のない型を読む
+    fn type_name(&mut self, token_list: &mut TokenList) -> Result<Type, Diagnostic> {
+        // 式の中ではエラーから回復しないので、構造体のメンバの構文エラーも返り値として返す
+        let mut errors = vec![];
+        let base = self.ty(token_list, &mut errors)?;
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e);
+        }
+        self.array_suffix(token_list, base)
+    }
+
+    // typedef = "typedef" ty ident ("[" const_expr "]")? ";"
+    // "typedef"を読んだ後から構文解析し、宣言された型名を今のスコープに追加する
+    fn typedef(
+        &mut self,
+        token_list: &mut TokenList,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<(), Diagnostic> {
+        let base = self.ty(token_list, errors)?;
+        let span = token_list.now_span();
+        let name = token_list.expect_ident()?;
+        let typ = self.array_suffix(token_list, base)?;
+        token_list.expect_sign(";")?;
+        // 不完全な構造体にも別名をつけられる
+        self.add_name(name, typ, NameKind::Typedef, span, errors);
+        Ok(())
+    }
+
+    // 型のタグを読み、メンバの定義が続く場合はそのタグを定義する構造体などのindexを返す。
+    // そうでなければ、見えている同じタグの構造体などのindexを返し、なければdeclareが真のときに限り新しく宣言する
+    fn tag(
+        &mut self,
+        token_list: &mut TokenList,
+        kind: TagKind,
+        declare: bool,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<usize, Diagnostic> {
+        let token_idx = token_list.now - 1;
+        let tag = token_list.consume_ident();
+        let span = token_list.span_from(token_idx);
+        let structs = &mut *self.structs;
+        if !token_list.get_now_token().kind.is_sign("{") {
+            let Some(tag) = tag else {
+                return Err(token_list.error_at_now(
+                    Code::ExpectedToken,
+                    Message::ExpectedEither("identifier".to_string(), "'{'".to_string()),
+                ));
+            };
+            return match structs.find_tag(&tag, false) {
+                Some(id) if structs.get(id).kind != kind => {
+                    Err(wrong_kind_of_tag(structs, id, span))
                 }
-                let arg_name = token_list.expect_ident();
-                args.push((arg_name, Type::Int(nst)));
-                if token_list.consume(TokenKind::Reserved, Some(")")) {
-                    // 引数は終わり
+                Some(id) => Ok(id),
+                None if declare => Ok(structs.add_new_struct(kind, Some(tag), span)),
+                None => Err(Diagnostic::error(
+                    Code::IncompleteType,
+                    span,
+                    Message::IncompleteType(format!("{} {}", kind.keyword(), tag)),
+                )),
+            };
+        }
+
+        // 同じスコープで宣言だけされていたものであれば、その定義になる
+        Ok(match tag {
+            Some(tag) => match structs.find_tag(&tag, true) {
+                Some(id) if structs.get(id).kind != kind => {
+                    errors.push(wrong_kind_of_tag(structs, id, span));
+                    structs.add_new_struct(kind, None, span)
+                }
+                Some(id) if !structs.get(id).defined => id,
+                Some(id) => {
+                    errors.push(
+                        Diagnostic::error(
+                            Code::Redefinition,
+                            span,
+                            Message::Redefinition(structs.type_name(id)),
+                        )
+                        .with_label(structs.get(id).span, Message::PreviousDefinition),
+                    );
+                    structs.add_new_struct(kind, None, span)
+                }
+                None => structs.add_new_struct(kind, Some(tag), span),
+            },
+            None => structs.add_new_struct(kind, None, span),
+        })
+    }
+
+    // struct_decl = ("struct" | "union") ident? ("{" (ty ident ("[" const_expr "]")? ";")* "}")?
+    // "struct"か"union"を読んだ後から構文解析する。メンバのない"struct" identは、見えている同じタグの構造体を表し、なければ新しく宣言する
+    fn struct_decl(
+        &mut self,
+        token_list: &mut TokenList,
+        kind: TagKind,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<Type, Diagnostic> {
+        let token_idx = token_list.now - 1;
+        let id = self.tag(token_list, kind, true, errors)?;
+        let span = token_list.span_from(token_idx);
+        let brace_token_idx = token_list.now;
+        if !token_list.consume_sign("{") {
+            return Ok(Type::Struct { id, level: 0 });
+        }
+
+        let mut members: Vec<Member> = vec![];
+        while !token_list.consume_sign("}") {
+            if token_list.at_eof() {
+                return Err(unclosed_brace_error(token_list, brace_token_idx));
+            }
+            match self.struct_member(token_list, errors) {
+                Ok(member) => {
+                    if let Some(prev) = members.iter().find(|m| m.name == member.name) {
+                        errors.push(
+                            Diagnostic::error(
+                                Code::Redefinition,
+                                member.span,
+                                Message::Redefinition(member.name.clone()),
+                            )
+                            .with_label(prev.span, Message::PreviousDefinition),
+                        );
+                        continue;
+                    }
+                    members.push(member);
+                }
+                Err(e) => {
+                    errors.push(e);
+                    token_list.skip_to_stmt_end();
+                }
+            }
+        }
+
+        let (size, align) = match kind {
+            TagKind::Union => union_layout(&members, self.structs),
+            _ => struct_layout(&mut members, self.structs),
+        };
+        let def = &mut self.structs.defs[id];
+        def.members = members;
+        def.size = size;
+        def.align = align;
+        def.defined = true;
+        def.span = span;
+        Ok(Type::Struct { id, level: 0 })
+    }
+
+    // 構造体・共用体のメンバの宣言を1つ構文解析する
+    fn struct_member(
+        &mut self,
+        token_list: &mut TokenList,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<Member, Diagnostic> {
+        let base = self.ty(token_list, errors)?;
+        let span = token_list.now_span();
+        let name = token_list.expect_ident()?;
+        let typ = self.array_suffix(token_list, base)?;
+        check_complete(typ, self.structs, span)?;
+        token_list.expect_sign(";")?;
+        Ok(Member {
+            name,
+            typ,
+            offset: 0,
+            span,
+        })
+    }
+
+    // enum_decl = "enum" ident? ("{" ident ("=" const_expr)? ("," ident ("=" const_expr)?)* ","? "}")?
+    // "enum"を読んだ後から構文解析する。列挙型はint型として扱う
+    fn enum_decl(
+        &mut self,
+        token_list: &mut TokenList,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Result<Type, Diagnostic> {
+        // 列挙型は宣言だけすることはできないので、列挙定数のない"enum" identは定義済みでなければならない
+        let id = self.tag(token_list, TagKind::Enum, false, errors)?;
+        if !token_list.consume_sign("{") {
+            return Ok(Type::Int(0));
+        }
+
+        // 値が指定されていない列挙定数は、直前の列挙定数の値に1を足したもの
+        let mut next = Some(0);
+        loop {
+            let start = token_list.now;
+            let span = token_list.now_span();
+            let name = token_list.expect_ident()?;
+            let val = if token_list.consume_sign("=") {
+                // 定数式でない値はエラーを積んで0とみなし、続きの列挙定数も読む
+                let expr = self.expr(token_list)?;
+                Some(const_value(&expr).unwrap_or_else(|e| {
+                    errors.push(e);
+                    0
+                }))
+            } else {
+                next
+            };
+            // 列挙定数の値はint型で表せなければならない
+            let val = match val.filter(|&val| i32::try_from(val).is_ok()) {
+                Some(val) => val,
+                None => {
+                    errors.push(Diagnostic::error(
+                        Code::EnumOutOfRange,
+                        token_list.span_from(start),
+                        Message::EnumOutOfRange(name.clone()),
+                    ));
+                    0
+                }
+            };
+            self.add_name(name, Type::Int(0), NameKind::EnumConst(val), span, errors);
+            next = val.checked_add(1);
+            if token_list.consume_sign(",") {
+                // 最後の列挙定数の後ろにも','を置ける
+                if token_list.consume_sign("}") {
                     break;
-                } else if token_list.consume(TokenKind::Reserved, Some(",")) {
-                    // 引数はまだ続く
-                    continue;
-                } else {
-                    // ここには辿り着かないはずなのでparseが失敗している
-                    error::error();
                 }
+            } else if token_list.consume_sign("}") {
+                break;
+            } else {
+                return Err(token_list.error_at_now(
+                    Code::ExpectedToken,
+                    Message::ExpectedEither("','".to_string(), "'}'".to_string()),
+                ));
             }
         }
+        self.structs.defs[id].defined = true;
+        Ok(Type::Int(0))
+    }
 
-        token_list.expect(TokenKind::Reserved, Some("{"));
+    // 列挙定数や型名など、領域を持たない名前を今のスコープに追加する。同じ名前の大域変数などがあればerrorsに積む
+    fn add_name(
+        &mut self,
+        name: String,
+        typ: Type,
+        kind: NameKind,
+        span: Span,
+        errors: &mut Vec<Diagnostic>,
+    ) {
+        if !self.top_level {
+            match self.check_redeclaration(&name, span) {
+                Ok(()) => self.lvar_list.add_name(&name, typ, kind, span),
+                Err(e) => errors.push(e),
+            }
+            return;
+        }
+        let prev_span = self
+            .globals
+            .iter()
+            .map(|gvar| (&gvar.name, gvar.span))
+            .chain(self.sigs.iter().map(|sig| (&sig.name, sig.span)))
+            .find(|(prev, _)| **prev == name)
+            .map(|(_, span)| span);
+        if let Some(prev_span) = prev_span {
+            errors.push(
+                Diagnostic::error(Code::Redefinition, span, Message::Redefinition(name))
+                    .with_label(prev_span, Message::PreviousDefinition),
+            );
+            return;
+        }
+        self.globals.push(GVar {
+            name,
+            typ,
+            span,
+            init: None,
+            kind,
+        });
+    }
 
-        let mut program = NodeList::new(&args);
-        while !token_list.consume(TokenKind::Reserved, Some("}")) {
-            let idx = program.stmt(token_list);
-            program.roots.push(idx);
+    // 宣言された変数名の後ろにある、配列の大きさの指定を読む。baseは変数名の前に書かれた型
+    fn array_suffix(&mut self, token_list: &mut TokenList, base: Type) -> Result<Type, Diagnostic> {
+        if !token_list.consume_sign("[") {
+            return Ok(base);
         }
+        let token_idx = token_list.now;
+        let size = self.const_expr(token_list)?;
+        if size < 0 {
+            return Err(Diagnostic::error(
+                Code::InvalidArraySize,
+                token_list.span_from(token_idx),
+                Message::NegativeArraySize,
+            ));
+        }
+        let size = size as usize;
+        token_list.expect_sign("]")?;
+        if is_array(base) {
+            return Err(Diagnostic::error(
+                Code::Unsupported,
+                token_list.span_from(token_idx - 1),
+                Message::NestedArray,
+            ));
+        }
+        Ok(match base {
+            Type::Int(level) => Type::IntArr(Array { size, level }),
+            Type::Char(level) => Type::CharArr(Array { size, level }),
+            Type::Struct { id, level } => Type::StructArr {
+                id,
+                arr: Array { size, level },
+            },
+            _ => unreachable!(),
+        })
+    }
 
-        Func {
-            program,
-            args,
-            name: func_name,
+    // const_expr = expr
+    // 値がコンパイル時に決まる式を読み、その値を返す
+    fn const_expr(&mut self, token_list: &mut TokenList) -> Result<isize, Diagnostic> {
+        let expr = self.expr(token_list)?;
+        const_value(&expr)
+    }
+}
+
+// typが不完全な構造体(の配列)であればエラーにする。spanはその型を使った位置
+fn check_complete(typ: Type, structs: &StructList, span: Span) -> Result<(), Diagnostic> {
+    let id = match typ {
+        Type::Struct { id, level: 0 } => id,
+        Type::StructArr { id, arr } if arr.level == 0 => id,
+        _ => return Ok(()),
+    };
+    if structs.get(id).defined {
+        return Ok(());
+    }
+    Err(Diagnostic::error(
+        Code::IncompleteType,
+        span,
+        Message::IncompleteType(structs.type_name(id)),
+    )
+    .with_label(structs.get(id).span, Message::DeclaredHere))
+}
+
+// 構造体でない値exprのメンバを参照しようとしたときのエラーを作る。opはメンバの参照に使った演算子
+fn not_a_struct(expr: &Expr, span: Span, op: &str, structs: &StructList) -> Diagnostic {
+    Diagnostic::error(
+        Code::InvalidMemberAccess,
+        span,
+        Message::NotAStruct(op.to_string()),
+    )
+    .with_label(
+        expr.span,
+        Message::ValueOfType(type_to_string(expr.typ, structs)),
+    )
+}
+// 定数式でなければならない式exprの値を計算する。定数式でない場合はエラーにする
+fn const_value(expr: &Expr) -> Result<isize, Diagnostic> {
+    eval_const(expr)
+        .ok_or_else(|| Diagnostic::error(Code::NotConstant, expr.span, Message::NotConstant))
+}
+
+// 種類の違う型のタグを使ったときのエラーを作る。idは先に宣言されていた方の構造体など
+fn wrong_kind_of_tag(structs: &StructList, id: usize, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        Code::Redefinition,
+        span,
+        Message::WrongKindOfTag(structs.type_name(id)),
+    )
+    .with_label(structs.get(id).span, Message::PreviousDefinition)
+}
+
+// 定数valをtyp型に変換した値を返す。キャストと同じく、上位のビットを捨てて符号拡張し直す
+fn convert_const(val: isize, typ: Type) -> isize {
+    match typ {
+        Type::Char(0) => val as i8 as isize,
+        Type::Int(0) => val as i32 as isize,
+        _ => val,
+    }
+}
+
+// 定数式exprの値を計算する。定数式でない場合はNoneを返す
+pub fn eval_const(expr: &Expr) -> Option<isize> {
+    match &expr.kind {
+        ExprKind::Num(val) => Some(*val),
+        ExprKind::Cast(operand) => eval_const(operand).map(|val| convert_const(val, expr.typ)),
+        ExprKind::Binary { op, lhs, rhs } => {
+            let (lhs, rhs) = (eval_const(lhs)?, eval_const(rhs)?);
+            match op {
+                BinOp::Add => lhs.checked_add(rhs),
+                BinOp::Sub => lhs.checked_sub(rhs),
+                BinOp::Mul => lhs.checked_mul(rhs),
+                BinOp::Div => lhs.checked_div(rhs),
+                BinOp::Eq => Some((lhs == rhs) as isize),
+                BinOp::Ne => Some((lhs != rhs) as isize),
+                BinOp::Lt => Some((lhs < rhs) as isize),
+                BinOp::Le => Some((lhs <= rhs) as isize),
+            }
         }
+        _ => None,
+    }
+}
+
+// 今着目しているトークンがまだサポートしていないキーワードであれば、その位置のエラーを作る
+fn unsupported_keyword(token_list: &TokenList) -> Option<Diagnostic> {
+    let kind = &token_list.get_now_token().kind;
+    match kind {
+        TokenKind::If
+        | TokenKind::Else
+        | TokenKind::While
+        | TokenKind::For
+        | TokenKind::Return
+        | TokenKind::Sizeof
+        | TokenKind::Int
+        | TokenKind::Char
+        | TokenKind::Struct
+        | TokenKind::Union
+        | TokenKind::Enum
+        | TokenKind::Typedef => None,
+        _ => kind.keyword().map(|word| {
+            token_list.error_at_now(
+                Code::Unsupported,
+                Message::UnsupportedKeyword(word.to_string()),
+            )
+        }),
+    }
+}
+
+// open_token_idx番目のトークンの'{'が閉じられないまま入力が終わったときのエラーを作る
+fn unclosed_brace_error(token_list: &TokenList, open_token_idx: usize) -> Diagnostic {
+    let open_span = token_list.tokens[open_token_idx].span;
+    token_list
+        .error_at_now(Code::ExpectedToken, Message::Expected("'}'".to_string()))
+        .with_label(open_span, Message::UnclosedBrace)
+}
+
+// プログラム全体を構文解析し、構文解析中に見つかった警告とともに返す。構文エラーがあった場合は、見つかった全てのエラーと警告を返す
+pub fn parse_program(
+    token_list: &mut TokenList,
+) -> Result<(Program, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let mut program = Program::default();
+    let mut errors = vec![];
+    while !token_list.at_eof() {
+        if let Err(e) = program.top_level(token_list, &mut errors) {
+            errors.push(e);
+            token_list.skip_to_func_end();
+        }
+    }
+    if errors
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        Err(errors)
+    } else {
+        Ok((program, errors))
     }
 }
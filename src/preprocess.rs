@@ -0,0 +1,1262 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    common::{SourceFile, SourceMap, Span},
+    error::{Code, Diagnostic},
+    lexer::{self, Lexer, NumType, Token, TokenKind, TokenList},
+    message::Message,
+    symbol::{HideSet, Symbol, SymbolTable},
+    vfs::FileSystem,
+};
+
+// #includeの入れ子の深さの上限
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+// マクロの定義
+#[derive(Debug)]
+enum Macro {
+    // オブジェクト形式マクロ。置き換え後のトークン列を持つ
+    Object(Vec<Token>),
+    // 関数形式マクロ。可変長引数をとる場合は、paramsの最後が__VA_ARGS__になる
+    Function {
+        params: Vec<Symbol>,
+        variadic: bool,
+        body: Vec<Token>,
+    },
+    // 使った位置によって置き換え後が変わる組み込みのマクロ
+    File,
+    Line,
+}
+
+// 関数形式マクロの呼び出しの実引数。引数ごとにトークン列を持つ
+type Args = Vec<Vec<Token>>;
+
+// マクロ展開で読んでいるトークン列
+struct Stream {
+    pending: Vec<Token>, // ソースファイルより先に読むトークン。最後の要素から順に読む
+    files: bool,         // pendingを読み終えた後に、ソースファイルから続きを読むか
+}
+
+// #if, #ifdef, #ifndefから#endifまでの条件付きの部分
+#[derive(Debug)]
+struct Conditional {
+    directive: &'static str, // 始めた指令の名前
+    span: Span,              // 始めた指令の位置
+    active: bool,            // 今のグループを読むか
+    taken: bool,             // すでにいずれかのグループを読んだか
+    else_span: Option<Span>, // #elseの位置
+}
+
+// 読んでいる途中のソースファイル
+struct Frame {
+    lexer: Lexer,
+    path: String,        // ファイルを探したときのパス
+    conditionals: usize, // このファイルを読み始めたときの、条件付きの部分の入れ子の深さ
+}
+
+// ソースファイルを前処理し、トークン列にする
+pub struct Preprocessor<'a> {
+    fs: &'a dyn FileSystem,
+    sources: &'a mut SourceMap, // #includeしたファイルもここに追加する
+    include_paths: &'a [String],
+    symbols: SymbolTable,
+    macros: HashMap<Symbol, Rc<Macro>>,
+    once: Vec<String>,  // #pragma onceが書かれたファイルのパス
+    frames: Vec<Frame>, // #includeで読んでいるファイルのスタック。最後が今読んでいるファイル
+    conditionals: Vec<Conditional>,
+    errors: Vec<Diagnostic>,
+}
+impl<'a> Preprocessor<'a> {
+    pub fn new(
+        fs: &'a dyn FileSystem,
+        sources: &'a mut SourceMap,
+        include_paths: &'a [String],
+    ) -> Self {
+        Preprocessor {
+            fs,
+            sources,
+            include_paths,
+            symbols: SymbolTable::new(),
+            macros: HashMap::new(),
+            once: vec![],
+            frames: vec![],
+            conditionals: vec![],
+            errors: vec![],
+        }
+    }
+
+    // file番目のソースファイルを前処理してトークン列にする。
+    // definesは-D, -Uで指定されたマクロで、-Dは(名前, Some(値))、-Uは(名前, None)として順に並ぶ
+    pub fn run(
+        mut self,
+        file: u32,
+        defines: &[(String, Option<String>)],
+    ) -> Result<TokenList, Vec<Diagnostic>> {
+        self.predefine();
+        let path = self.sources.get(file).unwrap().name.clone();
+        self.push_file(file, path);
+        // コマンドラインで指定されたマクロは、ソースファイルより先に読む仮想的なファイルとして扱う
+        if !defines.is_empty() {
+            let text = defines
+                .iter()
+                .map(|(name, value)| match value {
+                    Some(value) => format!("#define {} {}\n", name, value),
+                    None => format!("#undef {}\n", name),
+                })
+                .collect::<String>();
+            let name = "<command line>";
+            let id = self.sources.add(SourceFile::new(name, &text));
+            self.push_file(id, name.to_string());
+        }
+
+        let mut tokens = vec![];
+        if let Err(e) = self.read(&mut tokens) {
+            self.errors.push(e);
+        }
+        if self.errors.is_empty() {
+            Ok(TokenList::new(tokens, self.symbols))
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    // 組み込みのマクロを定義する
+    fn predefine(&mut self) {
+        let token = |kind| Token {
+            span: Span::default(),
+            kind,
+            bol: false,
+            space: false,
+            hideset: HideSet::default(),
+        };
+        let macros = [
+            ("__FILE__", Macro::File),
+            ("__LINE__", Macro::Line),
+            (
+                "__DATE__",
+                Macro::Object(vec![token(TokenKind::Str {
+                    val: today().into_bytes(),
+                })]),
+            ),
+            (
+                "__STDC__",
+                Macro::Object(vec![token(TokenKind::Num {
+                    val: 1,
+                    typ: NumType::Int,
+                })]),
+            ),
+            (
+                "__STDC_VERSION__",
+                Macro::Object(vec![token(TokenKind::Num {
+                    val: 201112,
+                    typ: NumType::Long,
+                })]),
+            ),
+        ];
+        for (name, mac) in macros {
+            let name = self.symbols.intern(name);
+            self.macros.insert(name, Rc::new(mac));
+        }
+    }
+
+    // id番目のソースファイルを読み始める
+    fn push_file(&mut self, id: u32, path: String) {
+        let text = Rc::clone(&self.sources.get(id).unwrap().text);
+        self.frames.push(Frame {
+            lexer: Lexer::new(id, text),
+            path,
+            conditionals: self.conditionals.len(),
+        });
+    }
+
+    fn lexer(&mut self) -> &mut Lexer {
+        &mut self.frames.last_mut().unwrap().lexer
+    }
+
+    // 今のグループを読むか
+    fn is_active(&self) -> bool {
+        self.conditionals.last().is_none_or(|c| c.active)
+    }
+
+    // 全てのファイルを読み終わるまで前処理を続ける。字句解析に失敗した場合はそこで止める
+    fn read(&mut self, out: &mut Vec<Token>) -> Result<(), Diagnostic> {
+        let mut stream = Stream {
+            pending: vec![],
+            files: true,
+        };
+        while let Some(token) = self.next(&mut stream)? {
+            if !self.expand_macro(&token, &mut stream)? {
+                out.push(token);
+            }
+        }
+        Ok(())
+    }
+
+    // ソースファイルから、前処理指令を処理しながらトークンを1つ読む。
+    // #includeしたファイルの終わりは読み飛ばし、最初のファイルの終わりだけをEofとして返す
+    fn read_token(&mut self) -> Result<Token, Diagnostic> {
+        loop {
+            let active = self.is_active();
+            let frame = self.frames.last_mut().unwrap();
+            if !active {
+                frame.lexer.skip_group()?;
+            }
+            let token = frame.lexer.next_token(&mut self.symbols)?;
+            if token.kind == TokenKind::Eof {
+                self.end_of_file();
+                if self.frames.is_empty() {
+                    return Ok(token);
+                }
+            } else if token.bol && token.kind.is_sign("#") {
+                // 前処理指令。エラーがあった場合は報告して、次の行から前処理を続ける
+                let depth = self.frames.len();
+                if let Err(e) = self.directive(&token) {
+                    self.errors.push(e);
+                }
+                self.frames[depth - 1].lexer.rest_of_line()?;
+            } else {
+                return Ok(token);
+            }
+        }
+    }
+
+    // streamから次のトークンを読む。読むものがなくなったときはNoneを返す
+    fn next(&mut self, stream: &mut Stream) -> Result<Option<Token>, Diagnostic> {
+        if let Some(token) = stream.pending.pop() {
+            return Ok(Some(token));
+        }
+        if stream.files && !self.frames.is_empty() {
+            return self.read_token().map(Some);
+        }
+        Ok(None)
+    }
+
+    // 今読んでいるファイルを読み終える
+    fn end_of_file(&mut self) {
+        let frame = self.frames.pop().unwrap();
+        while self.conditionals.len() > frame.conditionals {
+            let conditional = self.conditionals.pop().unwrap();
+            self.errors.push(Diagnostic::error(
+                Code::UnterminatedConditional,
+                conditional.span,
+                Message::UnterminatedConditional(conditional.directive.to_string()),
+            ));
+        }
+    }
+
+    // 識別子とキーワードの名前を返す
+    fn token_name(&self, token: &Token) -> Option<&str> {
+        match &token.kind {
+            TokenKind::ID { name } => Some(self.symbols.name(*name)),
+            kind => kind.keyword(),
+        }
+    }
+
+    // 識別子とキーワードを、マクロ名として使うときの名前を返す
+    fn macro_symbol(&mut self, token: &Token) -> Option<Symbol> {
+        match &token.kind {
+            TokenKind::ID { name } => Some(*name),
+            kind => kind.keyword().map(|word| self.symbols.intern(word)),
+        }
+    }
+
+    // 今の行に残っているトークンを1つ読む。行末に達したときはNoneを返す
+    fn next_token_in_line(&mut self) -> Result<Option<Token>, Diagnostic> {
+        let frame = self.frames.last_mut().unwrap();
+        frame.lexer.next_token_in_line(&mut self.symbols)
+    }
+
+    // 今の行に残っているトークンを全て読む
+    fn line_tokens(&mut self) -> Result<Vec<Token>, Diagnostic> {
+        let mut tokens = vec![];
+        while let Some(token) = self.next_token_in_line()? {
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    // tokenがマクロであれば展開し、結果をstreamの先頭に戻して真を返す。
+    // 展開結果は後に続くトークンと合わせて読み直されるので、その中のマクロもまた展開される。
+    // マクロの使い方が正しくない場合は報告して、呼び出しを読み飛ばす
+    fn expand_macro(&mut self, token: &Token, stream: &mut Stream) -> Result<bool, Diagnostic> {
+        let Some(name) = self.macro_symbol(token) else {
+            return Ok(false);
+        };
+        // 自分自身の展開結果の中では、もう展開しない
+        if token.hideset.contains(name) {
+            return Ok(false);
+        }
+        let Some(mac) = self.macros.get(&name).cloned() else {
+            return Ok(false);
+        };
+        let expanded = match &*mac {
+            Macro::Object(body) => {
+                let hideset = token.hideset.with(name);
+                self.substitute(body, &[], &[], token, &hideset)
+            }
+            Macro::Function {
+                params,
+                variadic,
+                body,
+            } => {
+                // 名前の後に'('が続かなければ、関数形式マクロの呼び出しではない
+                match self.next(stream)? {
+                    Some(paren) if paren.kind.is_sign("(") => {}
+                    next => {
+                        stream.pending.extend(next);
+                        return Ok(false);
+                    }
+                }
+                let Some((args, rparen)) =
+                    self.read_args(token, params.len(), *variadic, stream)?
+                else {
+                    return Ok(true);
+                };
+                let args = match self.check_args(token, params.len(), *variadic, args) {
+                    Ok(args) => args,
+                    Err(e) => {
+                        self.errors.push(e);
+                        return Ok(true);
+                    }
+                };
+                let mut call = token.clone();
+                if rparen.span.file == token.span.file {
+                    call.span = token.span.to(rparen.span);
+                }
+                let hideset = token.hideset.intersection(&rparen.hideset).with(name);
+                self.substitute(body, params, &args, &call, &hideset)
+            }
+            Macro::File => {
+                let file = self.sources.get(token.span.file).unwrap();
+                Ok(vec![Token {
+                    kind: TokenKind::Str {
+                        val: file.name.clone().into_bytes(),
+                    },
+                    hideset: token.hideset.with(name),
+                    ..token.clone()
+                }])
+            }
+            Macro::Line => {
+                let file = self.sources.get(token.span.file).unwrap();
+                let (row, _) = file.line_col(token.span.start);
+                Ok(vec![Token {
+                    kind: TokenKind::Num {
+                        val: row as isize + 1,
+                        typ: NumType::Int,
+                    },
+                    hideset: token.hideset.with(name),
+                    ..token.clone()
+                }])
+            }
+        };
+        match expanded {
+            Ok(tokens) => stream.pending.extend(tokens.into_iter().rev()),
+            Err(e) => self.errors.push(e),
+        }
+        Ok(true)
+    }
+
+    // 関数形式マクロtokenの呼び出しを'('の後から対応する')'まで読み、引数ごとに分けたトークン列と')'を返す。
+    // 可変長引数のマクロでは、最後の引数は','も含めて残り全てとする。')'がない場合は報告してNoneを返す
+    fn read_args(
+        &mut self,
+        token: &Token,
+        params: usize,
+        variadic: bool,
+        stream: &mut Stream,
+    ) -> Result<Option<(Args, Token)>, Diagnostic> {
+        let mut args = vec![vec![]];
+        let mut depth = 0;
+        loop {
+            let arg = match self.next(stream)? {
+                Some(arg) if arg.kind != TokenKind::Eof => arg,
+                eof => {
+                    stream.pending.extend(eof);
+                    self.errors.push(Diagnostic::error(
+                        Code::MacroExpansion,
+                        token.span,
+                        Message::UnterminatedMacroCall(self.spell(token)),
+                    ));
+                    return Ok(None);
+                }
+            };
+            if arg.kind.is_sign("(") {
+                depth += 1;
+            } else if arg.kind.is_sign(")") {
+                if depth == 0 {
+                    return Ok(Some((args, arg)));
+                }
+                depth -= 1;
+            } else if arg.kind.is_sign(",") && depth == 0 && !(variadic && args.len() == params) {
+                args.push(vec![]);
+                continue;
+            }
+            args.last_mut().unwrap().push(arg);
+        }
+    }
+
+    // 関数形式マクロtokenに渡された引数の数を確かめる。可変長引数が省略された場合は空の引数を補う
+    fn check_args(
+        &self,
+        token: &Token,
+        params: usize,
+        variadic: bool,
+        mut args: Args,
+    ) -> Result<Args, Diagnostic> {
+        // F()は、引数をとらないマクロでは0個の引数の呼び出しとみなす
+        if params == 0 && args.len() == 1 && args[0].is_empty() {
+            args.clear();
+        }
+        if variadic && args.len() == params - 1 {
+            args.push(vec![]);
+        }
+        if args.len() != params {
+            return Err(Diagnostic::error(
+                Code::MacroExpansion,
+                token.span,
+                Message::MacroArgCount {
+                    name: self.spell(token),
+                    expected: params - variadic as usize,
+                    given: args.len(),
+                    variadic,
+                },
+            ));
+        }
+        Ok(args)
+    }
+
+    // tokenの位置で使ったマクロの置き換え後のトークン列bodyについて、仮引数paramsを実引数argsで置き換え、
+    // '#'と'##'を処理する。hidesetは展開結果のトークンに加える、もう展開してはいけないマクロの名前
+    fn substitute(
+        &mut self,
+        body: &[Token],
+        params: &[Symbol],
+        args: &[Vec<Token>],
+        token: &Token,
+        hideset: &HideSet,
+    ) -> Result<Vec<Token>, Diagnostic> {
+        let mut out: Vec<Token> = vec![];
+        // 直前に置いたのが空の引数で、続く'##'でつなげる相手がないか
+        let mut placemarker = false;
+        let mut idx = 0;
+        while idx < body.len() {
+            let now = &body[idx];
+            // '#'の後の引数は、展開せずに文字列にする
+            if now.kind.is_sign("#") {
+                if let Some(param) = self.param_index(body.get(idx + 1), params) {
+                    out.push(self.stringize(&args[param], token.span, now.space));
+                    placemarker = false;
+                    idx += 2;
+                    continue;
+                }
+            }
+            // '##'の両側のトークンを1つのトークンにする。引数は展開せずに使う
+            if now.kind.is_sign("##") {
+                let rhs = match self.param_index(body.get(idx + 1), params) {
+                    Some(param) => args[param].clone(),
+                    None => vec![at(&body[idx + 1], token.span)],
+                };
+                idx += 2;
+                if placemarker {
+                    placemarker = rhs.is_empty();
+                    out.extend(rhs);
+                    continue;
+                }
+                let mut rhs = rhs.into_iter();
+                if let Some(first) = rhs.next() {
+                    let lhs = out.pop().unwrap();
+                    out.push(self.paste(&lhs, &first)?);
+                    out.extend(rhs);
+                }
+                continue;
+            }
+            if let Some(param) = self.param_index(Some(now), params) {
+                let mut arg = if body
+                    .get(idx + 1)
+                    .is_some_and(|next| next.kind.is_sign("##"))
+                {
+                    placemarker = args[param].is_empty();
+                    args[param].clone()
+                } else {
+                    placemarker = false;
+                    self.expand_tokens(args[param].clone())?
+                };
+                if let Some(first) = arg.first_mut() {
+                    first.space = now.space;
+                }
+                out.extend(arg);
+                idx += 1;
+                continue;
+            }
+            out.push(at(now, token.span));
+            placemarker = false;
+            idx += 1;
+        }
+
+        if let Some(first) = out.first_mut() {
+            first.space = token.space;
+        }
+        for token in out.iter_mut() {
+            token.hideset = token.hideset.union(hideset);
+        }
+        Ok(out)
+    }
+
+    // tokenが仮引数であれば、その番号を返す
+    fn param_index(&mut self, token: Option<&Token>, params: &[Symbol]) -> Option<usize> {
+        if params.is_empty() {
+            return None;
+        }
+        let name = self.macro_symbol(token?)?;
+        params.iter().position(|param| *param == name)
+    }
+
+    // tokensの中のマクロを、後に続くソースコードを読まずに全て展開する
+    fn expand_tokens(&mut self, tokens: Vec<Token>) -> Result<Vec<Token>, Diagnostic> {
+        let mut stream = Stream {
+            pending: tokens.into_iter().rev().collect(),
+            files: false,
+        };
+        let mut out = vec![];
+        while let Some(token) = self.next(&mut stream)? {
+            if !self.expand_macro(&token, &mut stream)? {
+                out.push(token);
+            }
+        }
+        Ok(out)
+    }
+
+    // 引数のトークン列を、書かれたとおりの文字列リテラルにする
+    fn stringize(&self, arg: &[Token], span: Span, space: bool) -> Token {
+        let mut text = String::new();
+        for (i, token) in arg.iter().enumerate() {
+            if i > 0 && token.space {
+                text.push(' ');
+            }
+            text.push_str(&self.spell(token));
+        }
+        Token {
+            span,
+            kind: TokenKind::Str {
+                val: text.into_bytes(),
+            },
+            bol: false,
+            space,
+            hideset: HideSet::default(),
+        }
+    }
+
+    // lhsとrhsをつなげて1つのトークンにする
+    fn paste(&mut self, lhs: &Token, rhs: &Token) -> Result<Token, Diagnostic> {
+        let (lhs_text, rhs_text) = (self.spell(lhs), self.spell(rhs));
+        let text = format!("{}{}", lhs_text, rhs_text);
+        let mut lexer = Lexer::new(lhs.span.file, Rc::from(text.as_str()));
+        // つなげた結果が、ちょうど1つのトークンになる場合だけを認める
+        let pasted = match lexer.next_token(&mut self.symbols) {
+            Ok(token) if token.kind != TokenKind::Eof => lexer
+                .next_token(&mut self.symbols)
+                .is_ok_and(|eof| eof.kind == TokenKind::Eof)
+                .then_some(token.kind),
+            _ => None,
+        };
+        let Some(kind) = pasted else {
+            return Err(Diagnostic::error(
+                Code::MacroExpansion,
+                lhs.span,
+                Message::InvalidPaste(lhs_text, rhs_text),
+            ));
+        };
+        // どちらもソースコードに書かれたトークンであれば、両方を含む範囲をつなげた結果の位置とする
+        let mut span = lhs.span;
+        if rhs.span.file == lhs.span.file && rhs.span.start >= lhs.span.end {
+            span = lhs.span.to(rhs.span);
+        }
+        Ok(Token {
+            span,
+            kind,
+            ..lhs.clone()
+        })
+    }
+
+    // トークンをソースコードに書く形にする。ソースファイルから読んだままのリテラルは、書かれたとおりにする
+    fn spell(&self, token: &Token) -> String {
+        let source = self
+            .sources
+            .get(token.span.file)
+            .filter(|_| token.hideset.is_empty())
+            .and_then(|file| {
+                file.text
+                    .get(token.span.start as usize..token.span.end as usize)
+            });
+        match &token.kind {
+            TokenKind::Reserved { sign } => sign.to_string(),
+            TokenKind::ID { name } => self.symbols.name(*name).to_string(),
+            TokenKind::Num { .. } | TokenKind::CharLit { .. } | TokenKind::Str { .. }
+                if source.is_some() =>
+            {
+                lexer::remove_splices(source.unwrap())
+            }
+            TokenKind::Num { val, typ } => {
+                let suffix = match typ {
+                    NumType::Int => "",
+                    NumType::UInt => "u",
+                    NumType::Long => "l",
+                    NumType::ULong => "ul",
+                    NumType::LongLong => "ll",
+                    NumType::ULongLong => "ull",
+                };
+                format!("{}{}", val, suffix)
+            }
+            TokenKind::CharLit { val } => format!("'{}'", escape(&[*val as u8], b'\'')),
+            TokenKind::Str { val } => format!("\"{}\"", escape(val, b'"')),
+            TokenKind::Eof => String::new(),
+            kind => kind.keyword().unwrap_or("").to_string(),
+        }
+    }
+
+    // hashから始まる前処理指令を処理する
+    fn directive(&mut self, hash: &Token) -> Result<(), Diagnostic> {
+        let Some(name_token) = self.next_token_in_line()? else {
+            // '#'だけの行は何もしない
+            return Ok(());
+        };
+        let span = hash.span.to(name_token.span);
+        let name = self.token_name(&name_token).unwrap_or("").to_string();
+        let active = self.is_active();
+        match name.as_str() {
+            "if" | "ifdef" | "ifndef" => {
+                let directive = match name.as_str() {
+                    "if" => "if",
+                    "ifdef" => "ifdef",
+                    _ => "ifndef",
+                };
+                // 読み飛ばしている部分の中では、条件を評価せずに入れ子の深さだけを数える
+                let cond = if !active {
+                    Ok(None)
+                } else if directive == "if" {
+                    self.eval_condition(span).map(Some)
+                } else {
+                    self.is_defined(span)
+                        .map(|defined| Some(defined == (directive == "ifdef")))
+                };
+                // 条件式が正しくない場合は、どのグループも読まない
+                let taken = cond.as_ref().map_or(true, |cond| cond.unwrap_or(true));
+                self.conditionals.push(Conditional {
+                    directive,
+                    span,
+                    active: matches!(cond, Ok(Some(true))),
+                    taken,
+                    else_span: None,
+                });
+                cond.map(|_| ())
+            }
+            "elif" => {
+                let conditional = self.current_conditional(&name, span)?;
+                conditional.active = false;
+                if conditional.taken {
+                    return Ok(());
+                }
+                let cond = self.eval_condition(span)?;
+                let conditional = self.conditionals.last_mut().unwrap();
+                conditional.active = cond;
+                conditional.taken = cond;
+                Ok(())
+            }
+            "else" => {
+                let conditional = self.current_conditional(&name, span)?;
+                conditional.active = !conditional.taken;
+                conditional.taken = true;
+                conditional.else_span = Some(span);
+                Ok(())
+            }
+            "endif" => {
+                self.current_conditional(&name, span)?;
+                self.conditionals.pop();
+                Ok(())
+            }
+            _ if !active => Ok(()),
+            "include" => self.include(span),
+            "define" => self.define(span),
+            "undef" => {
+                let name = self.macro_name(span)?;
+                self.macros.remove(&name);
+                Ok(())
+            }
+            "error" => {
+                let text_span = self.lexer().rest_of_line()?;
+                let text = self.lexer().slice(text_span).to_string();
+                Err(Diagnostic::error(
+                    Code::ErrorDirective,
+                    span.to(text_span),
+                    Message::ErrorDirective(text),
+                ))
+            }
+            "pragma" => {
+                let tokens = self.line_tokens()?;
+                if tokens.first().and_then(|token| self.token_name(token)) == Some("once") {
+                    let path = self.frames.last().unwrap().path.clone();
+                    self.once.push(path);
+                }
+                // それ以外の#pragmaは無視する
+                Ok(())
+            }
+            _ => {
+                let name = self.lexer().slice(name_token.span).to_string();
+                Err(Diagnostic::error(
+                    Code::InvalidDirective,
+                    span,
+                    Message::UnknownDirective(name),
+                ))
+            }
+        }
+    }
+
+    // #elif, #else, #endifが対応する条件付きの部分を返す。spanは指令の位置
+    fn current_conditional(
+        &mut self,
+        name: &str,
+        span: Span,
+    ) -> Result<&mut Conditional, Diagnostic> {
+        // 他のファイルで始まった#ifには対応させない
+        let start = self.frames.last().unwrap().conditionals;
+        if self.conditionals.len() <= start {
+            return Err(Diagnostic::error(
+                Code::InvalidDirective,
+                span,
+                Message::UnmatchedConditional(name.to_string()),
+            ));
+        }
+        let conditional = self.conditionals.last_mut().unwrap();
+        if let (Some(else_span), true) = (conditional.else_span, name != "endif") {
+            return Err(Diagnostic::error(
+                Code::InvalidDirective,
+                span,
+                Message::DirectiveAfterElse(name.to_string()),
+            )
+            .with_label(else_span, Message::ElseHere));
+        }
+        Ok(conditional)
+    }
+
+    // 指令の後に書かれたマクロ名を読む。spanは指令の位置
+    fn macro_name(&mut self, span: Span) -> Result<Symbol, Diagnostic> {
+        let token = self.next_token_in_line()?;
+        match token.as_ref().and_then(|token| self.macro_symbol(token)) {
+            Some(name) => Ok(name),
+            None => Err(Diagnostic::error(
+                Code::InvalidDirective,
+                token.map_or(span, |token| token.span),
+                Message::MissingMacroName,
+            )),
+        }
+    }
+
+    // #ifdef, #ifndefの後に書かれたマクロが定義されているか
+    fn is_defined(&mut self, span: Span) -> Result<bool, Diagnostic> {
+        let name = self.macro_name(span)?;
+        Ok(self.macros.contains_key(&name))
+    }
+
+    // #define
+    fn define(&mut self, span: Span) -> Result<(), Diagnostic> {
+        let name_token = self.next_token_in_line()?.ok_or_else(|| {
+            Diagnostic::error(Code::InvalidDirective, span, Message::MissingMacroName)
+        })?;
+        let name = self.macro_symbol(&name_token).ok_or_else(|| {
+            Diagnostic::error(
+                Code::InvalidDirective,
+                name_token.span,
+                Message::MissingMacroName,
+            )
+        })?;
+        let mut body = self.line_tokens()?;
+        // マクロ名の直後に'('がある場合は関数形式マクロ
+        let mac = if body
+            .first()
+            .is_some_and(|token| token.kind.is_sign("(") && token.span.start == name_token.span.end)
+        {
+            let (params, variadic, len) = self.macro_params(&body)?;
+            let body = body.split_off(len);
+            self.check_body(&body, &params)?;
+            Macro::Function {
+                params,
+                variadic,
+                body,
+            }
+        } else {
+            self.check_body(&body, &[])?;
+            Macro::Object(body)
+        };
+        self.macros.insert(name, Rc::new(mac));
+        Ok(())
+    }
+
+    // 関数形式マクロの'('から')'までの仮引数を読み、仮引数の名前と、可変長引数をとるかと、')'までのトークンの数を返す
+    fn macro_params(&mut self, tokens: &[Token]) -> Result<(Vec<Symbol>, bool, usize), Diagnostic> {
+        let error = |idx: usize| {
+            Diagnostic::error(
+                Code::InvalidDirective,
+                tokens
+                    .get(idx)
+                    .map_or(tokens[idx - 1].span.end_point(), |token| token.span),
+                Message::InvalidMacroParams,
+            )
+        };
+        let mut params = vec![];
+        let mut variadic = false;
+        let mut idx = 1;
+        if tokens.get(idx).is_some_and(|token| token.kind.is_sign(")")) {
+            return Ok((params, variadic, idx + 1));
+        }
+        loop {
+            let token = tokens.get(idx);
+            if token.is_some_and(|token| token.kind.is_sign("...")) {
+                variadic = true;
+                params.push(self.symbols.intern("__VA_ARGS__"));
+            } else {
+                match token.and_then(|token| self.macro_symbol(token)) {
+                    Some(param) if !params.contains(&param) => params.push(param),
+                    _ => return Err(error(idx)),
+                }
+            }
+            idx += 1;
+            match tokens.get(idx) {
+                Some(token) if token.kind.is_sign(")") => return Ok((params, variadic, idx + 1)),
+                Some(token) if token.kind.is_sign(",") && !variadic => idx += 1,
+                _ => return Err(error(idx)),
+            }
+        }
+    }
+
+    // マクロの置き換え後のトークン列bodyで、'#'と'##'が正しく使われているかを確かめる
+    fn check_body(&mut self, body: &[Token], params: &[Symbol]) -> Result<(), Diagnostic> {
+        for token in [body.first(), body.last()].into_iter().flatten() {
+            if token.kind.is_sign("##") {
+                return Err(Diagnostic::error(
+                    Code::InvalidDirective,
+                    token.span,
+                    Message::PasteAtEdge,
+                ));
+            }
+        }
+        // 関数形式マクロでは、'#'の後には仮引数が必要
+        if params.is_empty() {
+            return Ok(());
+        }
+        for (idx, token) in body.iter().enumerate() {
+            if token.kind.is_sign("#") && self.param_index(body.get(idx + 1), params).is_none() {
+                return Err(Diagnostic::error(
+                    Code::InvalidDirective,
+                    token.span,
+                    Message::StringifyNonParam,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // #include。spanは指令の位置
+    fn include(&mut self, span: Span) -> Result<(), Diagnostic> {
+        let name_span = self.lexer().rest_of_line()?;
+        let text = self.lexer().slice(name_span);
+        // "ファイル名"か<ファイル名>
+        let header = match text.as_bytes().first() {
+            Some(b'"') => text[1..].find('"').map(|end| (&text[1..end + 1], true)),
+            Some(b'<') => text[1..].find('>').map(|end| (&text[1..end + 1], false)),
+            _ => None,
+        };
+        let Some((name, quoted)) = header else {
+            return Err(Diagnostic::error(
+                Code::InvalidDirective,
+                if text.is_empty() { span } else { name_span },
+                Message::ExpectedHeaderName,
+            ));
+        };
+        let name = name.to_string();
+        if self.frames.len() > MAX_INCLUDE_DEPTH {
+            return Err(Diagnostic::error(
+                Code::InvalidDirective,
+                span.to(name_span),
+                Message::IncludeTooDeep(MAX_INCLUDE_DEPTH),
+            ));
+        }
+
+        // "ファイル名"は今読んでいるファイルと同じディレクトリから探し、次に-Iで指定されたディレクトリから探す
+        let mut dirs = vec![];
+        if quoted {
+            let current = &self.frames.last().unwrap().path;
+            dirs.push(
+                Path::new(current)
+                    .parent()
+                    .map_or_else(PathBuf::new, Path::to_path_buf),
+            );
+        }
+        dirs.extend(self.include_paths.iter().map(PathBuf::from));
+        let found = dirs.iter().find_map(|dir| {
+            let path = dir.join(&name).to_string_lossy().into_owned();
+            self.fs.read_to_string(&path).ok().map(|text| (path, text))
+        });
+        let Some((path, text)) = found else {
+            return Err(Diagnostic::error(
+                Code::IncludeNotFound,
+                name_span,
+                Message::IncludeNotFound(name),
+            ));
+        };
+        if self.once.contains(&path) {
+            return Ok(());
+        }
+        let id = self.sources.add(SourceFile::new(&path, &text));
+        self.push_file(id, path);
+        Ok(())
+    }
+
+    // #if, #elifの後に書かれた条件式を評価する。spanは指令の位置
+    fn eval_condition(&mut self, span: Span) -> Result<bool, Diagnostic> {
+        let line = self.line_tokens()?;
+        // defined X, defined(X)を0か1に置き換え、マクロを展開する
+        let mut tokens = vec![];
+        let mut idx = 0;
+        while idx < line.len() {
+            let token = &line[idx];
+            if self.token_name(token) != Some("defined") {
+                tokens.push(token.clone());
+                idx += 1;
+                continue;
+            }
+            let paren = line
+                .get(idx + 1)
+                .is_some_and(|token| token.kind.is_sign("("));
+            let name_idx = idx + 1 + paren as usize;
+            let name = line
+                .get(name_idx)
+                .and_then(|token| self.macro_symbol(token))
+                .ok_or_else(|| {
+                    Diagnostic::error(
+                        Code::InvalidDirective,
+                        line.get(name_idx).map_or(token.span, |token| token.span),
+                        Message::MissingMacroName,
+                    )
+                })?;
+            let mut end = name_idx + 1;
+            if paren {
+                if !line.get(end).is_some_and(|token| token.kind.is_sign(")")) {
+                    return Err(Diagnostic::error(
+                        Code::ExpectedToken,
+                        line.get(end)
+                            .map_or(line[name_idx].span, |token| token.span),
+                        Message::Expected("')'".to_string()),
+                    ));
+                }
+                end += 1;
+            }
+            tokens.push(Token {
+                span: token.span.to(line[end - 1].span),
+                kind: TokenKind::Num {
+                    val: self.macros.contains_key(&name) as isize,
+                    typ: NumType::Int,
+                },
+                bol: false,
+                space: token.space,
+                hideset: HideSet::default(),
+            });
+            idx = end;
+        }
+        let tokens = self.expand_tokens(tokens)?;
+
+        let end = line.last().map_or(span, |token| token.span).end_point();
+        let mut expr = CondExpr {
+            tokens: &tokens,
+            idx: 0,
+            end,
+            unevaluated: false,
+        };
+        let val = expr.conditional()?;
+        if expr.idx < tokens.len() {
+            return Err(expr.error_at_now());
+        }
+        Ok(val.is_true())
+    }
+}
+
+// 展開結果のトークンは、マクロを使った位置spanにあるものとする
+fn at(token: &Token, span: Span) -> Token {
+    Token {
+        span,
+        bol: false,
+        ..token.clone()
+    }
+}
+
+// 文字列リテラル・文字定数の中身を、quoteで囲んでソースコードに書ける形にする
+fn escape(bytes: &[u8], quote: u8) -> String {
+    let mut text = String::new();
+    for &c in bytes {
+        match c {
+            b'\\' => text.push_str("\\\\"),
+            b'\n' => text.push_str("\\n"),
+            b'\t' => text.push_str("\\t"),
+            b'\r' => text.push_str("\\r"),
+            _ if c == quote => {
+                text.push('\\');
+                text.push(c as char);
+            }
+            b' '..=b'~' => text.push(c as char),
+            _ => text.push_str(&format!("\\{:03o}", c)),
+        }
+    }
+    text
+}
+
+// __DATE__の値として、今日の日付を"Mmm dd yyyy"の形にする。
+// 再現可能なビルドのため、環境変数SOURCE_DATE_EPOCHがあればその時刻を使う
+fn today() -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs())
+        });
+    // 1970年1月1日からの日数を、3月始まりの暦を使って年月日にする
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{} {:>2} {}", MONTHS[month as usize - 1], day, year)
+}
+
+// 二項演算子の優先順位。大きいほど強く結びつく
+fn precedence(sign: &str) -> Option<u8> {
+    let prec = match sign {
+        "*" | "/" | "%" => 10,
+        "+" | "-" => 9,
+        "<<" | ">>" => 8,
+        "<" | "<=" | ">" | ">=" => 7,
+        "==" | "!=" => 6,
+        "&" => 5,
+        "^" => 4,
+        "|" => 3,
+        "&&" => 2,
+        "||" => 1,
+        _ => return None,
+    };
+    Some(prec)
+}
+
+// #ifの条件式を評価する
+// #ifの条件式の値。符号付きの値はintmax_t、符号なしの値はuintmax_tとして扱う
+#[derive(Clone, Copy)]
+struct CondVal {
+    val: i64,
+    unsigned: bool,
+}
+impl CondVal {
+    fn signed(val: i64) -> Self {
+        CondVal {
+            val,
+            unsigned: false,
+        }
+    }
+
+    fn is_true(&self) -> bool {
+        self.val != 0
+    }
+}
+
+struct CondExpr<'t> {
+    tokens: &'t [Token],
+    idx: usize,
+    end: Span,         // 条件式の終わりの位置
+    unevaluated: bool, // &&, ||, ?:の結果に使われない側を読んでいるか。その間は0除算をエラーにしない
+}
+impl CondExpr<'_> {
+    fn error_at_now(&self) -> Diagnostic {
+        let span = self
+            .tokens
+            .get(self.idx)
+            .map_or(self.end, |token| token.span);
+        Diagnostic::error(
+            Code::InvalidConditionalExpression,
+            span,
+            Message::InvalidConditionalExpression,
+        )
+    }
+
+    fn consume_sign(&mut self, sign: &str) -> bool {
+        if self
+            .tokens
+            .get(self.idx)
+            .is_some_and(|token| token.kind.is_sign(sign))
+        {
+            self.idx += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // conditional = binary ("?" conditional ":" conditional)?
+    fn conditional(&mut self) -> Result<CondVal, Diagnostic> {
+        let cond = self.binary(1)?;
+        if !self.consume_sign("?") {
+            return Ok(cond);
+        }
+        let then = self.unevaluated(!cond.is_true(), Self::conditional)?;
+        if !self.consume_sign(":") {
+            return Err(self.error_at_now());
+        }
+        let els = self.unevaluated(cond.is_true(), Self::conditional)?;
+        // 結果の型は、2つの値の型をそろえたもの
+        let val = if cond.is_true() { then } else { els };
+        Ok(CondVal {
+            val: val.val,
+            unsigned: then.unsigned || els.unsigned,
+        })
+    }
+
+    // skipがtrueなら、結果に使われない部分としてparseで読む
+    fn unevaluated(
+        &mut self,
+        skip: bool,
+        parse: impl FnOnce(&mut Self) -> Result<CondVal, Diagnostic>,
+    ) -> Result<CondVal, Diagnostic> {
+        let outer = self.unevaluated;
+        self.unevaluated |= skip;
+        let val = parse(self);
+        self.unevaluated = outer;
+        val
+    }
+
+    // 優先順位がmin_prec以上の二項演算子からなる式
+    fn binary(&mut self, min_prec: u8) -> Result<CondVal, Diagnostic> {
+        let mut lhs = self.unary()?;
+        while let Some(token) = self.tokens.get(self.idx) {
+            let TokenKind::Reserved { sign } = token.kind else {
+                break;
+            };
+            let prec = match precedence(sign) {
+                Some(prec) if prec >= min_prec => prec,
+                _ => break,
+            };
+            self.idx += 1;
+            // &&と||は、左辺だけで結果が決まる場合は右辺を評価しない
+            let skip = match sign {
+                "&&" => !lhs.is_true(),
+                "||" => lhs.is_true(),
+                _ => false,
+            };
+            let rhs = self.unevaluated(skip, |expr| expr.binary(prec + 1))?;
+            // シフト以外の算術演算と比較は、片方が符号なしなら両方を符号なしとして計算する
+            let unsigned = lhs.unsigned || rhs.unsigned;
+            let (l, r) = (lhs.val, rhs.val);
+            let (ul, ur) = (l as u64, r as u64);
+            let val = match sign {
+                "*" => l.wrapping_mul(r),
+                "/" | "%" if r == 0 && self.unevaluated => 0,
+                "/" | "%" if r == 0 => {
+                    return Err(Diagnostic::error(
+                        Code::InvalidConditionalExpression,
+                        token.span,
+                        Message::DivisionByZero,
+                    ))
+                }
+                "/" if unsigned => (ul / ur) as i64,
+                "%" if unsigned => (ul % ur) as i64,
+                "/" => l.wrapping_div(r),
+                "%" => l.wrapping_rem(r),
+                "+" => l.wrapping_add(r),
+                "-" => l.wrapping_sub(r),
+                "<<" => l.wrapping_shl(r as u32),
+                ">>" if lhs.unsigned => ul.wrapping_shr(r as u32) as i64,
+                ">>" => l.wrapping_shr(r as u32),
+                "<" if unsigned => (ul < ur) as i64,
+                "<=" if unsigned => (ul <= ur) as i64,
+                ">" if unsigned => (ul > ur) as i64,
+                ">=" if unsigned => (ul >= ur) as i64,
+                "<" => (l < r) as i64,
+                "<=" => (l <= r) as i64,
+                ">" => (l > r) as i64,
+                ">=" => (l >= r) as i64,
+                "==" => (l == r) as i64,
+                "!=" => (l != r) as i64,
+                "&" => l & r,
+                "^" => l ^ r,
+                "|" => l | r,
+                "&&" => (l != 0 && r != 0) as i64,
+                _ => (l != 0 || r != 0) as i64,
+            };
+            // シフトの結果は左辺の型、比較と論理演算の結果はint
+            let unsigned = match sign {
+                "<<" | ">>" => lhs.unsigned,
+                "<" | "<=" | ">" | ">=" | "==" | "!=" | "&&" | "||" => false,
+                _ => unsigned,
+            };
+            lhs = CondVal { val, unsigned };
+        }
+        Ok(lhs)
+    }
+
+    // unary = ("+" | "-" | "~" | "!") unary | "(" conditional ")" | num | char | ident
+    fn unary(&mut self) -> Result<CondVal, Diagnostic> {
+        let Some(token) = self.tokens.get(self.idx) else {
+            return Err(self.error_at_now());
+        };
+        self.idx += 1;
+        match &token.kind {
+            TokenKind::Num { val, typ } => Ok(CondVal {
+                val: *val as i64,
+                unsigned: typ.is_unsigned(),
+            }),
+            TokenKind::CharLit { val } => Ok(CondVal::signed(*val as i64)),
+            TokenKind::Reserved { sign: "+" } => self.unary(),
+            TokenKind::Reserved { sign: "-" } => {
+                let operand = self.unary()?;
+                Ok(CondVal {
+                    val: operand.val.wrapping_neg(),
+                    ..operand
+                })
+            }
+            TokenKind::Reserved { sign: "~" } => {
+                let operand = self.unary()?;
+                Ok(CondVal {
+                    val: !operand.val,
+                    ..operand
+                })
+            }
+            TokenKind::Reserved { sign: "!" } => {
+                Ok(CondVal::signed(!self.unary()?.is_true() as i64))
+            }
+            TokenKind::Reserved { sign: "(" } => {
+                let val = self.conditional()?;
+                if !self.consume_sign(")") {
+                    return Err(self.error_at_now());
+                }
+                Ok(val)
+            }
+            // マクロとして展開されなかった識別子とキーワードは0とみなす
+            TokenKind::ID { .. } => Ok(CondVal::signed(0)),
+            kind if kind.keyword().is_some() => Ok(CondVal::signed(0)),
+            _ => {
+                self.idx -= 1;
+                Err(self.error_at_now())
+            }
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use crate::{
+    common::{SourceFile, SourceMap, Span},
+    error::{Diagnostic, Severity, SubDiagnostic},
+    message::Lang,
+};
+
+// タブ文字を何文字分の幅で表示するか
+const TAB_WIDTH: usize = 4;
+
+// 端末に色をつけるためのエスケープシーケンス
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+const GREEN: &str = "\x1b[1;32m";
+
+// 診断を人間が読みやすい形の文字列にするレンダラ
+pub struct Renderer<'a> {
+    sources: &'a SourceMap,
+    color: bool, // エスケープシーケンスで色をつけるか
+    lang: Lang,  // メッセージを表示する言語
+}
+
+// Spanを行と列で表したもの。(row, col)から(end_row, end_col)の直前までを表し、どれも0始まり
+#[derive(Clone, Copy)]
+struct Region {
+    row: usize,
+    col: usize,
+    end_row: usize,
+    end_col: usize,
+}
+impl Region {
+    fn new(source: &SourceFile, span: Span) -> Self {
+        let (row, col) = source.line_col(span.start);
+        let (end_row, end_col) = source.line_col(span.end);
+        Region {
+            row,
+            col,
+            end_row,
+            end_col,
+        }
+    }
+}
+
+// ソースコードの下に引く下線
+struct Underline {
+    region: Region,
+    label: Option<String>,
+    primary: bool, // 主な原因となった箇所かどうか
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(sources: &'a SourceMap, color: bool, lang: Lang) -> Self {
+        Renderer {
+            sources,
+            color,
+            lang,
+        }
+    }
+
+    // spanが指すソースファイルと、spanを行と列で表したものを返す
+    fn resolve(&self, span: Span) -> Option<(&'a SourceFile, Region)> {
+        let source = self.sources.get(span.file)?;
+        Some((source, Region::new(source, span)))
+    }
+
+    // colorが有効な場合のみ、textをstyleで装飾する
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color && !text.is_empty() {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn severity_style(severity: Severity) -> &'static str {
+        match severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+            Severity::Note => CYAN,
+        }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let style = Self::severity_style(diagnostic.severity);
+        let mut ret = format!(
+            "{}{}\n",
+            self.paint(
+                style,
+                &format!(
+                    "{}[{}]",
+                    self.lang.severity(diagnostic.severity),
+                    diagnostic.code.as_str()
+                )
+            ),
+            self.paint(
+                BOLD,
+                &format!(
+                    "{}{}",
+                    self.lang.colon(),
+                    diagnostic.message.text(self.lang)
+                )
+            )
+        );
+
+        // 行番号を表示するのに必要な幅
+        let mut max_row = 0;
+        let spans = diagnostic
+            .span
+            .into_iter()
+            .chain(diagnostic.children.iter().filter_map(|child| match child {
+                SubDiagnostic::Label { span, .. } | SubDiagnostic::Suggestion { span, .. } => {
+                    Some(*span)
+                }
+                SubDiagnostic::Note(_) => None,
+            }));
+        for span in spans {
+            if let Some((_, region)) = self.resolve(span) {
+                max_row = max_row.max(region.end_row);
+            }
+        }
+        let width = (max_row + 1).to_string().len();
+        let gutter = " ".repeat(width);
+
+        if let Some(span) = diagnostic.span {
+            // 下線をソースファイルごとにまとめる。主な原因のあるファイルを最初に表示する
+            let mut files: Vec<(u32, Vec<Underline>)> = vec![(span.file, vec![])];
+            let labels = diagnostic.children.iter().filter_map(|child| match child {
+                SubDiagnostic::Label { span, message } => Some((*span, message.text(self.lang))),
+                _ => None,
+            });
+            let primary_label = diagnostic.label.as_ref().map(|label| label.text(self.lang));
+            for (idx, (span, label)) in std::iter::once((span, primary_label))
+                .chain(labels.map(|(span, label)| (span, Some(label))))
+                .enumerate()
+            {
+                let Some((_, region)) = self.resolve(span) else {
+                    continue;
+                };
+                let underline = Underline {
+                    region,
+                    label,
+                    primary: idx == 0,
+                };
+                match files.iter_mut().find(|(file, _)| *file == span.file) {
+                    Some((_, underlines)) => underlines.push(underline),
+                    None => files.push((span.file, vec![underline])),
+                }
+            }
+
+            for (idx, (file, underlines)) in files.iter().enumerate() {
+                let (Some(source), Some(first)) = (self.sources.get(*file), underlines.first())
+                else {
+                    continue;
+                };
+                // 主な原因のあるファイルは"-->"、それ以外のファイルは":::"で始める
+                let arrow = if idx == 0 { "-->" } else { ":::" };
+                ret += &format!(
+                    "{}{} {}:{}:{}\n",
+                    gutter,
+                    self.paint(BLUE, arrow),
+                    source.name,
+                    first.region.row + 1,
+                    first.region.col + 1
+                );
+                ret += &self.render_snippet(source, underlines, style, width);
+            }
+        }
+
+        for child in diagnostic.children.iter() {
+            match child {
+                SubDiagnostic::Label { .. } => {}
+                SubDiagnostic::Note(note) => {
+                    ret += &format!(
+                        "{} {} {}{}{}\n",
+                        gutter,
+                        self.paint(BLUE, "="),
+                        self.paint(BOLD, self.lang.severity(Severity::Note)),
+                        self.lang.colon(),
+                        note.text(self.lang)
+                    );
+                }
+                SubDiagnostic::Suggestion {
+                    span,
+                    replacement,
+                    message,
+                } => {
+                    ret += &format!(
+                        "{} {} {}{}{}\n",
+                        gutter,
+                        self.paint(BLUE, "="),
+                        self.paint(BOLD, self.lang.help()),
+                        self.lang.colon(),
+                        message.text(self.lang)
+                    );
+                    if let Some((source, region)) = self.resolve(*span) {
+                        ret += &self.render_suggestion(source, region, replacement, width);
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    // underlinesが指す行を、下線とラベルをつけて表示する
+    fn render_snippet(
+        &self,
+        source: &SourceFile,
+        underlines: &[Underline],
+        style: &str,
+        width: usize,
+    ) -> String {
+        let gutter = " ".repeat(width);
+        let bar = self.paint(BLUE, "|");
+
+        // 表示する行を集める
+        let mut rows = vec![];
+        for underline in underlines.iter() {
+            for row in underline.region.row..=underline.region.end_row {
+                if row < source.line_count() && !rows.contains(&row) {
+                    rows.push(row);
+                }
+            }
+        }
+        rows.sort();
+
+        let mut ret = format!("{} {}\n", gutter, bar);
+        let mut prev_row = None;
+        for &row in rows.iter() {
+            // 離れた行の間は省略する
+            if let Some(prev) = prev_row {
+                if row > prev + 1 {
+                    ret += &format!("{}\n", self.paint(BLUE, "..."));
+                }
+            }
+            prev_row = Some(row);
+
+            let line = source.line(row).chars().collect::<Vec<char>>();
+            ret += &format!(
+                "{} {} {}\n",
+                self.paint(BLUE, &format!("{:>width$}", row + 1, width = width)),
+                bar,
+                expand_tabs(&line)
+            );
+
+            for underline in underlines.iter() {
+                let region = underline.region;
+                if row < region.row || region.end_row < row {
+                    continue;
+                }
+                // この行の中で下線を引く範囲
+                let start = if row == region.row {
+                    region.col
+                } else {
+                    line.iter().take_while(|c| c.is_whitespace()).count()
+                };
+                let end = if row == region.end_row {
+                    region.end_col
+                } else {
+                    line.len()
+                };
+                let start_col = display_width(&line, start);
+                let end_col = display_width(&line, end).max(start_col + 1);
+
+                let (mark, mark_style) = if underline.primary {
+                    ("^", style)
+                } else {
+                    ("-", BLUE)
+                };
+                let mut text = mark.repeat(end_col - start_col);
+                if row == region.end_row {
+                    if let Some(label) = &underline.label {
+                        text = format!("{} {}", text, label);
+                    }
+                }
+                ret += &format!(
+                    "{} {} {}{}\n",
+                    gutter,
+                    bar,
+                    " ".repeat(start_col),
+                    self.paint(mark_style, &text)
+                );
+            }
+        }
+        ret
+    }
+
+    // regionをreplacementで置き換えた結果の行を、変更箇所に印をつけて表示する
+    fn render_suggestion(
+        &self,
+        source: &SourceFile,
+        region: Region,
+        replacement: &str,
+        width: usize,
+    ) -> String {
+        let first = source.line(region.row).chars().collect::<Vec<char>>();
+        let last = source.line(region.end_row).chars().collect::<Vec<char>>();
+        let mut line = first[..region.col.min(first.len())].to_vec();
+        line.extend(replacement.chars());
+        let replaced_end = line.len();
+        line.extend(last[region.end_col.min(last.len())..].iter());
+
+        let gutter = " ".repeat(width);
+        let bar = self.paint(BLUE, "|");
+        let start_col = display_width(&line, region.col.min(first.len()));
+        let end_col = display_width(&line, replaced_end).max(start_col + 1);
+        let mark = if region.row == region.end_row && region.col == region.end_col {
+            "+" // 挿入
+        } else {
+            "~" // 置き換え
+        };
+        format!(
+            "{} {}\n{} {} {}\n{} {} {}{}\n",
+            gutter,
+            bar,
+            self.paint(BLUE, &format!("{:>width$}", region.row + 1, width = width)),
+            bar,
+            expand_tabs(&line),
+            gutter,
+            bar,
+            " ".repeat(start_col),
+            self.paint(GREEN, &mark.repeat(end_col - start_col))
+        )
+    }
+}
+
+// 1文字を表示するのに必要な幅を返す。全角文字は2文字分の幅で表示される
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0D => 0, // CR
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+// lineの先頭からcol文字目までを表示したときの幅を返す。タブはTAB_WIDTHの倍数の位置まで進む
+fn display_width(line: &[char], col: usize) -> usize {
+    let mut width = 0;
+    for &c in line.iter().take(col) {
+        if c == '\t' {
+            width += TAB_WIDTH - width % TAB_WIDTH;
+        } else {
+            width += char_width(c);
+        }
+    }
+    // 行末より後ろを指している場合は1文字ずつ進める
+    width + col.saturating_sub(line.len())
+}
+
+// タブを空白に展開した行を返す
+fn expand_tabs(line: &[char]) -> String {
+    let mut ret = String::new();
+    let mut width = 0;
+    for &c in line.iter() {
+        if c == '\t' {
+            let n = TAB_WIDTH - width % TAB_WIDTH;
+            ret += &" ".repeat(n);
+            width += n;
+        } else if c == '\r' {
+            // 行末のCRは表示しない
+        } else {
+            ret.push(c);
+            width += char_width(c);
+        }
+    }
+    ret
+}
+
+// 診断を1行のJSONにする。行と列は1始まりで、終わりの列はその文字を含まない
+pub fn render_json(diagnostic: &Diagnostic, sources: &SourceMap, lang: Lang) -> String {
+    let children = diagnostic
+        .children
+        .iter()
+        .map(|child| match child {
+            SubDiagnostic::Label { span, message } => format!(
+                "{{\"kind\":\"label\",\"span\":{},\"message\":{}}}",
+                json_span(sources, Some(*span)),
+                json_string(&message.text(lang))
+            ),
+            SubDiagnostic::Note(note) => format!(
+                "{{\"kind\":\"note\",\"span\":null,\"message\":{}}}",
+                json_string(&note.text(lang))
+            ),
+            SubDiagnostic::Suggestion {
+                span,
+                replacement,
+                message,
+            } => format!(
+                "{{\"kind\":\"suggestion\",\"span\":{},\"message\":{},\"replacement\":{}}}",
+                json_span(sources, Some(*span)),
+                json_string(&message.text(lang)),
+                json_string(replacement)
+            ),
+        })
+        .collect::<Vec<String>>();
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+    };
+    format!(
+        "{{\"code\":{},\"severity\":{},\"file\":{},\"span\":{},\"message\":{},\"label\":{},\"children\":[{}]}}\n",
+        json_string(diagnostic.code.as_str()),
+        json_string(severity),
+        diagnostic
+            .span
+            .and_then(|span| sources.get(span.file))
+            .map_or("null".to_string(), |source| json_string(&source.name)),
+        json_span(sources, diagnostic.span),
+        json_string(&diagnostic.message.text(lang)),
+        diagnostic
+            .label
+            .as_ref()
+            .map_or("null".to_string(), |label| json_string(&label.text(lang))),
+        children.join(",")
+    )
+}
+
+// 位置をJSONにする。ラベルなどはヘッダの中を指すこともあるので、位置ごとにファイル名を含める
+fn json_span(sources: &SourceMap, span: Option<Span>) -> String {
+    let source = span.and_then(|span| Some((sources.get(span.file)?, span)));
+    match source.map(|(source, span)| (source, Region::new(source, span))) {
+        Some((source, region)) => format!(
+            "{{\"file\":{},\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{}}}",
+            json_string(&source.name),
+            region.row + 1,
+            region.col + 1,
+            region.end_row + 1,
+            region.end_col + 1
+        ),
+        None => "null".to_string(),
+    }
+}
+
+// 文字列をJSONの文字列リテラルにする
+fn json_string(s: &str) -> String {
+    let mut ret = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => ret += "\\\"",
+            '\\' => ret += "\\\\",
+            '\n' => ret += "\\n",
+            '\r' => ret += "\\r",
+            '\t' => ret += "\\t",
+            c if (c as u32) < 0x20 => ret += &format!("\\u{:04x}", c as u32),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
@@ -0,0 +1,105 @@
+use std::{collections::HashMap, rc::Rc};
+
+// 識別子の名前を表す番号。同じ名前には同じ番号が振られるので、名前の比較は番号の比較で済む
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct Symbol(u32);
+
+// 識別子の名前を一度だけ保持する記号表
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    names: Vec<Box<str>>,           // 番号から名前を引く
+    ids: HashMap<Box<str>, Symbol>, // 名前から番号を引く
+}
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable {
+            names: vec![],
+            ids: HashMap::new(),
+        }
+    }
+
+    // nameに対応する番号を返す。初めて見る名前には新しい番号を振る
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.ids.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name.into());
+        self.ids.insert(name.into(), symbol);
+        symbol
+    }
+
+    // symbolの名前を返す
+    pub fn name(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+
+    // 登録されている名前の数
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+// マクロ展開で、これ以上展開してはいけないマクロの名前の集合。
+// 展開結果のトークンはどれも同じ集合を持つことが多いので、Rcで共有する
+#[derive(Debug, Clone, Default)]
+pub struct HideSet(Option<Rc<[Symbol]>>);
+impl HideSet {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    pub fn contains(&self, name: Symbol) -> bool {
+        self.0.as_ref().is_some_and(|names| names.contains(&name))
+    }
+
+    fn from_vec(names: Vec<Symbol>) -> Self {
+        if names.is_empty() {
+            HideSet(None)
+        } else {
+            HideSet(Some(names.into()))
+        }
+    }
+
+    fn names(&self) -> &[Symbol] {
+        self.0.as_deref().unwrap_or(&[])
+    }
+
+    // selfにnameを加えた集合を返す
+    pub fn with(&self, name: Symbol) -> Self {
+        if self.contains(name) {
+            return self.clone();
+        }
+        let mut names = self.names().to_vec();
+        names.push(name);
+        Self::from_vec(names)
+    }
+
+    // selfとotherの和集合を返す
+    pub fn union(&self, other: &HideSet) -> Self {
+        if other.is_empty() {
+            return self.clone();
+        }
+        if self.is_empty() {
+            return other.clone();
+        }
+        let mut names = self.names().to_vec();
+        names.extend(other.names().iter().filter(|name| !self.contains(**name)));
+        Self::from_vec(names)
+    }
+
+    // selfとotherの共通部分を返す
+    pub fn intersection(&self, other: &HideSet) -> Self {
+        let names = self
+            .names()
+            .iter()
+            .copied()
+            .filter(|name| other.contains(*name))
+            .collect();
+        Self::from_vec(names)
+    }
+}
@@ -1,52 +1,226 @@
-use crate::{error, lexer::TokenList, parser::Type};
+use crate::{
+    ast::{Expr, ExprKind},
+    common::Span,
+    error::{Code, Diagnostic},
+    message::Message,
+    parser::{Member, StructList, Type},
+};
 
-// typ型を格納するのに必要なバイト数を取得する
-pub fn get_size(typ: Type) -> usize {
+// typ型を格納するのに必要なバイト数を取得する。構造体の大きさはstructsから引く
+pub fn get_size(typ: Type, structs: &StructList) -> usize {
     match typ {
-        Type::Int(0) | Type::Unknown => 8, // TODO: レジスタの使い分けが非常に面倒なので、int型も一旦8bytes alignmentで実装する
-        Type::Int(x) if x > 0 => 8,
+        Type::Int(0) => 4,
+        Type::Char(0) => 1,
+        Type::Struct { id, level: 0 } => structs.get(id).size,
+        Type::Int(_) | Type::Char(_) | Type::Struct { .. } | Type::Unknown => 8,
+        Type::IntArr(arr) => arr.size * get_size(Type::Int(arr.level), structs),
+        Type::CharArr(arr) => arr.size * get_size(Type::Char(arr.level), structs),
+        Type::StructArr { id, arr } => {
+            arr.size
+                * get_size(
+                    Type::Struct {
+                        id,
+                        level: arr.level,
+                    },
+                    structs,
+                )
+        }
+    }
+}
+
+// typ型の値を置くアドレスが何バイト境界に揃っている必要があるかを取得する
+pub fn get_align(typ: Type, structs: &StructList) -> usize {
+    match typ {
+        Type::Struct { id, level: 0 } => structs.get(id).align,
+        Type::IntArr(arr) => get_align(Type::Int(arr.level), structs),
+        Type::CharArr(arr) => get_align(Type::Char(arr.level), structs),
+        Type::StructArr { id, arr } => get_align(
+            Type::Struct {
+                id,
+                level: arr.level,
+            },
+            structs,
+        ),
+        _ => get_size(typ, structs),
+    }
+}
+
+// 構造体のメンバを宣言された順に、それぞれのalignmentに合わせて並べてオフセットを決める。
+// 構造体全体の大きさとalignmentを返す。大きさは、配列にしても各要素のメンバが揃うようにalignmentの倍数にする
+pub fn struct_layout(members: &mut [Member], structs: &StructList) -> (usize, usize) {
+    let mut offset = 0;
+    let mut align = 1;
+    for member in members.iter_mut() {
+        let member_align = get_align(member.typ, structs);
+        member.offset = align_to(offset, member_align);
+        offset = member.offset + get_size(member.typ, structs);
+        align = align.max(member_align);
+    }
+    (align_to(offset, align), align)
+}
+
+// 共用体のメンバは全て先頭に置く。共用体全体の大きさとalignmentを返す。大きさは最も大きいメンバの大きさをalignmentの倍数にしたもの
+pub fn union_layout(members: &[Member], structs: &StructList) -> (usize, usize) {
+    let size = members
+        .iter()
+        .map(|member| get_size(member.typ, structs))
+        .max()
+        .unwrap_or(0);
+    let align = members
+        .iter()
+        .map(|member| get_align(member.typ, structs))
+        .max()
+        .unwrap_or(1);
+    (align_to(size, align), align)
+}
+
+// nをalignの倍数に切り上げる
+pub fn align_to(n: usize, align: usize) -> usize {
+    n.div_ceil(align) * align
+}
+
+// 配列の型であるか判定する
+pub fn is_array(typ: Type) -> bool {
+    matches!(
+        typ,
+        Type::IntArr(_) | Type::CharArr(_) | Type::StructArr { .. }
+    )
+}
+
+// 式の中で使われた配列を、先頭の要素へのポインタとみなした型を返す。配列以外の型はそのまま返す
+pub fn decay(typ: Type) -> Type {
+    match typ {
+        Type::IntArr(arr) => Type::Int(arr.level + 1),
+        Type::CharArr(arr) => Type::Char(arr.level + 1),
+        Type::StructArr { id, arr } => Type::Struct {
+            id,
+            level: arr.level + 1,
+        },
+        _ => typ,
+    }
+}
+
+// 式の中で使われた値の型を返す。配列はポインタとみなし、char型はint型に格上げする
+pub fn promote(typ: Type) -> Type {
+    match decay(typ) {
+        Type::Char(0) => Type::Int(0),
+        typ => typ,
+    }
+}
+
+// ポインタ型(か配列)であれば、それが指す先の型を返す。それ以外はNoneを返す
+pub fn pointee(typ: Type) -> Option<Type> {
+    match decay(typ) {
+        Type::Int(x) if x > 0 => Some(Type::Int(x - 1)),
+        Type::Char(x) if x > 0 => Some(Type::Char(x - 1)),
+        Type::Struct { id, level } if level > 0 => Some(Type::Struct {
+            id,
+            level: level - 1,
+        }),
+        _ => None,
+    }
+}
+
+// typ型へのポインタ型を返す。typは配列でないポインタ型かスカラー型
+pub fn pointer_to(typ: Type) -> Type {
+    match typ {
+        Type::Int(x) => Type::Int(x + 1),
+        Type::Char(x) => Type::Char(x + 1),
+        Type::Struct { id, level } => Type::Struct {
+            id,
+            level: level + 1,
+        },
         _ => unreachable!(),
     }
 }
 
+// typ型をCでの書き方で表す文字列を返す。例えばint *, char[3], struct P。診断に型を表示するときに使う
+pub fn type_to_string(typ: Type, structs: &StructList) -> String {
+    let (base, level, size) = match typ {
+        Type::Int(level) => ("int".to_string(), level, None),
+        Type::Char(level) => ("char".to_string(), level, None),
+        Type::Struct { id, level } => (structs.type_name(id), level, None),
+        Type::IntArr(arr) => ("int".to_string(), arr.level, Some(arr.size)),
+        Type::CharArr(arr) => ("char".to_string(), arr.level, Some(arr.size)),
+        Type::StructArr { id, arr } => (structs.type_name(id), arr.level, Some(arr.size)),
+        // 宣言されていない関数の返り値の型
+        Type::Unknown => return "<unknown>".to_string(),
+    };
+    let mut ret = base;
+    if level > 0 {
+        ret += " ";
+        ret += &"*".repeat(level);
+    }
+    if let Some(size) = size {
+        ret += &format!("[{}]", size);
+    }
+    ret
+}
+
 // 数値の二項演算において、2つの値の型に対する結果の型を返す
-pub fn binary_calc_type(typ1: Type, typ2: Type, token_list: &TokenList) -> Type {
-    match (typ1, typ2) {
+pub fn binary_calc_type(
+    typ1: Type,
+    typ2: Type,
+    span: Span,
+    structs: &StructList,
+) -> Result<Type, Diagnostic> {
+    match (promote(typ1), promote(typ2)) {
         (Type::Int(0), Type::Int(0))
         | (Type::Int(0), Type::Unknown)
-        | (Type::Unknown, Type::Int(0)) => Type::Int(0),
-        (Type::Int(x), Type::Int(0))
-        | (Type::Int(0), Type::Int(x))
-        | (Type::Int(x), Type::Unknown)
-        | (Type::Unknown, Type::Int(x)) => Type::Int(x),
-        (Type::Unknown, Type::Unknown) => Type::Unknown,
-        _ => {
-            error::error();
-            unreachable!()
+        | (Type::Unknown, Type::Int(0)) => Ok(Type::Int(0)),
+        // ポインタと整数の演算の結果はポインタ
+        (ptr, Type::Int(0) | Type::Unknown) | (Type::Int(0) | Type::Unknown, ptr)
+            if pointee(ptr).is_some() =>
+        {
+            Ok(ptr)
         }
+        (Type::Unknown, Type::Unknown) => Ok(Type::Unknown),
+        _ => Err(Diagnostic::error(
+            Code::InvalidOperands,
+            span,
+            Message::InvalidOperands {
+                lhs: type_to_string(typ1, structs),
+                rhs: type_to_string(typ2, structs),
+            },
+        )),
+    }
+}
+
+// typ1 = rhsという代入において、代入が成立するか判定する
+pub fn match_assign_type(
+    typ1: Type,
+    rhs: &Expr,
+    span: Span,
+    structs: &StructList,
+) -> Result<(), Diagnostic> {
+    if is_assignable(typ1, rhs) {
+        Ok(())
+    } else {
+        Err(Diagnostic::error(
+            Code::AssignMismatch,
+            span,
+            Message::AssignMismatch {
+                from: type_to_string(rhs.typ, structs),
+                to: type_to_string(typ1, structs),
+            },
+        ))
     }
 }
 
-// typ1 = typ2という代入において、代入が成立するか判定する
-pub fn match_assign_type(typ1: Type, typ2: Type, token_list: &TokenList) {
+// typ1型の変数に式rhsの値を代入できるか判定する。関数の引数を渡すときも同じ規則に従う
+pub fn is_assignable(typ1: Type, rhs: &Expr) -> bool {
+    let typ2 = rhs.typ;
     match typ1 {
-        Type::Int(0) => match typ2 {
-            // 左辺がint型であれば、Int(0), Unknownを右辺として受け付ける
-            Type::Int(0) | Type::Unknown => (),
-            _ => {
-                error::error();
-            }
-        },
-        Type::Int(x) => match typ2 {
-            // 左辺がintへのポインタ型であれば、同じ型のみを右辺として受け付ける
-            Type::Int(y) if x == y => (),
-            _ => {
-                error::error();
-            }
-        },
-        _ => {
-            error::error();
-            unreachable!()
+        // 左辺がint型かchar型であれば、Int(0), Char(0), Unknownを右辺として受け付ける
+        Type::Int(0) | Type::Char(0) => {
+            matches!(promote(typ2), Type::Int(0) | Type::Unknown)
+        }
+        // 左辺が構造体であれば、同じ構造体のみを右辺として受け付ける
+        Type::Struct { level: 0, .. } => typ2 == typ1,
+        // 左辺がポインタ型であれば、同じ型と空ポインタ定数0のみを右辺として受け付ける。右辺の配列はポインタとみなす
+        Type::Int(_) | Type::Char(_) | Type::Struct { .. } => {
+            decay(typ2) == typ1 || matches!(rhs.kind, ExprKind::Num(0))
         }
+        _ => false,
     }
 }
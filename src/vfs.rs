@@ -0,0 +1,42 @@
+use std::{collections::HashMap, fs, io};
+
+// ソースファイルを読み込むためのファイルシステム
+pub trait FileSystem {
+    // pathにあるファイルの中身を読み込む
+    fn read_to_string(&self, path: &str) -> io::Result<String>;
+}
+
+// 実際のファイルシステム
+#[derive(Debug, Default)]
+pub struct RealFileSystem;
+impl FileSystem for RealFileSystem {
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+// メモリ上に置かれたファイルからなる仮想ファイルシステム
+#[derive(Debug, Default)]
+pub struct MemoryFileSystem {
+    files: HashMap<String, String>,
+}
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        MemoryFileSystem {
+            files: HashMap::new(),
+        }
+    }
+
+    // pathにファイルを置く。すでにファイルがある場合は上書きする
+    pub fn add_file(&mut self, path: &str, source: &str) {
+        self.files.insert(path.to_string(), source.to_string());
+    }
+}
+impl FileSystem for MemoryFileSystem {
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        self.files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{
+    error::{Code, Diagnostic, Severity},
+    message::Message,
+};
+
+// 名前のついた警告の種類
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Warning {
+    UnusedVariable,              // 使われていないローカル変数
+    UnreachableCode,             // returnの後にあって実行されない文
+    ImplicitFunctionDeclaration, // 宣言されていない関数の呼び出し
+    ReturnType,                  // returnせずに関数の終わりに到達する
+    Parentheses,                 // 条件式としての代入
+}
+impl Warning {
+    pub const ALL: [Warning; 5] = [
+        Warning::UnusedVariable,
+        Warning::UnreachableCode,
+        Warning::ImplicitFunctionDeclaration,
+        Warning::ReturnType,
+        Warning::Parentheses,
+    ];
+
+    // -W<name>, -Wno-<name>で使う名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            Warning::UnusedVariable => "unused-variable",
+            Warning::UnreachableCode => "unreachable-code",
+            Warning::ImplicitFunctionDeclaration => "implicit-function-declaration",
+            Warning::ReturnType => "return-type",
+            Warning::Parentheses => "parentheses",
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        match self {
+            Warning::UnusedVariable => Code::UnusedVariable,
+            Warning::UnreachableCode => Code::UnreachableCode,
+            Warning::ImplicitFunctionDeclaration => Code::ImplicitFunctionDeclaration,
+            Warning::ReturnType => Code::ReturnType,
+            Warning::Parentheses => Code::AssignInCondition,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|w| w.name() == name)
+    }
+
+    pub fn from_code(code: Code) -> Option<Self> {
+        Self::ALL.iter().copied().find(|w| w.code() == code)
+    }
+
+    // -Wallを指定しなくても有効になっているか
+    fn enabled_by_default(&self) -> bool {
+        matches!(
+            self,
+            Warning::ImplicitFunctionDeclaration | Warning::ReturnType
+        )
+    }
+}
+
+// どの警告を報告するかの設定
+#[derive(Debug, Clone)]
+pub struct WarningOptions {
+    enabled: Vec<Warning>,
+    pub werror: bool, // 警告をエラーとして扱うか
+}
+impl Default for WarningOptions {
+    fn default() -> Self {
+        WarningOptions {
+            enabled: Warning::ALL
+                .iter()
+                .copied()
+                .filter(Warning::enabled_by_default)
+                .collect(),
+            werror: false,
+        }
+    }
+}
+impl WarningOptions {
+    pub fn is_enabled(&self, warning: Warning) -> bool {
+        self.enabled.contains(&warning)
+    }
+
+    pub fn enable(&mut self, warning: Warning) {
+        if !self.is_enabled(warning) {
+            self.enabled.push(warning);
+        }
+    }
+
+    pub fn disable(&mut self, warning: Warning) {
+        self.enabled.retain(|&w| w != warning);
+    }
+
+    // -Wall
+    pub fn enable_all(&mut self) {
+        for warning in Warning::ALL.iter() {
+            self.enable(*warning);
+        }
+    }
+
+    // 無効になっている警告を取り除き、-Werrorが指定されていれば警告をエラーにする
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter_map(|diagnostic| {
+                if diagnostic.severity != Severity::Warning {
+                    return Some(diagnostic);
+                }
+                let warning = Warning::from_code(diagnostic.code)?;
+                if !self.is_enabled(warning) {
+                    return None;
+                }
+                let mut diagnostic =
+                    diagnostic.with_note(Message::WarningFlag(warning.name().to_string()));
+                if self.werror {
+                    diagnostic.severity = Severity::Error;
+                    diagnostic = diagnostic.with_note(Message::Werror);
+                }
+                Some(diagnostic)
+            })
+            .collect()
+    }
+}
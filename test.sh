@@ -5,52 +5,703 @@ YELLOW='\033[0;33m'
 PURPLE='\033[0;35m'
 NC='\033[0m'
 
+DCC=./target/debug/dcc
+
 echo
 echo -e "${PURPLE}test started.${NC}"
 echo
 
 # sample.oを生成
-riscv64-unknown-elf-gcc -c sample.c
+cc -c sample.c -o sample.o
+
+# ./tmpを実行し、その終了コードがexpectedと一致するか確かめる
+check_run() {
+    expected="$1"
+    title="$2"
+
+    # 実行
+    ./tmp
+
+    # 実行結果の代入
+    actual="$?"
+
+    echo -e "${YELLOW}\`\`\`$title\`\`\`${NC}"
+    if [ "$actual" = "$expected" ]; then
+        echo "=> $actual"
+        echo
+    else
+        echo -e "${RED}=> $expected expected, but got $actual${NC}"
+        echo
+        exit 1
+    fi
+}
+
+# 失敗した場合のメッセージを表示して終了する
+fail() {
+    title="$1"
+    message="$2"
+
+    echo -e "${YELLOW}\`\`\`$title\`\`\`${NC}"
+    echo -e "${RED}=> $message${NC}"
+    echo
+    exit 1
+}
 
+# inputをコンパイル・実行し、終了コードがexpectedになるか確かめる。3つ目以降の引数はdccにそのまま渡す
 assert() {
     expected="$1"
     input="$2"
+    shift 2
 
     # dccによるコンパイル
-    ./target/debug/dcc r "$input" > tmp.s
+    echo "$input" | $DCC -S -o tmp.s "$@" -
+    if [ $? != 0 ]; then
+        # コンパイル失敗した場合
+        fail "$input" "compile error"
+    fi
+
+    # アセンブル
+    cc -c tmp.s -o tmp.o
+
+    # sample.oとのリンク
+    cc -o tmp tmp.o sample.o
+
+    check_run "$expected" "$input"
+}
+
+# inputのコンパイルが失敗し、codeのエラーが報告されるか確かめる。3つ目以降の引数はdccにそのまま渡す
+assert_error() {
+    code="$1"
+    input="$2"
+    shift 2
+
+    actual=$(echo "$input" | $DCC -S -o tmp.s --error-format=json "$@" - 2>&1)
     if [ $? = 0 ]; then
-        # コンパイル成功した場合
+        fail "$input" "$code expected, but compiled successfully"
+    fi
+    if ! echo "$actual" | grep -q "\"code\":\"$code\""; then
+        fail "$input" "$code expected, but got $actual"
+    fi
 
-        # アセンブル
-        riscv64-unknown-elf-gcc -c tmp.s
+    echo -e "${YELLOW}\`\`\`$input\`\`\`${NC}"
+    echo "=> $code"
+    echo
+}
 
-        # sample.oとのリンク
-        riscv64-unknown-elf-gcc -o tmp tmp.o sample.o
+# inputをコンパイルし、報告されたエラーと警告のコードがcodes(空白区切り)と順に一致するか確かめる。3つ目以降の引数はdccにそのまま渡す
+assert_codes() {
+    codes="$1"
+    input="$2"
+    shift 2
 
-        # 実行
-        spike pk tmp
+    actual=$(echo "$input" | $DCC -S -o tmp.s --error-format=json "$@" - 2>&1 | grep -o '^{"code":"[A-Z0-9]*"' | cut -d'"' -f4 | xargs)
+    if [ "$actual" != "$codes" ]; then
+        fail "$input" "$codes expected, but got $actual"
+    fi
 
-        # 実行結果の代入
-        actual="$?"
+    echo -e "${YELLOW}\`\`\`$input\`\`\`${NC}"
+    echo "=> $codes"
+    echo
+}
 
-        echo -e "${YELLOW}\`\`\`$input\`\`\`${NC}"
-        if [ "$actual" = "$expected" ]; then
-            echo "=> $actual"
-            echo
-        else
-            echo -e "${RED}=> $expected expected, but got $actual${NC}"
-            echo
-            exit 1
-        fi
-    else
-    # コンパイル失敗した場合
-        echo -e "${YELLOW}\`\`\`$input\`\`\`${NC}"
-        echo -e "${RED}=> compile error"
-        echo
-        exit 1
+# inputをコンパイルし、標準エラー出力にmessageが含まれるか確かめる。3つ目以降の引数はdccにそのまま渡す
+assert_message() {
+    message="$1"
+    input="$2"
+    shift 2
+
+    actual=$(echo "$input" | NO_COLOR=1 $DCC -S -o tmp.s "$@" - 2>&1)
+    if ! echo "$actual" | grep -qF -- "$message"; then
+        fail "$input" "'$message' expected, but got $actual"
+    fi
+
+    echo -e "${YELLOW}\`\`\`$input\`\`\`${NC}"
+    echo "=> $message"
+    echo
+}
+
+# inputを前処理したトークン列が、expectedを前処理したトークン列と一致するか確かめる
+assert_tokens() {
+    expected="$1"
+    input="$2"
+
+    echo "$expected" | $DCC --emit=tokens -o tmp-expected.txt -
+    if [ $? != 0 ]; then
+        fail "$expected" "compile error"
+    fi
+    echo "$input" | $DCC --emit=tokens -o tmp-actual.txt -
+    if [ $? != 0 ]; then
+        fail "$input" "compile error"
+    fi
+
+    # トークンの位置は比べない
+    if ! diff <(cut -f2 tmp-expected.txt) <(cut -f2 tmp-actual.txt); then
+        fail "$input" "expanded to different tokens from \`\`\`$expected\`\`\`"
     fi
+
+    echo -e "${YELLOW}\`\`\`$input\`\`\`${NC}"
+    echo "=> $expected"
+    echo
+}
+
+# commandを実行して./tmpを作り、その終了コードがexpectedになるか確かめる
+assert_driver() {
+    expected="$1"
+    command="$2"
+
+    rm -f tmp
+    if ! eval "$command"; then
+        fail "$command" "command failed"
+    fi
+    check_run "$expected" "$command"
+}
+
+# commandが失敗し、codeのエラーが報告されるか確かめる
+assert_driver_error() {
+    code="$1"
+    command="$2"
+
+    actual=$(eval "$command --error-format=json" 2>&1)
+    if [ $? = 0 ]; then
+        fail "$command" "$code expected, but succeeded"
+    fi
+    if ! echo "$actual" | grep -q "\"code\":\"$code\""; then
+        fail "$command" "$code expected, but got $actual"
+    fi
+
+    echo -e "${YELLOW}\`\`\`$command\`\`\`${NC}"
+    echo "=> $code"
+    echo
 }
 
-assert 0 'aa'
+assert 0 'int main() { return 0; }'
+assert 42 'int main() { return 42; }'
+assert 21 'int main() { return 5+20-4; }'
+assert 47 'int main() { return 5+6*7; }'
+assert 15 'int main() { return 5*(9-6); }'
+assert 4 'int main() { return (3+5)/2; }'
+assert 10 'int main() { return -10+20; }'
+assert 1 'int main() { return 1<2; }'
+assert 0 'int main() { return 1>=2; }'
+assert 1 'int main() { return 3==3; }'
+assert 14 'int main() { int a; int b; a = 3; b = 5 * 6 - 8; return a + b / 2; }'
+assert 3 'int main() { int x; if (0) x = 2; else x = 3; return x; }'
+assert 10 'int main() { int i; i = 0; while (i < 10) i = i + 1; return i; }'
+assert 55 'int main() { int i; int j; j = 0; for (i = 0; i <= 10; i = i + 1) j = i + j; return j; }'
+assert 8 'int add(int a, int b) { return a + b; } int main() { return add(3, 5); }'
+assert 55 'int fib(int n) { if (n <= 1) return n; return fib(n-1) + fib(n-2); } int main() { return fib(10); }'
+assert 3 'int main() { int x; int *y; y = &x; *y = 3; return x; }'
+
+# エラーから回復して、全てのエラーを報告する
+assert_codes 'E0006 E0006 E0006 E0006' 'int main() { x; y = 1; return z; }
+int f() { return w; }
+int g() { return 0; }'
+assert_codes 'E0003 E0003 E0003 E0003' 'int main() { int a; a = (1; a = 2 return a; }
+int f( { return 0; }
+int g() { return 1 }
+int h() { return 2; }'
+assert_codes 'E0003' 'int main() { return 0;'
+# -ferror-limitを超えたエラーは報告しない
+assert_codes 'E0006 E0016' 'int main() { x; y; return z; }' -ferror-limit=1
+assert_codes 'E0006 E0006 E0016' 'int main() { x; y; return z; }' -ferror-limit=2
+assert_codes 'E0006 E0006 E0006' 'int main() { x; y; return z; }' -ferror-limit=0
+assert_driver_error E0014 "$DCC -ferror-limit=x none.c"
+
+# --error-format=jsonでは、ヘッダの中を指すラベルにもファイル名をつける
+mkdir -p tmp-json/inc
+echo 'int f(int a);' > tmp-json/inc/d.h
+cat > tmp-json/main.c <<EOF
+#include "inc/d.h"
+char f(int a);
+int main() { return 0; }
+EOF
+actual=$($DCC -S -o tmp.s --error-format=json tmp-json/main.c 2>&1)
+if ! echo "$actual" | grep -q '"kind":"label","span":{"file":"tmp-json/inc/d.h","line":1,"column":5'; then
+    fail "$DCC --error-format=json tmp-json/main.c" "label in tmp-json/inc/d.h expected, but got $actual"
+fi
+if ! echo "$actual" | grep -q '"file":"tmp-json/main.c","span":{"file":"tmp-json/main.c","line":2,"column":6'; then
+    fail "$DCC --error-format=json tmp-json/main.c" "error in tmp-json/main.c expected, but got $actual"
+fi
+echo -e "${YELLOW}\`\`\`$DCC --error-format=json tmp-json/main.c\`\`\`${NC}"
+echo "=> $actual"
+echo
+
+# 診断の言語と型の表示
+assert_message "error[E0006]: variable 'x' is not defined" 'int main() { return x; }' --lang=en
+assert_message "エラー[E0006]：変数'x'は定義されていません" 'int main() { return x; }' --lang=ja
+assert_message "error[E0006]" 'int main() { return x; }' --lang=ja --lang=en
+assert_message "error[E0006]" 'int main() { return x; }' --lang=en --error-format=human
+assert_driver_error E0014 "$DCC --lang=fr none.c"
+assert_message "cannot pass a value of type 'int' as an argument of type 'int *'" 'int f(int *p) { return 0; } int main() { return f(3); }' --lang=en
+assert_message "cannot assign a value of type 'int[3]' to type 'int'" 'int main() { int a[3]; int x; x = a; return 0; }' --lang=en
+assert_message "invalid operands of types 'struct P' and 'int *'" 'struct P { int a; }; int main() { struct P p; int *q; return p + q; }' --lang=en
+assert_message "value of type 'char **'" 'int main() { char **c; return c.x; }' --lang=en
+assert_message "'char[4]'型の値を'int'型に代入することはできません" 'int main() { int x; x = "abc"; return 0; }' --lang=ja
+
+# 空白、改行とコメント
+assert 3 $'int main() {\r\n  return 3;\r\n}\r\n'
+assert 4 $'int\tmain()\v{\freturn\t4;}'
+assert 5 'int main() { /* return 1; */ return 5; }'
+assert 6 'int main() { // return 1;
+return 6; }'
+assert 7 'int main() { /* // */ return 7; /* /* */ }'
+assert 8 'int main() { return 4/**/*2; }'
+assert 9 'int main() { return 9; } // コメントの中の日本語'
+assert 10 $'int main() {\r\n  // CRLFで終わるコメント\r\n  return 10;\r\n}'
+assert_error E0017 'int main() { return 0; } /* 閉じていない'
+
+# 文字定数と文字列リテラル
+assert 65 $'int main() { return \'A\'; }'
+assert 141 $'int main() { return \'\\n\' + \'\\\\\' + \'\\\'\'; }'
+assert 1 $'int main() { return \'\\377\' == -1; }'
+assert 130 'int main() { char *s; s = "\x41\101"; return s[0] + s[1] + s[2]; }'
+assert 100 'int main() { return "\0a"[1] + sizeof("\0a"); }'
+assert 7 'int main() { return "\a\b\t\n\v\f\r"[0]; }'
+assert 34 'int main() { return "\"\?"[0]; }'
+assert 5 'int main() { return sizeof("ab" "cd"); }'
+assert 104 'int main() { char *s; s = "hello"; return *s; }'
+assert 1 'int main() { char *a; char *b; a = "same"; b = "same"; return a == b; }'
+assert_error E0018 $'int main() { return \'\\q\'; }'
+assert_error E0018 'int main() { return "\x100"[0]; }'
+assert_error E0018 'int main() { return "\x"[0]; }'
+assert_error E0019 'int main() { return "abc; }'
+assert_error E0020 $'int main() { return \'\'; }'
+assert_error E0020 $'int main() { return \'ab\'; }'
+
+# 警告
+assert_codes '' 'int main() { int x; return 0; }'
+assert_codes 'W0001' 'int main() { int x; return 0; }' -Wall
+assert_codes 'W0001' 'int main() { int x; return 0; }' -Wunused-variable
+assert_codes '' 'int main() { int x; return 0; }' -Wall -Wno-unused-variable
+assert_error W0001 'int main() { int x; return 0; }' -Wall -Werror
+assert 0 'int main() { int x; return 0; }' -Wall
+assert_codes 'W0002' 'int main() { return 0; 1; }' -Wunreachable-code
+assert_codes 'W0003' 'int main() { return f(); } int f() { return 0; }'
+assert_codes '' 'int main() { return f(); } int f() { return 0; }' -Wno-implicit-function-declaration
+assert_codes 'W0004' 'int f(int a) { if (a) return 1; } int main() { return 0; }'
+assert_codes '' 'int f(int a) { if (a) return 1; } int main() { return 0; }' -Wno-return-type
+assert_codes 'W0005' 'int main() { int x; if (x = 1) return 1; return 0; }' -Wparentheses
+assert_codes '' 'int main() { int x; if ((x = 1)) return 1; return 0; }' -Wparentheses
+# 終わらないループの後には関数の終わりに到達しない
+assert_codes '' 'int g(int a) { while (1) { return a; } } int k() { for (;;) {} } int main() { return 0; }' -Wall
+assert_codes 'W0004 W0004' 'int g(int a) { while (a) { return a; } } int k(int i) { for (; i;) {} } int main() { return 0; }'
+# sizeofの被演算子に使った変数は使われている
+assert_codes '' 'int main() { int y; return sizeof(y); }' -Wall
+assert_driver_error E0014 "$DCC -Wno-such-warning none.c"
+
+# 整数定数
+assert 31 'int main() { return 0x1F; }'
+assert 171 'int main() { return 0XaB; }'
+assert 15 'int main() { return 017; }'
+assert 0 'int main() { return 0; }'
+assert 5 'int main() { return 0b101; }'
+assert 10 'int main() { return 10u; }'
+assert 255 'int main() { return 0xffU; }'
+assert 1 'int main() { return 0xffffffff == -1; }'
+assert 1 'int main() { return 2147483647 > 0; }'
+assert_error E0021 'int main() { return 09; }'
+assert_error E0021 'int main() { return 0b; }'
+assert_error E0021 'int main() { return 1uu; }'
+assert_error E0022 'int main() { return 99999999999999999999; }'
+assert_error E0023 'int main() { return 1L; }'
+assert_error E0023 'int main() { return 2147483648; }'
+
+# 前処理
+rm -rf tmp-inc tmp-inc2 tmp-inc3
+mkdir -p tmp-inc/sub tmp-inc2 tmp-inc3
+cat > tmp-inc/main.c <<EOF
+#include "a.h"
+int main() { return A; }
+EOF
+cat > tmp-inc/angle.c <<EOF
+#include <a.h>
+int main() { return A; }
+EOF
+echo '#define A 1' > tmp-inc/a.h
+echo '#define A 2' > tmp-inc2/a.h
+echo '#define A 3' > tmp-inc3/a.h
+echo '#include "c.h"' > tmp-inc/sub/b.h
+echo '#define C 5' > tmp-inc/sub/c.h
+echo '#define C 6' > tmp-inc/c.h
+cat > tmp-inc/once.h <<EOF
+#pragma once
+int once() { return 7; }
+EOF
+cat > tmp-inc/guard.h <<EOF
+#ifndef GUARD_H
+#define GUARD_H
+int guard() { return 8; }
+#endif
+EOF
+
+# "ファイル名"は読んでいるファイルのディレクトリから先に探す
+assert_driver 1 "$DCC -o tmp -Itmp-inc2 tmp-inc/main.c"
+# <ファイル名>は-Iのディレクトリだけから、指定された順に探す
+assert_driver 2 "$DCC -o tmp -Itmp-inc2 -Itmp-inc3 tmp-inc/angle.c"
+assert_driver 3 "$DCC -o tmp -Itmp-inc3 -Itmp-inc2 tmp-inc/angle.c"
+assert_driver_error E0024 "$DCC -o tmp tmp-inc/angle.c"
+# ヘッダの中の#includeは、そのヘッダのディレクトリから探す
+assert 5 '#include "tmp-inc/sub/b.h"
+int main() { return C; }'
+assert 2 '#include <a.h>
+int main() { return A; }' -Itmp-inc2
+# #pragma onceとインクルードガード
+assert 7 '#include "tmp-inc/once.h"
+#include "tmp-inc/once.h"
+int main() { return once(); }'
+assert 8 '#include "tmp-inc/guard.h"
+#include "tmp-inc/guard.h"
+int main() { return guard(); }'
+
+# #if, #elifとdefined
+assert 1 '#if defined(A)
+int main() { return 1; }
+#elif defined B
+int main() { return 2; }
+#else
+int main() { return 3; }
+#endif' -DA -DB
+assert 2 '#if defined(A)
+int main() { return 1; }
+#elif defined B
+int main() { return 2; }
+#else
+int main() { return 3; }
+#endif' -DB
+assert 3 '#if defined(A)
+int main() { return 1; }
+#elif defined B
+int main() { return 2; }
+#else
+int main() { return 3; }
+#endif'
+assert 4 '#ifdef A
+#if 1
+int main() { return 1; }
+#endif
+#elif !defined(A) && 2 * 3 == 6
+int main() { return 4; }
+#endif'
+assert 5 '#define X 2
+#if X == 1
+int main() { return 1; }
+#elif X + 3 == 5
+int main() { return 5; }
+#endif'
+assert 6 '#ifndef A
+int main() { return 6; }
+#endif'
+
+# -Dと-U
+assert 1 'int main() { return N; }' -DN
+assert 9 'int main() { return N; }' -DN=9
+assert 9 'int main() { return N; }' -D N=9
+assert 4 'int main() { return F(3); }' '-DF(x)=x+1'
+assert 0 '#ifdef N
+int main() { return 1; }
+#else
+int main() { return 0; }
+#endif' -DN -UN
+assert 2 'int main() { return N; }' -UN -DN=2
+
+# #error
+assert_error E0027 '#error stop here'
+assert_error E0027 '#if 1
+#error stop here
+#endif'
+assert 0 '#if 0
+#error not reached
+#endif
+int main() { return 0; }'
+assert_error E0026 '#if 1
+int main() { return 0; }'
+
+# 行末の'\'は、前処理指令の外でも次の行とつなげる
+assert 5 'int main() { re\
+turn 5; }'
+assert 6 'int main() {
+// comment \
+return 1;
+return 6; }'
+assert 3 'int main() { return sizeof("a\
+b"); }'
+assert 1 'int main() { return 3 =\
+= 3; }'
+assert 5 '#define S(x) #x
+int main() { return sizeof(S("a\
+b")); }'
+
+# マクロ
+assert 9 '#define SQ(x) ((x) * (x))
+int main() { return SQ(1 + 2); }'
+assert 6 '#define CAT(a, b) a ## b
+int main() { int foo; CAT(f, oo) = 6; return foo; }'
+assert 12 '#define NUM(a, b) a ## b
+int main() { return NUM(1, 2); }'
+assert 4 '#define str(x) #x
+int main() { return sizeof(str(abc)); }'
+assert 34 '#define str(x) #x
+int main() { return str("a")[0]; }'
+assert 7 '#define SUM(...) sum(__VA_ARGS__)
+int sum(int a, int b, int c) { return a + b + c; }
+int main() { return SUM(1, 2, 4); }'
+assert 3 '#define FIRST(a, ...) a
+int main() { return FIRST(3, 4, 5); }'
+assert 5 '#define F(x) x + G
+#define G F
+int main() { int F; F = 3; return F(2); }'
+
+# C11 6.10.3.5の例
+assert_tokens 'f(2 * (y+1)) + f(2 * (f(2 * (z[0])))) % f(2 * (0)) + t(1);
+f(2 * (2+(3,4)-0,1)) | f(2 * (~ 5)) & f(2 * (0,1))^m(0,1);
+int i[] = { 1, 23, 4, 5, };
+char c[2][6] = { "hello", "" };' '#define x 3
+#define f(a) f(x * (a))
+#undef x
+#define x 2
+#define g f
+#define z z[0]
+#define h g(~
+#define m(a) a(w)
+#define w 0,1
+#define t(a) a
+#define p() int
+#define q(x) x
+#define r(x,y) x ## y
+#define str(x) # x
+f(y+1) + f(f(z)) % t(t(g)(0) + t)(1);
+g(x+(3,4)-w) | h 5) & m
+(f)^m(m);
+p() i[q()] = { q(1), r(2,3), r(4,), r(,5), r(,) };
+char c[2][6] = { str(hello), str() };'
+assert_tokens 'printf("x" "1" "= %d, x" "2" "= %s", x1, x2);
+fputs("strncmp(\"abc\\0d\", \"abc\", '"'"'\\4'"'"') == 0" ": ;", s);
+"vers2.h"
+"hello";
+"hello" ", world"' '#define str(s) # s
+#define xstr(s) str(s)
+#define debug(s, t) printf("x" # s "= %d, x" # t "= %s", \
+ x ## s, x ## t)
+#define INCFILE(n) vers ## n
+#define glue(a, b) a ## b
+#define xglue(a, b) glue(a, b)
+#define HIGHLOW "hello"
+#define LOW LOW ", world"
+debug(1, 2);
+fputs(str(strncmp("abc\0d", "abc", '"'"'\4'"'"') // this goes away
+ == 0) str(: ;), s);
+xstr(INCFILE(2).h)
+glue(HIGH, LOW);
+xglue(HIGH, LOW)'
+assert_tokens 'fprintf(stderr, "Flag");
+fprintf(stderr, "X = %d\n", x);
+puts("The first, second, and third items.");
+((x>y)?puts("x>y"): printf("x is %d but y is %d", x, y));' '#define debug(...) fprintf(stderr, __VA_ARGS__)
+#define showlist(...) puts(#__VA_ARGS__)
+#define report(test, ...) ((test)?puts(#test): printf(__VA_ARGS__))
+debug("Flag");
+debug("X = %d\n", x);
+showlist(The first, second, and third items.);
+report(x>y, "x is %d but y is %d", x, y);'
+
+# 自分自身を参照するマクロは、その展開の中では展開しない
+assert_tokens '(4 + foo)' '#define foo (4 + foo)
+foo'
+assert_tokens '(4 + (2 * x)) (2 * (4 + y))' '#define x (4 + y)
+#define y (2 * x)
+x y'
+assert_tokens 'f(f(1))' '#define f(a) f(a)
+f(f(1))'
+assert 3 '#define foo foo
+int main() { int foo; foo = 3; return foo; }'
+
+# #ifの&&, ||, ?:は結果に使われない側を評価しない
+assert 2 '#if 0 && (1/0)
+int main() { return 1; }
+#else
+int main() { return 2; }
+#endif'
+assert 1 '#if 1 || 1/0
+int main() { return 1; }
+#endif'
+assert 3 '#if 1 ? 3 : 1%0
+int main() { return 3; }
+#endif'
+assert 4 '#if defined(X) && 10/X > 1
+int main() { return 1; }
+#else
+int main() { return 4; }
+#endif'
+assert_error E0028 '#if 1 && 1/0
+#endif'
+
+# #ifの式は、片方が符号なしなら符号なしとして比較・計算する
+assert 2 '#if -1 < 0u
+int main() { return 1; }
+#else
+int main() { return 2; }
+#endif'
+assert 3 '#if -1 < 0 && 0xffffffffffffffff == -1 && -1u / 2 > 0 && -1u >> 63 == 1
+int main() { return 3; }
+#endif'
+assert 4 '#if (1 ? -1 : 0u) > 0
+int main() { return 4; }
+#endif'
+
+# 大域変数
+assert 6 'int g; int main() { g = 6; return g; }'
+assert 10 'int g = 3; char c = 7; int main() { return g + c; }'
+assert 5 'int a[3]; int *p = 0; int main() { a[2] = 5; return a[2] + (p == 0) - 1; }'
+assert 3 'int g; int g = 3; int g; int main() { return g; }'
+# 初期値は変数の型に変換する
+assert 44 'char c = 300; int main() { return c; }'
+assert 5 'int x = 65536 * 65536 + 5; int main() { return x; }'
+assert 1 'char c = 255; int main() { return c == -1; }'
+# 初期値が正しくなくても、変数は宣言されたものとして扱う
+assert_codes 'E0031' 'int h; int *hp = &h; int main() { return *hp; }'
+assert_codes 'E0008' 'int *p = 5; int main() { return *p; }'
+assert_error E0030 'int g = 1; int g = 2; int main() { return g; }'
+
+# 配列
+assert 5 'int main() { int a[3]; a[0] = 1; a[2] = 4; return a[0] + a[2]; }'
+assert 4 'int main() { int a[3]; a[1] = 4; return 1[a]; }'
+assert 29 'int main() { int a[5]; char c[5]; return sizeof(a) + sizeof(c) + sizeof(a[0]); }'
+assert 2 'int main() { char c[3]; c[0] = 1; c[1] = 2; c[2] = 255; return c[0] + c[1] + c[2]; }'
+assert 9 'int main() { int *a[2]; int x; a[1] = &x; x = 9; return *a[1]; }'
+# 配列は先頭の要素へのポインタに変換される
+assert 7 'int main() { int a[3]; int *p; p = a; *(p + 1) = 7; return a[1]; }'
+assert 12 'int sum(int *p, int n) { int s; int i; s = 0; for (i = 0; i < n; i = i + 1) s = s + p[i]; return s; } int main() { int a[4]; int i; for (i = 0; i < 4; i = i + 1) a[i] = i * 2; return sum(a, 4); }'
+assert_error E0009 'int main() { int a[2]; int b[2]; a = b; return 0; }'
+assert_error E0010 'int main() { int x; return x[0]; }'
+
+# 構造体
+assert 2 'struct P { char c; int x; }; int main() { struct P p; p.x = 2; return p.x; }'
+assert 5 'struct P { int x; int y; }; int main() { struct P p; struct P *q; q = &p; q->y = 5; return p.y; }'
+assert 8 'struct L { int v; struct L *next; }; int main() { struct L a; struct L b; a.next = &b; b.v = 8; return a.next->v; }'
+assert 18 'struct P { int x; }; int main() { struct P p[3]; p[2].x = 6; return p[2].x + sizeof(p); }'
+# メンバはそれぞれの型の境界にそろえて並べ、全体の大きさは最も大きい境界の倍数にする
+assert 8 'struct P { char c; int x; }; int main() { return sizeof(struct P); }'
+assert 24 'struct Q { char a; char b; int *p; char c; }; int main() { return sizeof(struct Q); }'
+assert 15 'struct I { char c; }; struct O { char a; struct I i; int x; }; int main() { struct O o; o.i.c = 3; o.x = 4; return o.i.c + o.x + sizeof(struct O); }'
+# 構造体の代入は全体をコピーする
+assert 63 'struct S { int a[64]; char c; }; int main() { struct S s; struct S t; int i; for (i = 0; i < 64; i = i + 1) s.a[i] = i; t = s; return t.a[63]; }'
+assert 6 'struct T { char x; char y; char z; }; int main() { struct T p; struct T q; struct T r; p.x = 1; p.y = 2; p.z = 3; r = q = p; return r.x + r.y + r.z; }'
+assert_error E0032 'struct P { int x; }; int main() { struct P p; return p.y; }'
+assert_error E0032 'int main() { int x; return x.y; }'
+assert_error E0033 'struct P; int main() { struct P p; return 0; }'
+
+# 共用体
+assert 8 'union U { char c; int x; int *p; }; int main() { return sizeof(union U); }'
+assert 8 'union U { char c[5]; int x; }; int main() { return sizeof(union U); }'
+# 全てのメンバは先頭から始まる
+assert 2 'union U { char c; int x; }; int main() { union U u; u.x = 258; return u.c; }'
+assert 8 'struct S { char c; union { int x; char d; } u; }; int main() { struct S s; return sizeof(s); }'
+assert_error E0030 'union U { int x; }; int main() { struct U u; return 0; }'
+
+# 列挙型
+assert 12 'enum E { A, B, C = 5, D }; int main() { return A + B + C + D; }'
+assert 5 'enum E { A, B }; int main() { enum E e; e = B; return e + sizeof(enum E); }'
+assert 3 'int main() { enum { X = 3 } e; return X; }'
+assert 1 'enum E { A, B, }; int main() { return B; }'
+assert 1 'enum { A = -2147483647 - 1, B }; int main() { return B == -2147483647; }'
+# 列挙定数は定数式に使える
+assert 16 'enum E { N = 4 }; int main() { int a[N]; return sizeof(a); }'
+assert 20 'enum E { N = 4 }; int a[N + 1]; int main() { return sizeof(a); }'
+assert_error E0030 'enum { A, A }; int main() { return 0; }'
+assert_codes 'E0031' 'int main() { int x; enum { A = x, B }; return B; }'
+# 列挙定数の値はintで表せなければならない
+assert_codes 'E0038' 'enum { A = 2147483647 * 2147483647 * 2 + 2147483647 * 4 + 1, B }; int main() { return B; }'
+assert_codes 'E0038' 'enum { A = 2147483647, B }; int main() { return B; }'
+assert_codes 'E0038' 'enum { A = -2147483647 - 2 }; int main() { return A; }'
+
+# typedef
+assert 3 'typedef int myint; int main() { myint x; x = 3; return x; }'
+assert 4 'typedef int *ip; int main() { int x; ip p; p = &x; *p = 4; return x; }'
+assert 13 'typedef struct P { int x; int y; } P; int main() { P p; p.y = 5; return p.y + sizeof(P); }'
+assert 12 'typedef int A[3]; int main() { A a; return sizeof(a); }'
+assert 5 'typedef int T; int f(T a, T *b) { return a + *b; } int main() { int x; x = 2; return f(3, &x); }'
+# 不完全な構造体にも名前をつけられる
+assert 6 'typedef struct Node Node; struct Node { int v; Node *next; }; int main() { Node a; Node b; a.next = &b; b.v = 6; return a.next->v; }'
+# 型名は内側のスコープの変数で隠せる
+assert 7 'typedef int T; int main() { int T; T = 7; return T; }'
+# ブロックの中のtypedefは、そのブロックの中だけで使える
+assert 10 'int main() { typedef int T; T x; x = 8; { typedef char T; T y; y = 1; return sizeof(T) + x + y; } }'
+assert_error E0006 'int main() { { typedef int T; } T x; return 0; }'
+assert_error E0030 'typedef int T; typedef char T; int main() { return 0; }'
+assert_error E0030 'typedef int T; int T; int main() { return 0; }'
+
+# キャストとsizeof(型名)
+assert 45 'typedef char C; int main() { return sizeof(C) + (C)300; }'
+assert 1 'int main() { return (int *)0 == 0; }'
+assert 17 'int main() { return sizeof(int *) + sizeof(char) + sizeof(struct Q *); }'
+assert 1 'int main() { return (char)255 == -1; }'
+assert_error E0035 'struct S { int x; }; int main() { struct S s; return (int)s; }'
+
+# ブロックスコープ
+assert 1 'int main() { int x; x = 1; { int x; x = 2; } return x; }'
+assert 2 'int main() { int x; x = 1; { int y; y = x + 1; x = y; } return x; }'
+assert 3 'int g; int main() { g = 3; { int g; g = 4; } return g; }'
+assert 5 'int main() { int x; x = 5; if (1) { int x; x = 9; } return x; }'
+assert 6 'int main() { int i; int s; s = 0; for (i = 0; i < 3; i = i + 1) { int t; t = i * 2; s = s + t; } return s; }'
+assert 7 'int f(int a) { { int a; a = 2; } return a; } int main() { return f(7); }'
+assert 4 'int main() { enum { A = 1 }; { int A; A = 4; return A; } }'
+assert_error E0006 'int main() { { int y; y = 1; } return y; }'
+# 同じスコープの中では同じ名前を宣言し直せない。引数と関数本体の一番外側のブロックは同じスコープ
+assert_error E0030 'int main() { int x; int x; return 0; }'
+assert_error E0030 'int f(int a) { int a; return a; } int main() { return 0; }'
+# 入れ子になっていないブロックの変数は、同じ場所を使い回す
+assert 1 'int main() { int *p; int *q; { int a; p = &a; } { int b; q = &b; } return p == q; }'
+assert 0 'int main() { int *p; int *q; { int a; p = &a; { int b; q = &b; } } return p == q; }'
+
+# 関数の宣言と型の検査
+assert 5 'int add(int, int); int main() { return add(2, 3); } int add(int a, int b) { return a + b; }'
+assert 3 'int first(int *, char); int main() { int x; x = 3; return first(&x, 1); } int first(int *p, char c) { return *p; }'
+assert 3 'char c() { return 259; } int main() { return c(); }'
+assert 1 'int *p() { return 0; } int main() { return p() == 0; }'
+assert 7 'int g; int *p() { return &g; } int main() { *p() = 7; return g; }'
+assert_error E0008 'int *f() { return 5; } int main() { return 0; }'
+assert_error E0008 'int f() { int *p; return p; } int main() { return 0; }'
+assert_error E0008 'char *f() { int x; return &x; } int main() { return 0; }'
+assert_error E0037 'int f() { return; } int main() { return 0; }'
+assert_error E0004 'int add(int, int b) { return b; } int main() { return 0; }'
+assert_error E0030 'int add(int a, int a); int main() { return 0; }'
+assert_error E0036 'int add(int a, int b); int main() { return add(2); } int add(int a, int b) { return a + b; }'
+assert_error E0008 'int f(int *p); int main() { return f(1); }'
+assert_error E0030 'char add(); int main() { return 0; } int add(int a, int b) { return a + b; }'
+assert_error E0030 'int f(int a); int f(char a); int main() { return 0; }'
+# "()"で宣言された関数の引数は調べない。本体のある定義の"()"は引数を取らない
+assert 5 'int add(); int main() { return add(2, 3); } int add(int a, int b) { return a + b; }'
+assert 0 'int print(); int alloc4(); int main() { int *p; alloc4(&p, 1, 2, 4, 8); print(p[3]); return 0; }'
+assert_error E0036 'int add(); int add(int a, int b); int main() { return add(2); }'
+assert_error E0036 'int f() { return 1; } int main() { return f(2); }'
+
+# ドライバの各段階
+cat > tmp-main.c <<EOF
+int sub(int a, int b);
+int main() { return sub(7, 3); }
+EOF
+cat > tmp-sub.c <<EOF
+int sub(int a, int b) { return a - b; }
+EOF
+rm -f tmp-main.s tmp-sub.s tmp-main.o tmp-sub.o
+
+# -Sは入力ごとにアセンブリを出力する
+assert_driver 4 "$DCC -S tmp-main.c tmp-sub.c && cc -o tmp tmp-main.s tmp-sub.s"
+assert_driver 4 "$DCC -S -o tmp-x.s tmp-sub.c && cc -o tmp tmp-main.s tmp-x.s"
+# -cは入力ごとにオブジェクトファイルを出力する
+assert_driver 4 "$DCC -c tmp-main.c tmp-sub.c && cc -o tmp tmp-main.o tmp-sub.o"
+assert_driver 4 "$DCC -c -o tmp-x.o tmp-sub.c && cc -o tmp tmp-main.o tmp-x.o"
+# 指定なしの場合は全ての入力をリンクする
+assert_driver 4 "$DCC -o tmp tmp-main.c tmp-sub.c"
+# .sと.oはそのままアセンブラ・リンカに渡す
+assert_driver 4 "$DCC -o tmp tmp-main.c tmp-sub.s"
+assert_driver 4 "$DCC -o tmp tmp-main.c tmp-sub.o"
+assert_driver 4 "$DCC -o tmp tmp-main.s tmp-x.o"
+# -oで標準出力に書き出す
+assert_driver 4 "$DCC -S -o - tmp-sub.c > tmp-y.s && cc -o tmp tmp-main.o tmp-y.s"
+
+# -Sと-cでは、複数の入力に1つの出力先を指定できない
+assert_driver_error E0014 "$DCC -S -o tmp-x.s tmp-main.c tmp-sub.c"
+assert_driver_error E0014 "$DCC -c -o tmp-x.o tmp-main.c tmp-sub.c"
 
-echo -e "${GREEN}test finished successfully.${NC}"
\ No newline at end of file
+echo -e "${GREEN}test finished successfully.${NC}"